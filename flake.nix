@@ -111,6 +111,11 @@
             ];
 
             postInstall = ''
+              install -Dm644 contrib/systemd/hypr-recorder.service \
+                $out/lib/systemd/user/hypr-recorder.service
+              substituteInPlace $out/lib/systemd/user/hypr-recorder.service \
+                --replace-fail /usr/bin/hypr-recorder $out/bin/hypr-recorder
+
               wrapProgram $out/bin/hypr-recorder \
                 --set WHISPER_COMMAND ${whisper}/bin/whisper-cli \
                 --prefix PATH : ${
@@ -140,6 +145,7 @@
             cargo-watch
             pkg-config
             pipewire
+            dbus
             gst_all_1.gstreamer
             gst_all_1.gst-plugins-base
             gst_all_1.gst-plugins-good
@@ -18,6 +18,7 @@ use cpal::{
 };
 use hound::{WavSpec, WavWriter};
 use tokio::{task::JoinHandle, time::sleep};
+use tracing::{debug, error, info};
 
 use crate::{Error, Result};
 
@@ -26,6 +27,7 @@ pub struct AudioRecorder {
     config: StreamConfig,
     samples: Arc<Mutex<Vec<f32>>>,
     is_recording: Arc<AtomicBool>,
+    is_paused: Arc<AtomicBool>,
     stream: Option<Stream>,
     timeout_task: Option<JoinHandle<()>>,
 }
@@ -39,22 +41,27 @@ impl AudioRecorder {
             )
         })?;
 
-        println!("Using input device: {}", device.name()?);
+        info!("Using input device: {}", device.name()?);
 
         let config = device.default_input_config()?.into();
 
-        println!("Input config: {:?}", config);
+        debug!("Input config: {:?}", config);
 
         Ok(Self {
             device,
             config,
             samples: Arc::new(Mutex::new(Vec::new())),
             is_recording: Arc::new(AtomicBool::new(false)),
+            is_paused: Arc::new(AtomicBool::new(false)),
             stream: None,
             timeout_task: None,
         })
     }
 
+    pub fn device_name(&self) -> String {
+        self.device.name().unwrap_or_else(|_| "unknown".into())
+    }
+
     pub async fn start(&mut self) -> Result<()> {
         if self.is_recording.load(Ordering::Relaxed) {
             return Ok(());
@@ -66,20 +73,24 @@ impl AudioRecorder {
 
         let samples = Arc::clone(&self.samples);
         let is_recording = Arc::clone(&self.is_recording);
+        let is_paused = Arc::clone(&self.is_paused);
 
         samples.lock().unwrap().clear();
+        is_paused.store(false, Ordering::Relaxed);
         is_recording.store(true, Ordering::Relaxed);
 
         let stream = self.device.build_input_stream(
             &self.config,
             move |data: &[f32], _: &cpal::InputCallbackInfo| {
-                if is_recording.load(Ordering::Relaxed) {
+                if is_recording.load(Ordering::Relaxed)
+                    && !is_paused.load(Ordering::Relaxed)
+                {
                     let mut samples_guard = samples.lock().unwrap();
                     samples_guard.extend_from_slice(data);
                 }
             },
             |err| {
-                eprintln!("Audio stream error: {}", err);
+                error!("Audio stream error: {}", err);
             },
             None,
         )?;
@@ -91,13 +102,29 @@ impl AudioRecorder {
         self.timeout_task = Some(tokio::spawn(async move {
             sleep(Duration::from_secs(60)).await;
             if is_recording_timeout.swap(false, Ordering::Relaxed) {
-                println!("Recording stopped due to 1-minute timeout");
+                info!("Recording stopped due to 1-minute timeout");
             }
         }));
 
         Ok(())
     }
 
+    /// Drops incoming samples until [`AudioRecorder::resume`] is called.
+    ///
+    /// The standalone timeout is cancelled as well, since it cannot account
+    /// for paused time; the owning recorder enforces the limit instead.
+    pub fn pause(&mut self) {
+        if let Some(handle) = self.timeout_task.take() {
+            handle.abort();
+        }
+
+        self.is_paused.store(true, Ordering::Relaxed);
+    }
+
+    pub fn resume(&mut self) {
+        self.is_paused.store(false, Ordering::Relaxed);
+    }
+
     pub fn stop(&mut self) -> Result<Vec<f32>> {
         if !self.is_recording.swap(false, Ordering::Relaxed) {
             return Ok(Vec::new());
@@ -112,7 +139,7 @@ impl AudioRecorder {
         }
 
         let samples = self.samples.lock().unwrap().clone();
-        println!("Recorded {} samples", samples.len());
+        debug!("Recorded {} samples", samples.len());
 
         Ok(samples)
     }
@@ -137,7 +164,7 @@ impl AudioRecorder {
         }
 
         writer.finalize()?;
-        println!("Audio saved to: {}", path.as_ref().display());
+        info!("Audio saved to: {}", path.as_ref().display());
 
         Ok(())
     }
@@ -0,0 +1,77 @@
+#[cfg(feature = "portal")]
+mod portal;
+mod synthetic;
+
+use async_trait::async_trait;
+#[cfg(feature = "cli")]
+use clap::ValueEnum;
+
+#[cfg(feature = "portal")]
+pub use self::portal::PortalSource;
+pub use self::synthetic::SyntheticSource;
+use crate::{
+    metadata::{AudioSource, VideoSource},
+    Result,
+};
+
+/// Where recordings come from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(ValueEnum))]
+pub enum Source {
+    /// Screens and windows picked in the desktop portal
+    #[cfg(feature = "portal")]
+    Portal,
+    /// GStreamer test patterns and tones, for runs without a desktop
+    Synthetic,
+    /// Test patterns without audio, so the microphone fallback is used
+    SyntheticVideo,
+}
+
+impl Default for Source {
+    /// The portal, or test patterns when built without it.
+    fn default() -> Self {
+        #[cfg(feature = "portal")]
+        return Self::Portal;
+        #[cfg(not(feature = "portal"))]
+        return Self::Synthetic;
+    }
+}
+
+/// The streams one recording reads from, as `gst-launch` fragments that
+/// start with a source element.
+pub struct Streams {
+    pub video: String,
+    /// The audio fragment and what it records, if the source has audio.
+    pub audio: Option<(String, AudioSource)>,
+    pub video_sources: Vec<VideoSource>,
+}
+
+/// Provides the video, and possibly audio, of each recording.
+#[async_trait(?Send)]
+pub trait CaptureSource {
+    fn name(&self) -> &'static str;
+
+    /// GStreamer elements the streams start with, beyond the pipeline's own
+    /// [`ELEMENTS`](crate::pipeline::ELEMENTS), with where to get them.
+    fn elements(&self) -> &'static [(&'static str, &'static str)];
+
+    /// Sets up the streams for a new recording.
+    async fn open(&mut self) -> Result<Streams>;
+
+    /// Releases what [`CaptureSource::open`] set up, once the pipeline
+    /// reading the streams has stopped.
+    async fn close(&mut self);
+}
+
+/// Opens a built-in source. `persist_portal` only applies to the portal.
+pub fn open(source: Source, persist_portal: bool) -> Box<dyn CaptureSource> {
+    #[cfg(not(feature = "portal"))]
+    let _ = persist_portal;
+
+    match source {
+        #[cfg(feature = "portal")]
+        Source::Portal => Box::new(PortalSource::new(persist_portal)),
+        Source::Synthetic => Box::new(SyntheticSource::new(true)),
+        Source::SyntheticVideo => Box::new(SyntheticSource::new(false)),
+    }
+}
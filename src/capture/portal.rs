@@ -0,0 +1,299 @@
+use std::os::fd::{AsRawFd, FromRawFd, IntoRawFd, OwnedFd};
+
+use ashpd::{
+    desktop::{
+        screencast::{CursorMode, Screencast, SourceType, Stream},
+        PersistMode,
+        Session,
+    },
+    Error as AshpdError,
+    PortalError as AshpdPortalError,
+};
+use async_trait::async_trait;
+use tokio::fs;
+use tracing::{info, instrument, warn};
+
+use super::{CaptureSource, Streams};
+use crate::{
+    metadata::{AudioSource, VideoSource},
+    utils,
+    Error,
+    Result,
+};
+
+const RESTORE_TOKEN_NAME: &str = "portal-restore-token";
+
+/// Screens and windows the user picks in the XDG desktop portal, streamed
+/// over PipeWire.
+pub struct PortalSource {
+    screencast: Option<Screencast<'static>>,
+    persist: bool,
+    session: Option<Session<'static, Screencast<'static>>>,
+    remote_fd: Option<OwnedFd>,
+    /// The copies of the remote handed to `pipewiresrc`, which makes its own
+    /// copy of them in turn.
+    stream_fds: Vec<OwnedFd>,
+}
+
+impl PortalSource {
+    /// With `persist`, the portal is asked for a restore token so later
+    /// recordings reuse the chosen sources without the picker.
+    pub fn new(persist: bool) -> Self {
+        Self {
+            screencast: None,
+            persist,
+            session: None,
+            remote_fd: None,
+            stream_fds: Vec::new(),
+        }
+    }
+}
+
+#[async_trait(?Send)]
+impl CaptureSource for PortalSource {
+    fn name(&self) -> &'static str {
+        "portal"
+    }
+
+    fn elements(&self) -> &'static [(&'static str, &'static str)] {
+        &[(
+            "pipewiresrc",
+            "the PipeWire GStreamer plugin (gst-plugin-pipewire)",
+        )]
+    }
+
+    #[instrument(name = "portal", skip_all, fields(persist = self.persist))]
+    async fn open(&mut self) -> Result<Streams> {
+        let result = self.start().await;
+        if result.is_err() {
+            self.close().await;
+        }
+        result
+    }
+
+    async fn close(&mut self) {
+        if let Some(session) = self.session.take() {
+            let _ = session.close().await;
+        }
+        self.stream_fds.clear();
+        self.remote_fd = None;
+    }
+}
+
+impl PortalSource {
+    /// Negotiates the streams, leaving the session in `self` as soon as it
+    /// exists so that [`CaptureSource::close`] can end it on failure.
+    async fn start(&mut self) -> Result<Streams> {
+        // The portal proxy outlives individual recordings, which saves a
+        // round trip per recording when running as a daemon.
+        let screencast = match self.screencast.take() {
+            Some(screencast) => screencast,
+            None => Screencast::new().await?,
+        };
+        let screencast = self.screencast.insert(screencast);
+        let session = self.session.insert(screencast.create_session().await?);
+
+        let available_types = screencast.available_source_types().await?;
+
+        let mut requested_types = SourceType::Monitor | SourceType::Window;
+        let audio_supported = available_types.contains(SourceType::Virtual);
+        if audio_supported {
+            requested_types |= SourceType::Virtual;
+        }
+
+        requested_types &= available_types;
+
+        if !requested_types.contains(SourceType::Monitor)
+            && !requested_types.contains(SourceType::Window)
+        {
+            return Err(Error::ScreenCapture(
+                "Portal did not advertise any monitor or window sources to capture"
+                    .into(),
+            ));
+        }
+
+        let (restore_token, persist_mode) = if self.persist {
+            (read_restore_token().await, PersistMode::ExplicitlyRevoked)
+        } else {
+            (None, PersistMode::DoNot)
+        };
+
+        screencast
+            .select_sources(
+                session,
+                CursorMode::Embedded,
+                requested_types,
+                true,
+                restore_token.as_deref(),
+                persist_mode,
+            )
+            .await?
+            .response()?;
+
+        let start_request = match screencast.start(session, None).await {
+            Ok(request) => request,
+            Err(err) => {
+                if let AshpdError::Portal(AshpdPortalError::Failed(message)) =
+                    &err
+                {
+                    if message.contains("No streams available") {
+                        return Err(Error::ScreenCapture(
+                            "Portal did not return any streams. Please ensure you selected a source and that audio capture is enabled in your compositor portal configuration."
+                                .into(),
+                        ));
+                    }
+                }
+
+                return Err(err.into());
+            }
+        };
+
+        let streams = match start_request.response() {
+            Ok(streams) => streams,
+            Err(err) => {
+                if let AshpdError::Portal(AshpdPortalError::Failed(message)) =
+                    &err
+                {
+                    if message.contains("No streams available") {
+                        return Err(Error::ScreenCapture(
+                            "Portal did not return any streams. Please ensure you selected a source and that audio capture is enabled in your compositor portal configuration."
+                                .into(),
+                        ));
+                    }
+                }
+
+                return Err(err.into());
+            }
+        };
+
+        if self.persist {
+            if let Some(token) = streams.restore_token() {
+                if let Err(e) = write_restore_token(token).await {
+                    warn!("Failed to persist portal restore token: {}", e);
+                }
+            }
+        }
+
+        info!("Portal returned {} stream(s)", streams.streams().len());
+        for stream in streams.streams() {
+            info!(
+                "- node {} type {:?} id {:?}",
+                stream.pipe_wire_node_id(),
+                stream.source_type(),
+                stream.id()
+            );
+        }
+
+        let remote_fd = screencast.open_pipe_wire_remote(session).await?;
+        let remote = unsafe { OwnedFd::from_raw_fd(remote_fd.into_raw_fd()) };
+        let remote = self.remote_fd.insert(remote);
+
+        let (video_stream, audio_stream) = split_streams(streams.streams())?;
+
+        let video =
+            pipewire_source(remote, &video_stream, &mut self.stream_fds)?;
+        let audio = match &audio_stream {
+            Some(stream) => Some((
+                pipewire_source(remote, stream, &mut self.stream_fds)?,
+                AudioSource::Portal {
+                    node_id: stream.pipe_wire_node_id(),
+                },
+            )),
+            None => None,
+        };
+
+        Ok(Streams {
+            video,
+            audio,
+            video_sources: vec![video_source(&video_stream)],
+        })
+    }
+}
+
+fn split_streams(streams: &[Stream]) -> Result<(Stream, Option<Stream>)> {
+    let mut video: Option<Stream> = None;
+    let mut audio: Option<Stream> = None;
+
+    for stream in streams.iter().cloned() {
+        match stream.source_type() {
+            Some(SourceType::Monitor) | Some(SourceType::Window) => {
+                video = Some(stream)
+            }
+            Some(SourceType::Virtual) => audio = Some(stream),
+            None => {
+                if stream.id().map(|id| id.contains("audio")).unwrap_or(false) {
+                    audio = Some(stream);
+                } else {
+                    video = Some(stream);
+                }
+            }
+        }
+    }
+
+    match (video, audio) {
+        (Some(v), maybe_audio) => {
+            if maybe_audio.is_none() {
+                warn!(
+                    "Portal did not supply an audio stream. Continuing with video-only recording."
+                );
+                warn!(
+                    "If you expected audio, ensure xdg-desktop-portal-hyprland and PipeWire are configured for audio capture."
+                );
+            }
+            Ok((v, maybe_audio))
+        }
+        _ => Err(Error::ScreenCapture(
+            "Portal did not provide a video stream".into(),
+        )),
+    }
+}
+
+/// `pipewiresrc` reading `stream` through its own copy of the remote, which
+/// is kept in `fds` until the source closes.
+fn pipewire_source(
+    remote_fd: &OwnedFd,
+    stream: &Stream,
+    fds: &mut Vec<OwnedFd>,
+) -> Result<String> {
+    let fd = remote_fd.try_clone()?;
+    let description = format!(
+        "pipewiresrc fd={} path={} do-timestamp=true",
+        fd.as_raw_fd(),
+        stream.pipe_wire_node_id()
+    );
+    fds.push(fd);
+
+    Ok(description)
+}
+
+fn video_source(stream: &Stream) -> VideoSource {
+    let kind = match stream.source_type() {
+        Some(SourceType::Monitor) => "monitor",
+        Some(SourceType::Window) => "window",
+        _ => "unknown",
+    };
+
+    VideoSource {
+        kind: kind.into(),
+        node_id: Some(stream.pipe_wire_node_id()),
+        size: stream.size(),
+        position: stream.position(),
+    }
+}
+
+async fn read_restore_token() -> Option<String> {
+    let path = utils::state_dir().join(RESTORE_TOKEN_NAME);
+    let token = fs::read_to_string(path).await.ok()?;
+    let token = token.trim();
+
+    (!token.is_empty()).then(|| token.to_string())
+}
+
+/// Saves the token for the next session; the portal issues a new one every
+/// time a token is used.
+async fn write_restore_token(token: &str) -> Result<()> {
+    let dir = utils::state_dir();
+    fs::create_dir_all(&dir).await?;
+    fs::write(dir.join(RESTORE_TOKEN_NAME), token).await?;
+    Ok(())
+}
@@ -0,0 +1,68 @@
+use async_trait::async_trait;
+
+use super::{CaptureSource, Streams};
+use crate::{
+    metadata::{AudioSource, VideoSource},
+    Result,
+};
+
+const WIDTH: i32 = 1280;
+const HEIGHT: i32 = 720;
+
+const VIDEO: (&str, &str) = ("videotestsrc", "gst-plugins-base");
+const AUDIO: (&str, &str) = ("audiotestsrc", "gst-plugins-base");
+
+/// Live test pattern and tone, so recordings can be made without a
+/// compositor, portal or PipeWire.
+pub struct SyntheticSource {
+    audio: bool,
+}
+
+impl SyntheticSource {
+    /// Without `audio`, recordings fall back to the microphone as they do
+    /// when the portal offers no audio stream.
+    pub fn new(audio: bool) -> Self {
+        Self { audio }
+    }
+}
+
+#[async_trait(?Send)]
+impl CaptureSource for SyntheticSource {
+    fn name(&self) -> &'static str {
+        "synthetic"
+    }
+
+    fn elements(&self) -> &'static [(&'static str, &'static str)] {
+        if self.audio {
+            &[VIDEO, AUDIO]
+        } else {
+            &[VIDEO]
+        }
+    }
+
+    async fn open(&mut self) -> Result<Streams> {
+        let video = format!(
+            "videotestsrc is-live=true pattern=smpte ! \
+             video/x-raw,width={WIDTH},height={HEIGHT},framerate=30/1"
+        );
+        let audio = self.audio.then(|| {
+            (
+                "audiotestsrc is-live=true wave=sine".to_string(),
+                AudioSource::Synthetic,
+            )
+        });
+
+        Ok(Streams {
+            video,
+            audio,
+            video_sources: vec![VideoSource {
+                kind: "synthetic".into(),
+                node_id: None,
+                size: Some((WIDTH, HEIGHT)),
+                position: None,
+            }],
+        })
+    }
+
+    async fn close(&mut self) {}
+}
@@ -0,0 +1,528 @@
+use std::{
+    fs::{self, File},
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+use crate::{markers::Marker, Error, Result};
+
+/// Boxes holding nothing but other boxes, on the way from `moov` to the
+/// chunk offset tables.
+const CONTAINERS: &[&[u8; 4]] = &[b"trak", b"mdia", b"minf", b"stbl"];
+
+/// Nero chapter lists hold at most this many chapters, with titles of at
+/// most this many bytes.
+const MAX_CHAPTERS: usize = 255;
+const MAX_TITLE: usize = 255;
+
+/// Chapter starts are in units of 100 ns.
+const TICKS_PER_SEC: f64 = 10_000_000.0;
+
+/// Embeds `markers` as chapters in the MP4 at `path`.
+///
+/// `mp4mux` writes no chapters of its own, so a Nero chapter list (`chpl`,
+/// read by FFmpeg-based players and VLC) is added to the movie's user data,
+/// replacing any earlier one. The movie box grows, so when it precedes the
+/// media data, as with `faststart`, the chunk offsets are moved along. The
+/// file is rewritten next to itself and then renamed over it.
+pub fn embed(path: &Path, markers: &[Marker]) -> Result<()> {
+    if markers.is_empty() {
+        return Ok(());
+    }
+
+    let mut file = File::open(path)?;
+    let length = file.metadata()?.len();
+    let moov = top_level(&mut file, length)?
+        .into_iter()
+        .find(|header| &header.kind == b"moov")
+        .ok_or_else(|| Error::Chapters("no movie box".into()))?;
+
+    let mut movie = vec![0; (moov.end - moov.start) as usize];
+    file.seek(SeekFrom::Start(moov.start))?;
+    file.read_exact(&mut movie)?;
+
+    let mut chaptered = with_chapters(&movie, &chpl(markers))?;
+    let growth = chaptered.len() as i64 - movie.len() as i64;
+    shift_chunk_offsets(&mut chaptered, |offset| {
+        if offset < moov.end {
+            return Ok(offset);
+        }
+        offset
+            .checked_add_signed(growth)
+            .ok_or_else(|| Error::Chapters("chunk offset out of range".into()))
+    })?;
+
+    let staging = path.with_extension("chapters.tmp");
+    let written = (|| -> io::Result<()> {
+        let mut out = File::create(&staging)?;
+        file.seek(SeekFrom::Start(0))?;
+        io::copy(&mut (&mut file).take(moov.start), &mut out)?;
+        out.write_all(&chaptered)?;
+        file.seek(SeekFrom::Start(moov.end))?;
+        io::copy(&mut file, &mut out)?;
+        out.sync_all()
+    })();
+
+    match written {
+        Ok(()) => fs::rename(&staging, path)?,
+        Err(e) => {
+            let _ = fs::remove_file(&staging);
+            return Err(e.into());
+        }
+    }
+
+    Ok(())
+}
+
+/// Where a box sits, in bytes from the start of whatever it was read from.
+#[derive(Debug, Clone, Copy)]
+struct Header {
+    kind: [u8; 4],
+    start: u64,
+    payload: u64,
+    end: u64,
+}
+
+/// The box starting at `start`, given the first 16 bytes from there (fewer
+/// at the end) and the end of its parent.
+fn header(bytes: &[u8], start: u64, limit: u64) -> Result<Header> {
+    let malformed = || Error::Chapters(format!("malformed box at {start}"));
+
+    let size = u32::from_be_bytes(
+        bytes.get(0..4).ok_or_else(malformed)?.try_into().unwrap(),
+    );
+    let kind: [u8; 4] =
+        bytes.get(4..8).ok_or_else(malformed)?.try_into().unwrap();
+
+    let (payload, end) = match size {
+        0 => (start + 8, limit),
+        1 => {
+            let size = u64::from_be_bytes(
+                bytes.get(8..16).ok_or_else(malformed)?.try_into().unwrap(),
+            );
+            (start + 16, start.checked_add(size).ok_or_else(malformed)?)
+        }
+        size => (start + 8, start + u64::from(size)),
+    };
+
+    if end < payload || end > limit {
+        return Err(malformed());
+    }
+
+    Ok(Header {
+        kind,
+        start,
+        payload,
+        end,
+    })
+}
+
+/// The boxes at the top level of `file`, read header by header so the
+/// media data is never loaded.
+fn top_level(file: &mut File, length: u64) -> Result<Vec<Header>> {
+    let mut headers = Vec::new();
+    let mut start = 0;
+
+    while start < length {
+        let mut bytes = [0; 16];
+        file.seek(SeekFrom::Start(start))?;
+        let read = file.read(&mut bytes)?;
+
+        let header = header(&bytes[..read], start, length)?;
+        start = header.end;
+        headers.push(header);
+    }
+
+    Ok(headers)
+}
+
+/// The boxes directly inside `data`, which holds nothing else.
+fn children(data: &[u8]) -> Result<Vec<Header>> {
+    let mut headers = Vec::new();
+    let mut start = 0;
+
+    while start < data.len() {
+        let bytes = &data[start..data.len().min(start + 16)];
+        let header = header(bytes, start as u64, data.len() as u64)?;
+        start = header.end as usize;
+        headers.push(header);
+    }
+
+    Ok(headers)
+}
+
+fn boxed(kind: &[u8; 4], payload: &[u8]) -> Result<Vec<u8>> {
+    let size = u32::try_from(payload.len() + 8)
+        .map_err(|_| Error::Chapters("box too large".into()))?;
+
+    let mut data = Vec::with_capacity(payload.len() + 8);
+    data.extend_from_slice(&size.to_be_bytes());
+    data.extend_from_slice(kind);
+    data.extend_from_slice(payload);
+    Ok(data)
+}
+
+/// A Nero chapter list with a chapter at each marker.
+fn chpl(markers: &[Marker]) -> Vec<u8> {
+    let markers = &markers[..markers.len().min(MAX_CHAPTERS)];
+
+    // Version 1, no flags, four reserved bytes.
+    let mut data = vec![1, 0, 0, 0, 0, 0, 0, 0];
+    data.push(markers.len() as u8);
+
+    for (index, marker) in markers.iter().enumerate() {
+        let start = (marker.offset.as_secs_f64() * TICKS_PER_SEC) as u64;
+        let title = marker
+            .label
+            .clone()
+            .unwrap_or_else(|| format!("Marker {}", index + 1));
+        let title = truncate(&title, MAX_TITLE);
+
+        data.extend_from_slice(&start.to_be_bytes());
+        data.push(title.len() as u8);
+        data.extend_from_slice(title.as_bytes());
+    }
+
+    data
+}
+
+/// At most `max` bytes of `s`, cut at a character boundary.
+fn truncate(s: &str, max: usize) -> &str {
+    let end = (0..=max.min(s.len()))
+        .rev()
+        .find(|&end| s.is_char_boundary(end))
+        .unwrap_or(0);
+    &s[..end]
+}
+
+/// `movie`, a whole `moov` box, with `chpl` in its user data in place of
+/// any chapter list it had.
+fn with_chapters(movie: &[u8], chpl: &[u8]) -> Result<Vec<u8>> {
+    let moov = header(movie, 0, movie.len() as u64)?;
+    let contents = &movie[moov.payload as usize..];
+
+    let chapters = boxed(b"chpl", chpl)?;
+    let mut payload = Vec::with_capacity(contents.len() + chapters.len() + 8);
+    let mut has_user_data = false;
+
+    for child in children(contents)? {
+        let data = &contents[child.start as usize..child.end as usize];
+        if &child.kind != b"udta" {
+            payload.extend_from_slice(data);
+            continue;
+        }
+
+        has_user_data = true;
+        let user_data = &data[(child.payload - child.start) as usize..];
+        let mut kept = Vec::with_capacity(user_data.len() + chapters.len());
+        for entry in children(user_data)? {
+            if &entry.kind != b"chpl" {
+                kept.extend_from_slice(
+                    &user_data[entry.start as usize..entry.end as usize],
+                );
+            }
+        }
+        kept.extend_from_slice(&chapters);
+        payload.extend(boxed(b"udta", &kept)?);
+    }
+
+    if !has_user_data {
+        payload.extend(boxed(b"udta", &chapters)?);
+    }
+
+    boxed(b"moov", &payload)
+}
+
+/// Replaces every chunk offset in `movie`, a whole `moov` box, with what
+/// `shift` makes of it.
+fn shift_chunk_offsets(
+    movie: &mut [u8],
+    mut shift: impl FnMut(u64) -> Result<u64>,
+) -> Result<()> {
+    let moov = header(movie, 0, movie.len() as u64)?;
+    visit(&mut movie[moov.payload as usize..], &mut shift)
+}
+
+fn visit(
+    data: &mut [u8],
+    shift: &mut impl FnMut(u64) -> Result<u64>,
+) -> Result<()> {
+    for child in children(data)? {
+        let payload = &mut data[child.payload as usize..child.end as usize];
+
+        match &child.kind {
+            kind if CONTAINERS.contains(&kind) => visit(payload, shift)?,
+            b"stco" => {
+                for entry in offset_table(payload, 4)? {
+                    let offset = u32::from_be_bytes(entry.try_into().unwrap());
+                    let shifted = u32::try_from(shift(offset.into())?)
+                        .map_err(|_| {
+                            Error::Chapters("chunk offset out of range".into())
+                        })?;
+                    entry.copy_from_slice(&shifted.to_be_bytes());
+                }
+            }
+            b"co64" => {
+                for entry in offset_table(payload, 8)? {
+                    let offset = u64::from_be_bytes(entry.try_into().unwrap());
+                    entry.copy_from_slice(&shift(offset)?.to_be_bytes());
+                }
+            }
+            _ => (),
+        }
+    }
+
+    Ok(())
+}
+
+/// The entries of a chunk offset table, each `width` bytes.
+fn offset_table(
+    payload: &mut [u8],
+    width: usize,
+) -> Result<impl Iterator<Item = &mut [u8]>> {
+    let malformed = || Error::Chapters("malformed chunk offset table".into());
+
+    let count = u32::from_be_bytes(
+        payload.get(4..8).ok_or_else(malformed)?.try_into().unwrap(),
+    ) as usize;
+    let entries = payload
+        .get_mut(8..8 + count * width)
+        .ok_or_else(malformed)?;
+
+    Ok(entries.chunks_exact_mut(width))
+}
+
+/// The chapters embedded in the MP4 at `path`, as start and title.
+#[cfg(test)]
+pub(crate) fn read(path: &Path) -> Result<Vec<(std::time::Duration, String)>> {
+    let data = fs::read(path)?;
+    let Some(moov) = children(&data)?
+        .into_iter()
+        .find(|header| &header.kind == b"moov")
+    else {
+        return Ok(Vec::new());
+    };
+
+    let find = |data: &[u8], kind: &[u8; 4]| -> Result<Option<Vec<u8>>> {
+        Ok(children(data)?
+            .into_iter()
+            .find(|header| &header.kind == kind)
+            .map(|header| {
+                data[header.payload as usize..header.end as usize].to_vec()
+            }))
+    };
+
+    let movie = &data[moov.payload as usize..moov.end as usize];
+    let Some(user_data) = find(movie, b"udta")? else {
+        return Ok(Vec::new());
+    };
+    let Some(list) = find(&user_data, b"chpl")? else {
+        return Ok(Vec::new());
+    };
+
+    let mut chapters = Vec::new();
+    let mut rest = &list[9..];
+    for _ in 0..list[8] {
+        let start = u64::from_be_bytes(rest[..8].try_into().unwrap());
+        let length = rest[8] as usize;
+        let title = String::from_utf8_lossy(&rest[9..9 + length]).into_owned();
+        chapters.push((
+            std::time::Duration::from_secs_f64(start as f64 / TICKS_PER_SEC),
+            title,
+        ));
+        rest = &rest[9 + length..];
+    }
+
+    Ok(chapters)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tempfile::TempDir;
+
+    use super::*;
+
+    const MEDIA: &[u8] = b"first chunk|second chunk";
+
+    fn marker(secs: f64, label: Option<&str>) -> Marker {
+        Marker {
+            offset: Duration::from_secs_f64(secs),
+            label: label.map(Into::into),
+        }
+    }
+
+    /// A movie box whose two chunks start `base` bytes into the file.
+    fn movie(base: u64, co64: bool) -> Vec<u8> {
+        let offsets = [base, base + 12];
+        let mut table = vec![0, 0, 0, 0, 0, 0, 0, 2];
+        for offset in offsets {
+            if co64 {
+                table.extend_from_slice(&offset.to_be_bytes());
+            } else {
+                table.extend_from_slice(&(offset as u32).to_be_bytes());
+            }
+        }
+
+        let kind = if co64 { b"co64" } else { b"stco" };
+        let stbl = boxed(b"stbl", &boxed(kind, &table).unwrap()).unwrap();
+        let minf = boxed(b"minf", &stbl).unwrap();
+        let mdia = boxed(b"mdia", &minf).unwrap();
+        let mut payload = boxed(b"mvhd", &[0; 12]).unwrap();
+        payload.extend(boxed(b"trak", &mdia).unwrap());
+        boxed(b"moov", &payload).unwrap()
+    }
+
+    /// An MP4 with the movie box before the media data, as `faststart`
+    /// writes it, or after.
+    fn mp4(dir: &TempDir, faststart: bool, co64: bool) -> std::path::PathBuf {
+        let ftyp = boxed(b"ftyp", b"isom\0\0\0\0").unwrap();
+        let mut data = ftyp.clone();
+
+        if faststart {
+            // The movie's size does not depend on the offsets it holds.
+            let base = (ftyp.len() + movie(0, co64).len() + 8) as u64;
+            data.extend(movie(base, co64));
+            data.extend(boxed(b"mdat", MEDIA).unwrap());
+        } else {
+            let base = ftyp.len() as u64 + 8;
+            data.extend(boxed(b"mdat", MEDIA).unwrap());
+            data.extend(movie(base, co64));
+        }
+
+        let path = dir.path().join("capture.mp4");
+        fs::write(&path, data).unwrap();
+        path
+    }
+
+    /// The media each chunk offset points at.
+    fn chunks(path: &Path) -> Vec<String> {
+        let data = fs::read(path).unwrap();
+        let moov = children(&data)
+            .unwrap()
+            .into_iter()
+            .find(|header| &header.kind == b"moov")
+            .unwrap();
+        let mut movie = data[moov.start as usize..moov.end as usize].to_vec();
+
+        let mut offsets = Vec::new();
+        shift_chunk_offsets(&mut movie, |offset| {
+            offsets.push(offset);
+            Ok(offset)
+        })
+        .unwrap();
+
+        offsets
+            .iter()
+            .map(|&offset| {
+                let chunk = &data[offset as usize..];
+                let end = chunk.iter().position(|&b| b == b'|').unwrap_or(12);
+                String::from_utf8_lossy(&chunk[..end]).into_owned()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn chapters_are_added_before_the_media() {
+        let dir = TempDir::new().unwrap();
+        let path = mp4(&dir, true, false);
+        assert_eq!(chunks(&path), ["first chunk", "second chunk"]);
+
+        embed(
+            &path,
+            &[marker(0.0, None), marker(12.5, Some("Intro done"))],
+        )
+        .unwrap();
+
+        assert_eq!(
+            read(&path).unwrap(),
+            [
+                (Duration::ZERO, "Marker 1".to_string()),
+                (Duration::from_millis(12_500), "Intro done".to_string()),
+            ]
+        );
+        assert_eq!(chunks(&path), ["first chunk", "second chunk"]);
+        assert!(!path.with_extension("chapters.tmp").exists());
+    }
+
+    #[test]
+    fn chapters_are_added_after_the_media() {
+        let dir = TempDir::new().unwrap();
+        let path = mp4(&dir, false, true);
+
+        embed(&path, &[marker(3.0, Some("Bug"))]).unwrap();
+
+        assert_eq!(
+            read(&path).unwrap(),
+            [(Duration::from_secs(3), "Bug".to_string())]
+        );
+        assert_eq!(chunks(&path), ["first chunk", "second chunk"]);
+    }
+
+    #[test]
+    fn chapters_replace_earlier_ones() {
+        let dir = TempDir::new().unwrap();
+        let path = mp4(&dir, true, false);
+
+        embed(&path, &[marker(1.0, Some("Old")), marker(2.0, None)]).unwrap();
+        embed(&path, &[marker(4.0, Some("New"))]).unwrap();
+
+        assert_eq!(
+            read(&path).unwrap(),
+            [(Duration::from_secs(4), "New".to_string())]
+        );
+        assert_eq!(chunks(&path), ["first chunk", "second chunk"]);
+    }
+
+    #[test]
+    fn other_user_data_is_kept() {
+        let movie = movie(0, false);
+        let with_tags = {
+            let mut data = movie[8..].to_vec();
+            data.extend(
+                boxed(b"udta", &boxed(b"\xa9too", b"x264").unwrap()).unwrap(),
+            );
+            boxed(b"moov", &data).unwrap()
+        };
+
+        let chaptered =
+            with_chapters(&with_tags, &chpl(&[marker(1.0, None)])).unwrap();
+
+        let moov = header(&chaptered, 0, chaptered.len() as u64).unwrap();
+        let contents = &chaptered[moov.payload as usize..];
+        let user_data = children(contents)
+            .unwrap()
+            .into_iter()
+            .filter(|child| &child.kind == b"udta")
+            .collect::<Vec<_>>();
+        assert_eq!(user_data.len(), 1);
+        let entries: Vec<_> = children(
+            &contents[user_data[0].payload as usize..user_data[0].end as usize],
+        )
+        .unwrap()
+        .into_iter()
+        .map(|entry| entry.kind)
+        .collect();
+        assert_eq!(entries, [*b"\xa9too", *b"chpl"]);
+    }
+
+    #[test]
+    fn long_titles_are_cut_at_a_character_boundary() {
+        let title = "é".repeat(200);
+        assert_eq!(truncate(&title, MAX_TITLE).len(), 254);
+        assert_eq!(truncate("short", MAX_TITLE), "short");
+    }
+
+    #[test]
+    fn files_without_a_movie_are_left_alone() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("capture.mp4");
+        fs::write(&path, boxed(b"mdat", MEDIA).unwrap()).unwrap();
+
+        let error = embed(&path, &[marker(1.0, None)]).unwrap_err();
+
+        assert!(matches!(error, Error::Chapters(_)));
+        assert_eq!(fs::read(&path).unwrap(), boxed(b"mdat", MEDIA).unwrap());
+    }
+}
@@ -0,0 +1,181 @@
+use std::time::Duration;
+
+use clap::{Args, Parser, Subcommand};
+
+use crate::{
+    capture::Source,
+    control::Request,
+    input::Backend,
+    instance::IfRunning,
+    library::{Age, Size},
+    logging,
+    recorder::OnError,
+};
+
+#[derive(Debug, Parser)]
+#[command(
+    version,
+    about = "Screen and audio recorder for Hyprland",
+    args_conflicts_with_subcommands = true
+)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// Log filter for stderr and the log file, e.g. `debug` or
+    /// `info,gstreamer=debug`
+    #[arg(
+        long,
+        global = true,
+        env = "HYPR_RECORDER_LOG",
+        default_value = logging::DEFAULT_LEVEL
+    )]
+    pub log_level: String,
+
+    #[command(flatten)]
+    pub record: RecordArgs,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Start a recording session (the default)
+    Record(RecordArgs),
+    /// Stay resident and record whenever asked to over the control socket
+    Daemon(SessionArgs),
+    /// Send a command to the running recorder
+    Ctl {
+        /// One of: start, toggle, save, cancel, pause, marker, status
+        request: Request,
+    },
+    /// List saved recordings, oldest first
+    List {
+        /// Print the list as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Show everything known about a recording
+    Show {
+        /// Recording id, as printed by `list`, or its path
+        id: String,
+    },
+    /// Delete a recording along with its sidecars
+    Delete {
+        /// Recording id, as printed by `list`, or its path
+        id: String,
+    },
+    /// Delete old recordings, by default following the retention policy
+    Prune(PruneArgs),
+    /// Search the transcripts of saved recordings
+    Search {
+        /// Words that must all appear in a transcript line
+        #[arg(required_unless_present = "reindex")]
+        query: Vec<String>,
+
+        /// Maximum number of matches to print
+        #[arg(long, default_value_t = 20)]
+        limit: usize,
+
+        /// Print the matches as JSON
+        #[arg(long)]
+        json: bool,
+
+        /// Rebuild the index from the subtitles of every saved recording
+        #[arg(long)]
+        reindex: bool,
+    },
+    /// Upload a recording to the configured S3 storage and print its link
+    Upload {
+        /// Recording id, as printed by `list`, or its path; without one,
+        /// retry the uploads queued while the storage was unreachable
+        id: Option<String>,
+    },
+    /// Check the plugins, tools and services recording depends on
+    Doctor,
+    /// Open a recording in the player, e.g. at a search match
+    Play {
+        /// Recording id, as printed by `list`, or its path
+        id: String,
+
+        /// Position to start at, as seconds or [H:]MM:SS
+        #[arg(long, value_parser = parse_offset, default_value = "0")]
+        at: Duration,
+    },
+}
+
+fn parse_offset(value: &str) -> Result<Duration, String> {
+    let seconds = value.split(':').try_fold(0.0, |total, part| {
+        part.parse::<f64>()
+            .ok()
+            .filter(|part| part.is_finite() && *part >= 0.0)
+            .map(|part| total * 60.0 + part)
+    });
+
+    seconds
+        .map(Duration::from_secs_f64)
+        .ok_or_else(|| format!("invalid position `{value}`"))
+}
+
+#[derive(Debug, Clone, Args)]
+pub struct PruneArgs {
+    /// Delete recordings older than this, e.g. 12h, 30d or 4w
+    #[arg(long)]
+    pub older_than: Option<Age>,
+
+    /// Delete the oldest recordings until the rest fit, e.g. 500M or 20G
+    #[arg(long)]
+    pub max_size: Option<Size>,
+
+    /// Only print what would be deleted
+    #[arg(long)]
+    pub dry_run: bool,
+}
+
+#[derive(Debug, Clone, Args)]
+pub struct RecordArgs {
+    /// What to do if a recording session is already active
+    #[arg(
+        long,
+        value_enum,
+        env = "HYPR_RECORDER_IF_RUNNING",
+        default_value_t = IfRunning::Save
+    )]
+    pub if_running: IfRunning,
+
+    #[command(flatten)]
+    pub session: SessionArgs,
+}
+
+/// Options shared by one-shot recordings and the daemon.
+#[derive(Debug, Clone, Args)]
+pub struct SessionArgs {
+    /// How to listen for the recording keybindings
+    #[arg(
+        long,
+        value_enum,
+        env = "HYPR_RECORDER_INPUT",
+        default_value_t = Backend::Auto
+    )]
+    pub input: Backend,
+
+    /// Where to capture from
+    #[arg(
+        long,
+        value_enum,
+        env = "HYPR_RECORDER_SOURCE",
+        default_value_t = Source::default()
+    )]
+    pub source: Source,
+
+    /// Keep a portal restore token so the source picker only shows once
+    #[arg(long, env = "HYPR_RECORDER_PERSIST_PORTAL")]
+    pub persist_portal: bool,
+
+    /// What to do with the recording if the pipeline fails mid-way
+    #[arg(
+        long,
+        value_enum,
+        env = "HYPR_RECORDER_ON_ERROR",
+        default_value_t = OnError::Salvage
+    )]
+    pub on_error: OnError,
+}
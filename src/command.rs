@@ -0,0 +1,35 @@
+#[cfg(any(test, feature = "test-support"))]
+mod scripted;
+
+use std::{process::Command, sync::Arc};
+
+#[cfg(any(test, feature = "test-support"))]
+pub use self::scripted::ScriptedRunner;
+use crate::{
+    utils::{run_command, Output},
+    Result,
+};
+
+/// Runs an external program to completion. Whatever shells out to swayosd
+/// or whisper goes through one, so tests can script the real programs'
+/// answers.
+pub trait CommandRunner: Send + Sync {
+    fn run(&self, program: &str, args: &[String]) -> Result<Output>;
+}
+
+/// Spawns the programs for real.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemRunner;
+
+impl CommandRunner for SystemRunner {
+    fn run(&self, program: &str, args: &[String]) -> Result<Output> {
+        let mut command = Command::new(program);
+        command.args(args);
+        run_command(command)
+    }
+}
+
+/// The runner used unless one is injected.
+pub fn system() -> Arc<dyn CommandRunner> {
+    Arc::new(SystemRunner)
+}
@@ -0,0 +1,71 @@
+use std::{collections::VecDeque, sync::Mutex};
+
+use super::CommandRunner;
+use crate::{utils::Output, Result};
+
+/// Expects an exact sequence of command lines, answering each with a canned
+/// [`Output`], and panics on anything else.
+#[derive(Debug, Default)]
+pub struct ScriptedRunner {
+    script: Mutex<VecDeque<(Vec<String>, Output)>>,
+    calls: Mutex<Vec<Vec<String>>>,
+}
+
+impl ScriptedRunner {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Expects `argv`, program first, to run next, answering with `output`.
+    pub fn expect(self, argv: &[&str], output: Output) -> Self {
+        self.script.lock().unwrap().push_back((
+            argv.iter().map(|arg| arg.to_string()).collect(),
+            output,
+        ));
+        self
+    }
+
+    /// Expects `argv` to run next and succeed with `stdout`.
+    pub fn expect_success(self, argv: &[&str], stdout: &str) -> Self {
+        self.expect(
+            argv,
+            Output {
+                stdout: stdout.into(),
+                stderr: String::new(),
+                status: 0,
+            },
+        )
+    }
+
+    /// Every command line run so far, program first.
+    pub fn calls(&self) -> Vec<Vec<String>> {
+        self.calls.lock().unwrap().clone()
+    }
+
+    /// Panics if an expected command has not run.
+    pub fn assert_done(&self) {
+        let script = self.script.lock().unwrap();
+        assert!(
+            script.is_empty(),
+            "expected commands did not run: {:?}",
+            script.iter().map(|(argv, _)| argv).collect::<Vec<_>>()
+        );
+    }
+}
+
+impl CommandRunner for ScriptedRunner {
+    fn run(&self, program: &str, args: &[String]) -> Result<Output> {
+        let argv: Vec<String> = std::iter::once(program.to_string())
+            .chain(args.iter().cloned())
+            .collect();
+        self.calls.lock().unwrap().push(argv.clone());
+
+        let Some((expected, output)) = self.script.lock().unwrap().pop_front()
+        else {
+            panic!("unexpected command: {argv:?}");
+        };
+        assert_eq!(argv, expected, "command line differs from the script");
+
+        Ok(output)
+    }
+}
@@ -0,0 +1,104 @@
+#[cfg(feature = "hyprland")]
+mod hyprland;
+mod sway;
+
+use std::{env, fmt};
+
+use async_trait::async_trait;
+use serde::Serialize;
+
+#[cfg(feature = "hyprland")]
+pub use self::hyprland::Hyprland;
+pub use self::sway::Sway;
+use crate::{input::Bindings, Result};
+
+/// Set for the commands our bindings run, used to recognise them later.
+pub const BIND_MARKER: &str = "HYPR_RECORDER_BIND=1";
+
+/// Sent by the enter chord on compositors that emulate the controls mode.
+pub const ARM_REQUEST: &str = "arm";
+/// Sent by the leave key on compositors that emulate the controls mode.
+pub const DISARM_REQUEST: &str = "disarm";
+
+/// The focused window and its position in the global layout.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Window {
+    pub class: String,
+    pub title: String,
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl fmt::Display for Window {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} ({}x{}+{}+{})",
+            self.class, self.width, self.height, self.x, self.y
+        )
+    }
+}
+
+/// The parts of a Wayland compositor the recorder talks to.
+///
+/// Triggered bindings report back through `hypr-recorder ctl`: an action
+/// request, or [`ARM_REQUEST`]/[`DISARM_REQUEST`] from compositors that
+/// cannot define a mode at runtime and emulate one with `arm`/`disarm`.
+#[async_trait(?Send)]
+pub trait Compositor {
+    fn name(&self) -> &'static str;
+
+    async fn focused_window(&self) -> Result<Option<Window>>;
+
+    /// Name of the output holding the focus, e.g. `DP-1`.
+    async fn focused_monitor(&self) -> Result<Option<String>>;
+
+    /// Installs the recording bindings for the session.
+    async fn bind(&mut self, bindings: &Bindings) -> Result<()>;
+
+    /// Keeps the bindings in place, e.g. across config reloads. Only returns
+    /// once the compositor stops reporting events.
+    async fn maintain(&mut self) -> Result<()>;
+
+    /// Activates the action keys after the enter chord was pressed.
+    async fn arm(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Deactivates the action keys after an action or the leave key.
+    async fn disarm(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Removes our bindings and restores any the user had on those keys.
+    async fn unbind(&mut self) -> Result<()>;
+
+    /// Blocking variant of [`Compositor::unbind`] for use in `Drop`.
+    fn unbind_blocking(&mut self);
+}
+
+/// Connects to the compositor this process runs under, if it is supported.
+pub fn detect() -> Result<Option<Box<dyn Compositor>>> {
+    #[cfg(feature = "hyprland")]
+    if env::var("HYPRLAND_INSTANCE_SIGNATURE").is_ok() {
+        return Ok(Some(Box::new(Hyprland::from_env()?)));
+    }
+
+    if env::var("SWAYSOCK").is_ok() {
+        return Ok(Some(Box::new(Sway::from_env()?)));
+    }
+
+    Ok(None)
+}
+
+/// Shell command a binding runs to send `request` to the control socket.
+pub(crate) fn ctl(request: &str) -> String {
+    let program = env::current_exe()
+        .map(|path| path.to_string_lossy().into_owned())
+        .unwrap_or_else(|_| "hypr-recorder".into());
+    let program = program.replace('\'', r"'\''");
+
+    format!("{BIND_MARKER} '{program}' ctl {request}")
+}
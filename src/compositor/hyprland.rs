@@ -0,0 +1,469 @@
+use std::{env, path::PathBuf};
+
+use async_trait::async_trait;
+use serde::Deserialize;
+use tokio::fs;
+use tracing::{debug, info, warn};
+
+use super::{ctl, Compositor, Window, BIND_MARKER};
+use crate::{
+    hyprland::{self, Bind, EventStream, HyprlandClient},
+    input::Bindings,
+    Result,
+};
+
+/// Name of the submap holding the recording controls.
+pub const SUBMAP: &str = "hypr-recorder";
+
+const SNAPSHOT_NAME: &str = "hypr-recorder-binds.json";
+
+/// Hyprland, driven over its IPC sockets.
+///
+/// The action keys live in a dedicated submap entered by the enter chord, so
+/// Hyprland itself takes care of arming and disarming them.
+pub struct Hyprland {
+    client: HyprlandClient,
+    events: Option<EventStream>,
+    registered: Option<Bindings>,
+    saved_binds: Vec<Bind>,
+    /// Where `saved_binds` are persisted while the bindings are registered.
+    snapshot: PathBuf,
+}
+
+/// The subset of `hyprctl activewindow -j` we care about.
+#[derive(Deserialize)]
+struct ActiveWindow {
+    #[serde(default)]
+    class: String,
+    #[serde(default)]
+    title: String,
+    at: (i32, i32),
+    size: (u32, u32),
+}
+
+/// The subset of `hyprctl activeworkspace -j` we care about.
+#[derive(Deserialize)]
+struct ActiveWorkspace {
+    monitor: String,
+}
+
+impl Hyprland {
+    pub fn from_env() -> Result<Self> {
+        let client = HyprlandClient::from_env()?;
+
+        info!(
+            "Using Hyprland socket: {}",
+            client.request_socket().display()
+        );
+
+        Ok(Self {
+            client,
+            events: None,
+            registered: None,
+            saved_binds: Vec::new(),
+            snapshot: snapshot_path(),
+        })
+    }
+
+    /// Removes bindings left behind by a run that exited without cleaning up,
+    /// restoring whatever that run had saved in their place.
+    async fn remove_stale_binds(&self) -> Result<()> {
+        let stale: Vec<Bind> = self
+            .client
+            .binds()
+            .await?
+            .into_iter()
+            .filter(is_ours)
+            .collect();
+
+        if stale.is_empty() {
+            let _ = fs::remove_file(&self.snapshot).await;
+            return Ok(());
+        }
+
+        info!(
+            "Removing {} stale keybinding(s) from a previous run",
+            stale.len()
+        );
+
+        let saved: Vec<Bind> = match fs::read(&self.snapshot).await {
+            Ok(contents) => serde_json::from_slice(&contents)?,
+            Err(_) => Vec::new(),
+        };
+
+        let mut commands = vec!["dispatch submap reset".to_string()];
+        for bind in &stale {
+            commands.extend(hyprland::in_submap(
+                &bind.submap,
+                vec![format!("keyword unbind {}", bind.combo())],
+            ));
+        }
+        self.client.batch(&commands).await?;
+
+        let current = self.client.binds().await?;
+        self.restore(&saved, &current).await?;
+
+        let _ = fs::remove_file(&self.snapshot).await;
+
+        Ok(())
+    }
+
+    async fn restore(&self, saved: &[Bind], current: &[Bind]) -> Result<()> {
+        let commands = restore_commands(saved, current);
+
+        if commands.is_empty() {
+            return Ok(());
+        }
+
+        self.client.batch(&commands).await
+    }
+
+    fn run_cleanup_blocking(&self, bindings: &Bindings) {
+        if let Err(e) = self.client.batch_blocking(&cleanup_commands(bindings))
+        {
+            warn!("Failed to remove keybindings in blocking fallback: {}", e);
+        }
+
+        let restored = self.client.binds_blocking().and_then(|current| {
+            let commands = restore_commands(&self.saved_binds, &current);
+            if commands.is_empty() {
+                return Ok(());
+            }
+            self.client.batch_blocking(&commands)
+        });
+
+        if let Err(e) = restored {
+            warn!("Failed to restore keybindings in blocking fallback: {}", e);
+        }
+    }
+
+    fn finish_cleanup(&mut self) {
+        self.registered = None;
+        self.events = None;
+        self.saved_binds.clear();
+        let _ = std::fs::remove_file(&self.snapshot);
+        debug!("Keybinding cleanup completed");
+    }
+}
+
+#[async_trait(?Send)]
+impl Compositor for Hyprland {
+    fn name(&self) -> &'static str {
+        "Hyprland"
+    }
+
+    async fn focused_window(&self) -> Result<Option<Window>> {
+        let reply = self.client.query_json("activewindow").await?;
+
+        // With nothing focused Hyprland answers with an empty object.
+        let Ok(window) = serde_json::from_str::<ActiveWindow>(&reply) else {
+            return Ok(None);
+        };
+
+        Ok(Some(Window {
+            class: window.class,
+            title: window.title,
+            x: window.at.0,
+            y: window.at.1,
+            width: window.size.0,
+            height: window.size.1,
+        }))
+    }
+
+    async fn focused_monitor(&self) -> Result<Option<String>> {
+        let reply = self.client.query_json("activeworkspace").await?;
+
+        Ok(serde_json::from_str::<ActiveWorkspace>(&reply)
+            .ok()
+            .map(|workspace| workspace.monitor))
+    }
+
+    async fn bind(&mut self, bindings: &Bindings) -> Result<()> {
+        self.remove_stale_binds().await?;
+
+        info!("Registering recording keybindings");
+
+        // `unbind` on cleanup also drops any binding the user had on the
+        // same keys, so remember theirs and put them back afterwards. The
+        // snapshot is persisted so a crashed run can be repaired next time.
+        let claimed = bindings.all();
+        self.saved_binds = self
+            .client
+            .binds()
+            .await?
+            .into_iter()
+            .filter(|bind| {
+                !is_ours(bind) && claimed.iter().any(|key| key.matches(bind))
+            })
+            .collect();
+        fs::write(&self.snapshot, serde_json::to_vec(&self.saved_binds)?)
+            .await?;
+
+        if !self.saved_binds.is_empty() {
+            info!(
+                "Saved {} existing binding(s) on the recording keys",
+                self.saved_binds.len()
+            );
+        }
+
+        self.client.batch(&register_commands(bindings)).await?;
+
+        // A config reload drops keywords set at runtime, so watch for it and
+        // put our bindings back.
+        self.events = match self.client.subscribe().await {
+            Ok(events) => Some(events),
+            Err(e) => {
+                warn!("Failed to subscribe to Hyprland events: {}", e);
+                None
+            }
+        };
+
+        self.registered = Some(bindings.clone());
+
+        info!(
+            "Keybindings registered; press {} to enter the `{SUBMAP}` submap",
+            bindings.enter
+        );
+        Ok(())
+    }
+
+    async fn maintain(&mut self) -> Result<()> {
+        let Some(events) = self.events.as_mut() else {
+            return Ok(());
+        };
+
+        while let Some(event) = events.next().await? {
+            if event.name != "configreloaded" {
+                continue;
+            }
+
+            if let Some(bindings) = &self.registered {
+                info!("Hyprland config reloaded, restoring keybindings");
+                self.client.batch(&register_commands(bindings)).await?;
+            }
+        }
+
+        warn!("Hyprland closed the event socket");
+        self.events = None;
+        Ok(())
+    }
+
+    async fn unbind(&mut self) -> Result<()> {
+        let Some(bindings) = self.registered.take() else {
+            return Ok(());
+        };
+
+        info!("Cleaning up recording keybindings");
+
+        let result = async {
+            self.client.batch(&cleanup_commands(&bindings)).await?;
+            let current = self.client.binds().await?;
+            self.restore(&self.saved_binds, &current).await
+        }
+        .await;
+
+        // Unbinding is idempotent and restoring only adds what is missing,
+        // so the fallback can simply run the whole sequence again.
+        if let Err(e) = result {
+            warn!("Asynchronous keybinding cleanup failed: {}", e);
+            warn!("Falling back to blocking keybinding cleanup");
+            self.run_cleanup_blocking(&bindings);
+        }
+
+        self.finish_cleanup();
+
+        Ok(())
+    }
+
+    fn unbind_blocking(&mut self) {
+        let Some(bindings) = self.registered.take() else {
+            return;
+        };
+
+        self.run_cleanup_blocking(&bindings);
+        self.finish_cleanup();
+    }
+}
+
+fn register_commands(bindings: &Bindings) -> Vec<String> {
+    let mut submap = Vec::new();
+    for (binding, action) in bindings.actions() {
+        let combo = binding.combo();
+        submap.push(format!(
+            "keyword bind {combo},exec,{}",
+            ctl(action.request())
+        ));
+        submap.push(format!("keyword bind {combo},submap,reset"));
+    }
+    submap.push(format!(
+        "keyword bind {},submap,reset",
+        bindings.leave.combo()
+    ));
+
+    let mut commands = vec![format!(
+        "keyword bind {},submap,{SUBMAP}",
+        bindings.enter.combo()
+    )];
+    commands.extend(hyprland::in_submap(SUBMAP, submap));
+    commands
+}
+
+fn cleanup_commands(bindings: &Bindings) -> Vec<String> {
+    let mut submap: Vec<String> = bindings
+        .actions()
+        .iter()
+        .map(|(binding, _)| format!("keyword unbind {}", binding.combo()))
+        .collect();
+    submap.push(format!("keyword unbind {}", bindings.leave.combo()));
+
+    let mut commands = vec![
+        "dispatch submap reset".to_string(),
+        format!("keyword unbind {}", bindings.enter.combo()),
+    ];
+    commands.extend(hyprland::in_submap(SUBMAP, submap));
+    commands
+}
+
+fn is_ours(bind: &Bind) -> bool {
+    bind.submap == SUBMAP
+        || bind.arg.contains(BIND_MARKER)
+        || (bind.dispatcher == "submap" && bind.arg == SUBMAP)
+}
+
+/// Commands recreating each saved binding that is no longer present.
+fn restore_commands(saved: &[Bind], current: &[Bind]) -> Vec<String> {
+    saved
+        .iter()
+        .filter(|bind| !current.contains(bind))
+        .flat_map(|bind| {
+            hyprland::in_submap(&bind.submap, vec![bind.keyword()])
+        })
+        .collect()
+}
+
+fn snapshot_path() -> PathBuf {
+    env::var("XDG_RUNTIME_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| env::temp_dir())
+        .join(SNAPSHOT_NAME)
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::hyprland::tests::serve;
+
+    /// The user's own binding on the enter chord, SUPER ALT R.
+    const USERS_BIND: &str = r#"{"modmask": 72, "key": "r",
+                                 "dispatcher": "exec", "arg": "kitty"}"#;
+
+    fn bindings() -> Bindings {
+        Bindings {
+            enter: "SUPER ALT, R".parse().unwrap(),
+            leave: "SUPER ALT, R".parse().unwrap(),
+            save: "Return".parse().unwrap(),
+            cancel: "Escape".parse().unwrap(),
+            pause: "P".parse().unwrap(),
+            marker: "M".parse().unwrap(),
+        }
+    }
+
+    fn hyprland(dir: &TempDir) -> Hyprland {
+        Hyprland {
+            client: HyprlandClient::with_socket_dir(dir.path()),
+            events: None,
+            registered: None,
+            saved_binds: Vec::new(),
+            snapshot: dir.path().join(SNAPSHOT_NAME),
+        }
+    }
+
+    /// The request running `commands`, and its reply once they succeed.
+    fn batch(commands: &[String]) -> (String, String) {
+        (
+            format!("[[BATCH]]{}", commands.join(";")),
+            "ok".repeat(commands.len()),
+        )
+    }
+
+    #[tokio::test]
+    async fn bind_saves_the_users_binds_on_the_recording_keys() {
+        let dir = TempDir::new().unwrap();
+        let bindings = bindings();
+        let (register, registered) = batch(&register_commands(&bindings));
+        let unrelated = r#"{"modmask": 64, "key": "Q",
+                            "dispatcher": "killactive"}"#;
+        let server = serve(
+            &dir,
+            &["[]", &format!("[{USERS_BIND}, {unrelated}]"), &registered],
+        );
+        let mut hyprland = hyprland(&dir);
+
+        hyprland.bind(&bindings).await.unwrap();
+
+        assert_eq!(server.await.unwrap(), ["j/binds", "j/binds", &register]);
+        let saved: Vec<Bind> =
+            serde_json::from_slice(&std::fs::read(&hyprland.snapshot).unwrap())
+                .unwrap();
+        assert_eq!(saved, [serde_json::from_str(USERS_BIND).unwrap()]);
+        assert!(hyprland.registered.is_some());
+    }
+
+    #[tokio::test]
+    async fn bind_removes_stale_binds_first() {
+        let dir = TempDir::new().unwrap();
+        let bindings = bindings();
+        let stale = format!(
+            r#"{{"modmask": 0, "key": "Return", "submap": "{SUBMAP}",
+                 "dispatcher": "exec", "arg": "{BIND_MARKER} x ctl save"}}"#
+        );
+        let (unbind, unbound) = batch(&[
+            "dispatch submap reset".to_string(),
+            format!("keyword submap {SUBMAP}"),
+            "keyword unbind ,Return".to_string(),
+            "keyword submap reset".to_string(),
+        ]);
+        let (register, registered) = batch(&register_commands(&bindings));
+        let server = serve(
+            &dir,
+            &[&format!("[{stale}]"), &unbound, "[]", "[]", &registered],
+        );
+        let mut hyprland = hyprland(&dir);
+
+        hyprland.bind(&bindings).await.unwrap();
+
+        assert_eq!(
+            server.await.unwrap(),
+            ["j/binds", &unbind, "j/binds", "j/binds", &register]
+        );
+    }
+
+    // The fallback blocks on the socket, so the fake server needs a thread
+    // of its own.
+    #[tokio::test(flavor = "multi_thread")]
+    async fn unbind_falls_back_to_blocking_cleanup() {
+        let dir = TempDir::new().unwrap();
+        let bindings = bindings();
+        let users_bind: Bind = serde_json::from_str(USERS_BIND).unwrap();
+        let (cleanup, cleaned) = batch(&cleanup_commands(&bindings));
+        let (restore, restored) = batch(&[users_bind.keyword()]);
+        let server =
+            serve(&dir, &["invalid dispatcher", &cleaned, "[]", &restored]);
+        let mut hyprland = hyprland(&dir);
+        hyprland.registered = Some(bindings);
+        hyprland.saved_binds = vec![users_bind];
+        std::fs::write(&hyprland.snapshot, "[]").unwrap();
+
+        hyprland.unbind().await.unwrap();
+
+        assert_eq!(
+            server.await.unwrap(),
+            [&cleanup, &cleanup, "j/binds", &restore]
+        );
+        assert!(hyprland.registered.is_none());
+        assert!(hyprland.saved_binds.is_empty());
+        assert!(!hyprland.snapshot.exists());
+    }
+}
@@ -0,0 +1,674 @@
+use async_trait::async_trait;
+use serde_json::Value;
+use tracing::{debug, info, warn};
+
+use super::{ctl, Compositor, Window, ARM_REQUEST, DISARM_REQUEST};
+use crate::{
+    hyprland,
+    input::{Bindings, KeyBinding},
+    sway::{EventStream, SwayClient},
+    Result,
+};
+
+/// Event type of workspace events, which also announce config reloads.
+const WORKSPACE_EVENT: u32 = 0;
+
+/// sway, driven over its i3-compatible IPC socket.
+///
+/// Modes can only be declared in the config file, so the controls mode is
+/// emulated: the enter chord is bound for the whole session and the action
+/// keys are only bound while the controls are armed.
+pub struct Sway {
+    client: SwayClient,
+    events: Option<EventStream>,
+    registered: Option<Bindings>,
+    armed: bool,
+    user_binds: Vec<UserBind>,
+}
+
+/// A binding from the user's config that one of ours shadows.
+#[derive(Debug, Clone)]
+struct UserBind {
+    /// Same form as [`bind_target`], so the two can be compared.
+    target: String,
+    command: String,
+}
+
+impl Sway {
+    pub fn from_env() -> Result<Self> {
+        let client = SwayClient::from_env()?;
+
+        Ok(Self {
+            client,
+            events: None,
+            registered: None,
+            armed: false,
+            user_binds: Vec::new(),
+        })
+    }
+
+    fn arm_commands(bindings: &Bindings) -> Vec<String> {
+        let mut commands: Vec<String> = bindings
+            .actions()
+            .iter()
+            .map(|(binding, action)| bind_command(binding, action.request()))
+            .collect();
+        commands.push(bind_command(&bindings.leave, DISARM_REQUEST));
+        commands
+    }
+
+    fn disarm_commands(&self, bindings: &Bindings) -> Vec<String> {
+        let mut keys: Vec<&KeyBinding> =
+            bindings.actions().map(|(binding, _)| binding).to_vec();
+        if bindings.leave != bindings.enter {
+            keys.push(&bindings.leave);
+        }
+
+        let mut commands: Vec<String> =
+            keys.iter().map(|binding| unbind_command(binding)).collect();
+        if bindings.leave == bindings.enter {
+            commands.push(bind_command(&bindings.enter, ARM_REQUEST));
+        }
+        commands.extend(self.restore_commands(&keys));
+        commands
+    }
+
+    fn restore_commands(&self, keys: &[&KeyBinding]) -> Vec<String> {
+        let targets: Vec<String> =
+            keys.iter().map(|binding| bind_target(binding)).collect();
+
+        self.user_binds
+            .iter()
+            .filter(|bind| targets.contains(&bind.target))
+            .map(|bind| bind.command.clone())
+            .collect()
+    }
+
+    fn cleanup_commands(&self, bindings: &Bindings) -> Vec<String> {
+        let mut commands = if self.armed {
+            self.disarm_commands(bindings)
+        } else {
+            Vec::new()
+        };
+
+        commands.push(unbind_command(&bindings.enter));
+        commands.extend(self.restore_commands(&[&bindings.enter]));
+        commands
+    }
+
+    fn finish_cleanup(&mut self) {
+        self.registered = None;
+        self.events = None;
+        self.armed = false;
+        self.user_binds.clear();
+        debug!("Keybinding cleanup completed");
+    }
+}
+
+#[async_trait(?Send)]
+impl Compositor for Sway {
+    fn name(&self) -> &'static str {
+        "sway"
+    }
+
+    async fn focused_window(&self) -> Result<Option<Window>> {
+        let tree = self.client.tree().await?;
+        Ok(find_focused(&tree))
+    }
+
+    async fn focused_monitor(&self) -> Result<Option<String>> {
+        let tree = self.client.tree().await?;
+        Ok(focused_output(&tree))
+    }
+
+    async fn bind(&mut self, bindings: &Bindings) -> Result<()> {
+        info!("Registering recording keybindings");
+
+        // `unbindsym` drops whatever was on the key, so find the user's own
+        // bindings on our keys in the loaded config to put them back later.
+        let config = self.client.config().await?;
+        self.user_binds = user_binds(&config);
+        let claimed: Vec<String> =
+            bindings.all().into_iter().map(bind_target).collect();
+        self.user_binds
+            .retain(|bind| claimed.contains(&bind.target));
+
+        if !self.user_binds.is_empty() {
+            info!(
+                "Saved {} existing binding(s) on the recording keys",
+                self.user_binds.len()
+            );
+        }
+
+        self.client
+            .run(&[bind_command(&bindings.enter, ARM_REQUEST)])
+            .await?;
+
+        // `swaymsg reload` drops bindings added at runtime, and announces
+        // itself as a workspace event.
+        self.events = match self.client.subscribe(&["workspace"]).await {
+            Ok(events) => Some(events),
+            Err(e) => {
+                warn!("Failed to subscribe to sway events: {}", e);
+                None
+            }
+        };
+
+        self.registered = Some(bindings.clone());
+        self.armed = false;
+
+        info!(
+            "Keybindings registered; press {} to arm the recording controls",
+            bindings.enter
+        );
+        Ok(())
+    }
+
+    async fn maintain(&mut self) -> Result<()> {
+        let Some(events) = self.events.as_mut() else {
+            return Ok(());
+        };
+
+        while let Some(event) = events.next().await? {
+            if event.kind != WORKSPACE_EVENT
+                || event.payload["change"] != "reload"
+            {
+                continue;
+            }
+
+            if let Some(bindings) = &self.registered {
+                info!("sway config reloaded, restoring keybindings");
+                self.armed = false;
+                self.client
+                    .run(&[bind_command(&bindings.enter, ARM_REQUEST)])
+                    .await?;
+            }
+        }
+
+        warn!("sway closed the event socket");
+        self.events = None;
+        Ok(())
+    }
+
+    async fn arm(&mut self) -> Result<()> {
+        let Some(bindings) = &self.registered else {
+            return Ok(());
+        };
+
+        if !self.armed {
+            self.client.run(&Self::arm_commands(bindings)).await?;
+            self.armed = true;
+            debug!("Recording controls armed");
+        }
+        Ok(())
+    }
+
+    async fn disarm(&mut self) -> Result<()> {
+        let Some(bindings) = &self.registered else {
+            return Ok(());
+        };
+
+        if self.armed {
+            self.client.run(&self.disarm_commands(bindings)).await?;
+            self.armed = false;
+        }
+        Ok(())
+    }
+
+    async fn unbind(&mut self) -> Result<()> {
+        let Some(bindings) = &self.registered else {
+            return Ok(());
+        };
+
+        info!("Cleaning up recording keybindings");
+
+        let commands = self.cleanup_commands(bindings);
+        let result = self.client.run(&commands).await;
+        self.finish_cleanup();
+        result
+    }
+
+    fn unbind_blocking(&mut self) {
+        let Some(bindings) = &self.registered else {
+            return;
+        };
+
+        let commands = self.cleanup_commands(bindings);
+        if let Err(e) = self.client.run_blocking(&commands) {
+            warn!("Failed to remove keybindings in blocking fallback: {}", e);
+        }
+        self.finish_cleanup();
+    }
+}
+
+/// sway's modifier names, indexed like [`hyprland::MODIFIERS`].
+const MODIFIER_NAMES: [&str; 8] = [
+    "Shift", "Lock", "Ctrl", "Mod1", "Mod2", "Mod3", "Mod4", "Mod5",
+];
+
+/// The binding as `bindsym`/`bindcode` spells it, in canonical form.
+fn combo(binding: &KeyBinding) -> String {
+    let mut parts: Vec<String> = hyprland::MODIFIERS
+        .iter()
+        .zip(MODIFIER_NAMES)
+        .filter(|((bit, _), _)| binding.modmask & bit != 0)
+        .map(|(_, name)| name.to_string())
+        .collect();
+
+    match binding.key.strip_prefix("code:") {
+        Some(code) => parts.push(code.to_string()),
+        None => parts.push(binding.key.to_ascii_lowercase()),
+    }
+
+    parts.join("+")
+}
+
+fn bind_keyword(binding: &KeyBinding) -> &'static str {
+    if binding.key.starts_with("code:") {
+        "bindcode"
+    } else {
+        "bindsym"
+    }
+}
+
+fn bind_command(binding: &KeyBinding, request: &str) -> String {
+    format!(
+        "{} --no-warn {} exec {}",
+        bind_keyword(binding),
+        combo(binding),
+        ctl(request)
+    )
+}
+
+/// `bindsym`/`bindcode` plus combo, identifying what a binding is on.
+fn bind_target(binding: &KeyBinding) -> String {
+    format!("{} {}", bind_keyword(binding), combo(binding))
+}
+
+fn unbind_command(binding: &KeyBinding) -> String {
+    format!("un{}", bind_target(binding))
+}
+
+/// Brings a config-file binding into the form [`bind_target`] produces.
+fn normalize_target(keyword: &str, raw: &str) -> Option<String> {
+    let mut parts: Vec<&str> = raw.split('+').collect();
+    let key = parts.pop()?;
+
+    let mut modmask = 0;
+    for part in parts {
+        let name = match part.to_ascii_lowercase().as_str() {
+            "shift" => "SHIFT",
+            "lock" => "CAPS",
+            "ctrl" | "control" => "CTRL",
+            "mod1" | "alt" => "ALT",
+            "mod2" => "MOD2",
+            "mod3" => "MOD3",
+            "mod4" | "super" | "logo" => "SUPER",
+            "mod5" => "MOD5",
+            _ => return None,
+        };
+        modmask |= hyprland::parse_modmask(name)?;
+    }
+
+    let key = if keyword == "bindcode" {
+        format!("code:{key}")
+    } else {
+        key.to_string()
+    };
+
+    Some(bind_target(&KeyBinding { modmask, key }))
+}
+
+/// Top-level `bindsym`/`bindcode` lines of `config`, variables expanded.
+fn user_binds(config: &str) -> Vec<UserBind> {
+    let mut variables: Vec<(String, String)> = config
+        .lines()
+        .filter_map(|line| {
+            let rest = line.trim().strip_prefix("set ")?.trim_start();
+            let (name, value) = rest.split_once(char::is_whitespace)?;
+            name.starts_with('$')
+                .then(|| (name.to_string(), value.trim().to_string()))
+        })
+        .collect();
+    // Replace `$modifier` before `$mod`.
+    variables.sort_by_key(|(name, _)| std::cmp::Reverse(name.len()));
+
+    let mut binds = Vec::new();
+    let mut depth = 0usize;
+    let mut block: Option<&str> = None;
+
+    for line in config.lines() {
+        let mut line = line.trim().to_string();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        if line == "}" {
+            depth = depth.saturating_sub(1);
+            block = None;
+            continue;
+        }
+
+        if let Some(opening) = line.strip_suffix('{') {
+            let opening = opening.trim();
+            if depth == 0 {
+                block = ["bindsym", "bindcode"]
+                    .into_iter()
+                    .find(|keyword| *keyword == opening);
+            }
+            depth += 1;
+            continue;
+        }
+
+        if let Some(keyword) = block {
+            if depth == 1 {
+                line = format!("{keyword} {line}");
+            }
+        } else if depth > 0 {
+            continue;
+        }
+
+        for (name, value) in &variables {
+            line = line.replace(name.as_str(), value);
+        }
+
+        let mut words = line.split_whitespace();
+        let keyword = words.next().unwrap_or_default();
+        if keyword != "bindsym" && keyword != "bindcode" {
+            continue;
+        }
+
+        let Some(raw) = words.find(|word| !word.starts_with("--")) else {
+            continue;
+        };
+
+        if let Some(target) = normalize_target(keyword, raw) {
+            binds.push(UserBind {
+                target,
+                command: line.clone(),
+            });
+        }
+    }
+
+    binds
+}
+
+/// The output whose subtree holds the focused node; an empty workspace is
+/// focused itself.
+fn focused_output(root: &Value) -> Option<String> {
+    fn has_focus(node: &Value) -> bool {
+        node["focused"] == true
+            || ["nodes", "floating_nodes"]
+                .iter()
+                .filter_map(|key| node[key].as_array())
+                .flatten()
+                .any(has_focus)
+    }
+
+    root["nodes"]
+        .as_array()?
+        .iter()
+        .find(|output| output["type"] == "output" && has_focus(output))
+        .and_then(|output| output["name"].as_str())
+        .map(str::to_string)
+}
+
+/// Depth-first search of a `GET_TREE` reply for the focused view.
+fn find_focused(node: &Value) -> Option<Window> {
+    let is_view = node["pid"].is_number();
+
+    if is_view && node["focused"] == true {
+        let rect = &node["rect"];
+        let class = node["app_id"]
+            .as_str()
+            .or_else(|| node["window_properties"]["class"].as_str())
+            .unwrap_or_default();
+
+        return Some(Window {
+            class: class.to_string(),
+            title: node["name"].as_str().unwrap_or_default().to_string(),
+            x: rect["x"].as_i64().unwrap_or_default() as i32,
+            y: rect["y"].as_i64().unwrap_or_default() as i32,
+            width: rect["width"].as_u64().unwrap_or_default() as u32,
+            height: rect["height"].as_u64().unwrap_or_default() as u32,
+        });
+    }
+
+    ["nodes", "floating_nodes"]
+        .iter()
+        .filter_map(|key| node[key].as_array())
+        .flatten()
+        .find_map(find_focused)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::sway::tests::{client, serve};
+
+    const RUN_COMMAND: u32 = 0;
+    const SUBSCRIBE: u32 = 2;
+    const GET_CONFIG: u32 = 9;
+    const SUCCESS: &str = r#"[{"success": true}]"#;
+
+    /// A config with the user's own binding on the enter chord.
+    const CONFIG: &str = "\
+set $mod Mod4
+set $modifier Mod1
+
+# bindsym $mod+Shift+e exit
+bindsym $mod+$modifier+r exec kitty
+bindsym --to-code $mod+q kill
+bindcode $mod+38 exec firefox
+bindsym {
+    $modifier+p exec pavucontrol
+}
+mode \"resize\" {
+    bindsym Left resize shrink width 10px
+}
+";
+
+    fn bindings() -> Bindings {
+        Bindings {
+            enter: "SUPER ALT, R".parse().unwrap(),
+            leave: "SUPER ALT, R".parse().unwrap(),
+            save: "Return".parse().unwrap(),
+            cancel: "Escape".parse().unwrap(),
+            pause: "P".parse().unwrap(),
+            marker: "M".parse().unwrap(),
+        }
+    }
+
+    fn sway(dir: &TempDir) -> Sway {
+        Sway {
+            client: client(dir),
+            events: None,
+            registered: None,
+            armed: false,
+            user_binds: Vec::new(),
+        }
+    }
+
+    /// A view as `GET_TREE` describes it.
+    fn view(id: u64, focused: bool) -> Value {
+        json!({
+            "id": id,
+            "type": "con",
+            "pid": 1000 + id,
+            "focused": focused,
+            "name": format!("Window {id}"),
+            "app_id": "foot",
+            "rect": {"x": 10, "y": 20, "width": 800, "height": 600},
+            "nodes": [],
+            "floating_nodes": [],
+        })
+    }
+
+    fn output(name: &str, workspace: Value) -> Value {
+        json!({"type": "output", "name": name, "nodes": [workspace]})
+    }
+
+    fn workspace(focused: bool, nodes: Value, floating: Value) -> Value {
+        json!({
+            "type": "workspace",
+            "focused": focused,
+            "nodes": nodes,
+            "floating_nodes": floating,
+        })
+    }
+
+    fn tree(outputs: Vec<Value>) -> Value {
+        let mut nodes =
+            vec![output("__i3", workspace(false, json!([]), json!([])))];
+        nodes.extend(outputs);
+        json!({"type": "root", "nodes": nodes})
+    }
+
+    #[test]
+    fn user_binds_are_read_with_variables_expanded() {
+        let binds = user_binds(CONFIG);
+
+        let found: Vec<(&str, &str)> = binds
+            .iter()
+            .map(|bind| (bind.target.as_str(), bind.command.as_str()))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                ("bindsym Mod1+Mod4+r", "bindsym Mod4+Mod1+r exec kitty"),
+                ("bindsym Mod4+q", "bindsym --to-code Mod4+q kill"),
+                ("bindcode Mod4+38", "bindcode Mod4+38 exec firefox"),
+                ("bindsym Mod1+p", "bindsym Mod1+p exec pavucontrol"),
+            ]
+        );
+    }
+
+    #[test]
+    fn config_targets_match_our_bindings() {
+        let enter: KeyBinding = "SUPER ALT, R".parse().unwrap();
+
+        assert_eq!(
+            normalize_target("bindsym", "Alt+Logo+R"),
+            Some(bind_target(&enter))
+        );
+        assert_eq!(normalize_target("bindsym", "Hyper+r"), None);
+    }
+
+    #[test]
+    fn the_focused_view_is_found_among_floating_ones() {
+        let mut floating = view(2, true);
+        floating["app_id"] = Value::Null;
+        floating["window_properties"] = json!({"class": "Firefox"});
+        let tree = tree(vec![output(
+            "DP-1",
+            workspace(false, json!([view(1, false)]), json!([floating])),
+        )]);
+
+        let window = find_focused(&tree).unwrap();
+
+        assert_eq!(window.class, "Firefox");
+        assert_eq!(window.title, "Window 2");
+        assert_eq!(
+            (window.x, window.y, window.width, window.height),
+            (10, 20, 800, 600)
+        );
+    }
+
+    #[test]
+    fn focused_workspaces_are_not_windows() {
+        let tree =
+            tree(vec![output("DP-1", workspace(true, json!([]), json!([])))]);
+
+        assert!(find_focused(&tree).is_none());
+    }
+
+    #[test]
+    fn the_focused_output_holds_the_focused_node() {
+        let tree = tree(vec![
+            output(
+                "DP-1",
+                workspace(false, json!([view(1, false)]), json!([])),
+            ),
+            output(
+                "HDMI-A-1",
+                workspace(false, json!([]), json!([view(2, true)])),
+            ),
+        ]);
+
+        assert_eq!(focused_output(&tree).as_deref(), Some("HDMI-A-1"));
+    }
+
+    #[test]
+    fn an_empty_focused_workspace_picks_its_output() {
+        let tree = tree(vec![
+            output(
+                "DP-1",
+                workspace(false, json!([view(1, false)]), json!([])),
+            ),
+            output("HDMI-A-1", workspace(true, json!([]), json!([]))),
+        ]);
+
+        assert_eq!(focused_output(&tree).as_deref(), Some("HDMI-A-1"));
+    }
+
+    #[tokio::test]
+    async fn bind_saves_the_users_binds_on_the_recording_keys() {
+        let dir = TempDir::new().unwrap();
+        let bindings = bindings();
+        let config = json!({ "config": CONFIG }).to_string();
+        let server = serve(
+            &dir,
+            &[
+                (GET_CONFIG, &config),
+                (RUN_COMMAND, SUCCESS),
+                (SUBSCRIBE, r#"{"success": false}"#),
+            ],
+        );
+        let mut sway = sway(&dir);
+
+        sway.bind(&bindings).await.unwrap();
+
+        assert_eq!(
+            server.await.unwrap(),
+            [
+                (GET_CONFIG, String::new()),
+                (RUN_COMMAND, bind_command(&bindings.enter, ARM_REQUEST)),
+                (SUBSCRIBE, r#"["workspace"]"#.to_string()),
+            ]
+        );
+        let saved: Vec<&str> = sway
+            .user_binds
+            .iter()
+            .map(|bind| bind.command.as_str())
+            .collect();
+        assert_eq!(saved, ["bindsym Mod4+Mod1+r exec kitty"]);
+        assert!(sway.registered.is_some());
+        assert!(sway.events.is_none());
+    }
+
+    #[tokio::test]
+    async fn unbind_puts_the_users_binds_back() {
+        let dir = TempDir::new().unwrap();
+        let bindings = bindings();
+        let server =
+            serve(&dir, &[(RUN_COMMAND, SUCCESS), (RUN_COMMAND, SUCCESS)]);
+        let mut sway = sway(&dir);
+        sway.registered = Some(bindings.clone());
+        sway.user_binds = user_binds(CONFIG);
+
+        sway.unbind().await.unwrap();
+
+        assert_eq!(
+            server.await.unwrap(),
+            [
+                (RUN_COMMAND, unbind_command(&bindings.enter)),
+                (RUN_COMMAND, "bindsym Mod4+Mod1+r exec kitty".to_string()),
+            ]
+        );
+        assert!(sway.registered.is_none());
+        assert!(sway.user_binds.is_empty());
+    }
+}
@@ -0,0 +1,429 @@
+use std::{env, fmt, path::PathBuf, str::FromStr};
+
+use tokio::{
+    fs,
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::{UnixListener, UnixStream},
+    sync::{broadcast, mpsc, oneshot},
+    task::JoinHandle,
+};
+use tracing::{debug, info, warn};
+
+use crate::{
+    dbus,
+    input::{Action, Arming},
+    session::{self, Event, State, Status},
+    Error,
+    Result,
+};
+
+const SOCKET_NAME: &str = "hypr-recorder.sock";
+
+/// A command accepted on the control socket, one per line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Request {
+    Start,
+    Toggle,
+    Save,
+    Cancel,
+    Pause,
+    Marker,
+    Status,
+    /// Sent by the enter chord on compositors that emulate the controls
+    /// mode.
+    Arm,
+    /// Sent by the leave key on compositors that emulate the controls mode.
+    Disarm,
+}
+
+impl Request {
+    /// The input action this request maps to, if it drives the session.
+    pub fn action(self) -> Option<Action> {
+        match self {
+            Self::Toggle | Self::Save => Some(Action::Save),
+            Self::Cancel => Some(Action::Cancel),
+            Self::Pause => Some(Action::Pause),
+            Self::Marker => Some(Action::Marker),
+            _ => None,
+        }
+    }
+
+    /// The change to the controls this request makes, if it is one.
+    pub fn arming(self) -> Option<Arming> {
+        match self {
+            Self::Arm => Some(Arming::Arm),
+            Self::Disarm => Some(Arming::Disarm),
+            _ => None,
+        }
+    }
+}
+
+impl FromStr for Request {
+    type Err = String;
+
+    fn from_str(value: &str) -> std::result::Result<Self, Self::Err> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "start" => Ok(Self::Start),
+            "toggle" => Ok(Self::Toggle),
+            "save" => Ok(Self::Save),
+            "cancel" => Ok(Self::Cancel),
+            "pause" => Ok(Self::Pause),
+            "marker" => Ok(Self::Marker),
+            "status" => Ok(Self::Status),
+            "arm" => Ok(Self::Arm),
+            "disarm" => Ok(Self::Disarm),
+            other => Err(format!("unknown command: {other}")),
+        }
+    }
+}
+
+impl fmt::Display for Request {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Start => "start",
+            Self::Toggle => "toggle",
+            Self::Save => "save",
+            Self::Cancel => "cancel",
+            Self::Pause => "pause",
+            Self::Marker => "marker",
+            Self::Status => "status",
+            Self::Arm => "arm",
+            Self::Disarm => "disarm",
+        };
+
+        f.write_str(name)
+    }
+}
+
+/// A single-line reply: `ok [message]` or `error <message>`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Response {
+    Ok(String),
+    Error(String),
+}
+
+impl Response {
+    fn parse(line: &str) -> Self {
+        let line = line.trim();
+        let (status, message) = line.split_once(' ').unwrap_or((line, ""));
+
+        match status {
+            "ok" => Self::Ok(message.to_string()),
+            "error" => Self::Error(message.to_string()),
+            _ => Self::Error(format!("malformed response: {line}")),
+        }
+    }
+}
+
+impl fmt::Display for Response {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Ok(message) if message.is_empty() => f.write_str("ok"),
+            Self::Ok(message) => write!(f, "ok {message}"),
+            Self::Error(message) => write!(f, "error {message}"),
+        }
+    }
+}
+
+/// A request received from a client, waiting for the session to answer it.
+pub struct Incoming {
+    request: Request,
+    responder: oneshot::Sender<Response>,
+}
+
+impl Incoming {
+    pub fn request(&self) -> Request {
+        self.request
+    }
+
+    pub fn respond(self, response: Response) {
+        let _ = self.responder.send(response);
+    }
+}
+
+pub struct ControlServer {
+    path: PathBuf,
+    sender: mpsc::Sender<Incoming>,
+    requests: mpsc::Receiver<Incoming>,
+    accept_task: JoinHandle<()>,
+}
+
+impl ControlServer {
+    pub async fn bind() -> Result<Self> {
+        let path = socket_path()?;
+
+        if fs::metadata(&path).await.is_ok() {
+            if UnixStream::connect(&path).await.is_ok() {
+                return Err(Error::Control(format!(
+                    "Another recorder is already listening on {}",
+                    path.display()
+                )));
+            }
+
+            fs::remove_file(&path).await?;
+        }
+
+        let listener = UnixListener::bind(&path)?;
+        let (sender, requests) = mpsc::channel(8);
+        let accept_task =
+            tokio::spawn(Self::accept_loop(listener, sender.clone()));
+
+        info!("Listening for control commands on {}", path.display());
+
+        Ok(Self {
+            path,
+            sender,
+            requests,
+            accept_task,
+        })
+    }
+
+    pub async fn recv(&mut self) -> Option<Incoming> {
+        self.requests.recv().await
+    }
+
+    /// A handle for feeding requests from other frontends, such as D-Bus,
+    /// into the same queue as the socket.
+    pub fn sender(&self) -> mpsc::Sender<Incoming> {
+        self.sender.clone()
+    }
+
+    async fn accept_loop(
+        listener: UnixListener,
+        sender: mpsc::Sender<Incoming>,
+    ) {
+        loop {
+            match listener.accept().await {
+                Ok((stream, _)) => {
+                    let sender = sender.clone();
+                    tokio::spawn(async move {
+                        if let Err(e) = Self::serve(stream, sender).await {
+                            warn!("Control connection error: {}", e);
+                        }
+                    });
+                }
+                Err(e) => {
+                    warn!("Failed to accept control connection: {}", e);
+                }
+            }
+        }
+    }
+
+    async fn serve(
+        stream: UnixStream,
+        sender: mpsc::Sender<Incoming>,
+    ) -> Result<()> {
+        let (reader, mut writer) = stream.into_split();
+        let mut lines = BufReader::new(reader).lines();
+
+        while let Some(line) = lines.next_line().await? {
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let response = match line.parse::<Request>() {
+                Ok(request) => dispatch(&sender, request).await,
+                Err(message) => Response::Error(message),
+            };
+
+            writer.write_all(format!("{response}\n").as_bytes()).await?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for ControlServer {
+    fn drop(&mut self) {
+        self.accept_task.abort();
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// Answers control requests while a session runs, forwarding the ones that
+/// drive it to `actions` and those from the controls bindings to `arming`.
+pub async fn serve(
+    control: &mut ControlServer,
+    mut events: broadcast::Receiver<Event>,
+    actions: mpsc::Sender<Action>,
+    arming: mpsc::Sender<Arming>,
+) {
+    let mut status = Status::default();
+
+    loop {
+        tokio::select! {
+            event = session::next_event(&mut events) => {
+                let Some(event) = event else {
+                    break;
+                };
+                status.apply(&event);
+                if event.ends_session() {
+                    break;
+                }
+            }
+
+            Some(incoming) = control.recv() => {
+                let request = incoming.request();
+                debug!("Control request: {request}");
+                let recording =
+                    matches!(status.state, State::Recording | State::Paused);
+
+                if let Some(change) = request.arming() {
+                    let response = if recording
+                        && arming.send(change).await.is_ok()
+                    {
+                        Response::Ok(String::new())
+                    } else {
+                        Response::Error("no recording in progress".into())
+                    };
+                    incoming.respond(response);
+                    continue;
+                }
+
+                let response = match request.action() {
+                    Some(action) if recording => {
+                        match actions.send(action).await {
+                            Ok(()) => Response::Ok(String::new()),
+                            Err(_) => Response::Error(
+                                "no recording in progress".into(),
+                            ),
+                        }
+                    }
+                    Some(_) => {
+                        Response::Error("no recording in progress".into())
+                    }
+                    None if request == Request::Start => Response::Error(
+                        "a recording is already in progress".into(),
+                    ),
+                    None => Response::Ok(format!(
+                        "{} {:.1}s",
+                        dbus::State::from(status.state),
+                        status.elapsed.as_secs_f64()
+                    )),
+                };
+
+                incoming.respond(response);
+            }
+        }
+    }
+}
+
+/// Queues `request` for the session and waits for its answer.
+pub async fn dispatch(
+    sender: &mpsc::Sender<Incoming>,
+    request: Request,
+) -> Response {
+    let (responder, receiver) = oneshot::channel();
+
+    if sender.send(Incoming { request, responder }).await.is_err() {
+        return Response::Error("recorder is shutting down".into());
+    }
+
+    receiver
+        .await
+        .unwrap_or_else(|_| Response::Error("recorder is shutting down".into()))
+}
+
+/// Sends a single request to the running recorder and waits for its reply.
+pub async fn send(request: Request) -> Result<Response> {
+    let path = socket_path()?;
+
+    let stream = UnixStream::connect(&path).await.map_err(|e| {
+        Error::Control(format!(
+            "No recorder is listening on {}: {e}",
+            path.display()
+        ))
+    })?;
+
+    let (reader, mut writer) = stream.into_split();
+    writer.write_all(format!("{request}\n").as_bytes()).await?;
+    writer.shutdown().await?;
+
+    let mut line = String::new();
+    BufReader::new(reader).read_line(&mut line).await?;
+
+    if line.is_empty() {
+        return Err(Error::Control(
+            "Recorder closed the connection without replying".into(),
+        ));
+    }
+
+    Ok(Response::parse(&line))
+}
+
+pub fn socket_path() -> Result<PathBuf> {
+    let runtime_dir = env::var("XDG_RUNTIME_DIR")
+        .map_err(|_| Error::Control("XDG_RUNTIME_DIR is not set".into()))?;
+
+    Ok(PathBuf::from(runtime_dir).join(SOCKET_NAME))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn requests_round_trip() {
+        let requests = [
+            Request::Start,
+            Request::Toggle,
+            Request::Save,
+            Request::Cancel,
+            Request::Pause,
+            Request::Marker,
+            Request::Status,
+            Request::Arm,
+            Request::Disarm,
+        ];
+
+        for request in requests {
+            assert_eq!(request.to_string().parse(), Ok(request));
+        }
+    }
+
+    #[test]
+    fn requests_ignore_case_and_whitespace() {
+        assert_eq!(" SAVE\n".parse(), Ok(Request::Save));
+        assert_eq!("Marker".parse(), Ok(Request::Marker));
+    }
+
+    #[test]
+    fn unknown_request_is_rejected() {
+        assert_eq!(
+            "record".parse::<Request>(),
+            Err("unknown command: record".to_string())
+        );
+    }
+
+    #[test]
+    fn requests_map_to_actions() {
+        assert_eq!(Request::Toggle.action(), Some(Action::Save));
+        assert_eq!(Request::Cancel.action(), Some(Action::Cancel));
+        assert_eq!(Request::Status.action(), None);
+        assert_eq!(Request::Arm.action(), None);
+        assert_eq!(Request::Arm.arming(), Some(Arming::Arm));
+        assert_eq!(Request::Disarm.arming(), Some(Arming::Disarm));
+        assert_eq!(Request::Save.arming(), None);
+    }
+
+    #[test]
+    fn responses_round_trip() {
+        let responses = [
+            Response::Ok(String::new()),
+            Response::Ok("recording 1.5s".into()),
+            Response::Error("no recording in progress".into()),
+        ];
+
+        for response in responses {
+            assert_eq!(Response::parse(&format!("{response}\n")), response);
+        }
+    }
+
+    #[test]
+    fn malformed_response_is_an_error() {
+        assert_eq!(
+            Response::parse("maybe later"),
+            Response::Error("malformed response: maybe later".into())
+        );
+    }
+}
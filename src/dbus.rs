@@ -0,0 +1,357 @@
+use std::{fmt, path::Path, time::Duration};
+
+use tokio::sync::{broadcast, mpsc, watch};
+use tracing::{info, warn};
+use zbus::{
+    connection,
+    fdo,
+    interface,
+    object_server::SignalEmitter,
+    Connection,
+};
+
+use crate::{
+    control::{self, Incoming, Request, Response},
+    session::{self, Event, Status},
+    Result,
+};
+
+pub const BUS_NAME: &str = "org.hyprrecorder.Recorder1";
+pub const OBJECT_PATH: &str = "/org/hyprrecorder/Recorder1";
+
+/// What the session is doing, as exposed through `State` and `ctl status`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum State {
+    Idle,
+    Recording,
+    Paused,
+    Stopping,
+}
+
+impl fmt::Display for State {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Idle => "idle",
+            Self::Recording => "recording",
+            Self::Paused => "paused",
+            Self::Stopping => "stopping",
+        };
+
+        f.write_str(name)
+    }
+}
+
+impl From<session::State> for State {
+    fn from(state: session::State) -> Self {
+        match state {
+            session::State::Starting | session::State::Recording => {
+                Self::Recording
+            }
+            session::State::Paused => Self::Paused,
+            session::State::Stopping | session::State::Transcribing => {
+                Self::Stopping
+            }
+            session::State::Idle
+            | session::State::Done
+            | session::State::Failed => Self::Idle,
+        }
+    }
+}
+
+/// The `org.hyprrecorder.Recorder1` interface.
+///
+/// Method calls are queued as control requests, so they behave exactly like
+/// the matching `ctl` commands.
+struct Recorder1 {
+    requests: mpsc::Sender<Incoming>,
+    status: watch::Receiver<(State, Duration)>,
+}
+
+impl Recorder1 {
+    async fn call(&self, request: Request) -> fdo::Result<()> {
+        match control::dispatch(&self.requests, request).await {
+            Response::Ok(_) => Ok(()),
+            Response::Error(message) => Err(fdo::Error::Failed(message)),
+        }
+    }
+}
+
+#[interface(name = "org.hyprrecorder.Recorder1")]
+impl Recorder1 {
+    async fn start(&self) -> fdo::Result<()> {
+        self.call(Request::Start).await
+    }
+
+    async fn stop(&self) -> fdo::Result<()> {
+        self.call(Request::Save).await
+    }
+
+    async fn cancel(&self) -> fdo::Result<()> {
+        self.call(Request::Cancel).await
+    }
+
+    async fn pause(&self) -> fdo::Result<()> {
+        self.call(Request::Pause).await
+    }
+
+    async fn add_marker(&self) -> fdo::Result<()> {
+        self.call(Request::Marker).await
+    }
+
+    #[zbus(property)]
+    fn state(&self) -> String {
+        self.status.borrow().0.to_string()
+    }
+
+    /// Seconds recorded so far, excluding pauses. Changes continuously, so
+    /// no change signal is sent; poll it instead.
+    #[zbus(property(emits_changed_signal = "false"))]
+    fn elapsed(&self) -> f64 {
+        self.status.borrow().1.as_secs_f64()
+    }
+
+    #[zbus(signal)]
+    async fn recording_saved(
+        emitter: &SignalEmitter<'_>,
+        path: &str,
+    ) -> zbus::Result<()>;
+
+    #[zbus(signal)]
+    async fn transcript_ready(
+        emitter: &SignalEmitter<'_>,
+        recording: &str,
+        transcript: &str,
+    ) -> zbus::Result<()>;
+}
+
+/// The recorder's presence on the session bus.
+pub struct DbusService {
+    connection: Connection,
+    status: watch::Sender<(State, Duration)>,
+}
+
+impl DbusService {
+    /// Claims [`BUS_NAME`] and serves the interface at [`OBJECT_PATH`].
+    pub async fn start(
+        requests: mpsc::Sender<Incoming>,
+        initial: State,
+    ) -> Result<Self> {
+        Self::serve(connection::Builder::session()?, requests, initial).await
+    }
+
+    /// Like [`DbusService::start`], on the bus `builder` connects to.
+    async fn serve(
+        builder: connection::Builder<'_>,
+        requests: mpsc::Sender<Incoming>,
+        initial: State,
+    ) -> Result<Self> {
+        let (status, receiver) = watch::channel((initial, Duration::ZERO));
+
+        let connection = builder
+            .name(BUS_NAME)?
+            .serve_at(
+                OBJECT_PATH,
+                Recorder1 {
+                    requests,
+                    status: receiver,
+                },
+            )?
+            .build()
+            .await?;
+
+        info!("Serving {BUS_NAME} on the session bus");
+
+        Ok(Self { connection, status })
+    }
+
+    /// Publishes the session's progress, signalling `State` changes.
+    pub async fn update(&self, state: State, elapsed: Duration) -> Result<()> {
+        let previous = self.status.send_replace((state, elapsed)).0;
+
+        if previous != state {
+            let interface = self
+                .connection
+                .object_server()
+                .interface::<_, Recorder1>(OBJECT_PATH)
+                .await?;
+            interface
+                .get()
+                .await
+                .state_changed(interface.signal_emitter())
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Publishes a session's state and signals until it ends, leaving the
+    /// state idle.
+    pub async fn follow(&self, mut events: broadcast::Receiver<Event>) {
+        let mut status = Status::default();
+
+        while let Some(event) = session::next_event(&mut events).await {
+            status.apply(&event);
+
+            let signalled = match &event {
+                Event::Saved(metadata) => {
+                    self.recording_saved(&metadata.recording).await
+                }
+                Event::Transcribed(metadata) => match &metadata.transcript {
+                    Some(transcript) => {
+                        self.transcript_ready(&metadata.recording, transcript)
+                            .await
+                    }
+                    None => Ok(()),
+                },
+                _ => Ok(()),
+            };
+            if let Err(e) = signalled {
+                warn!("Failed to emit D-Bus signal: {}", e);
+            }
+
+            let elapsed = if status.state.is_active() {
+                status.elapsed
+            } else {
+                Duration::ZERO
+            };
+            if let Err(e) = self.update(status.state.into(), elapsed).await {
+                warn!("Failed to publish D-Bus state: {}", e);
+            }
+
+            if event.ends_session() {
+                break;
+            }
+        }
+    }
+
+    pub async fn recording_saved(&self, path: &Path) -> Result<()> {
+        let emitter = SignalEmitter::new(&self.connection, OBJECT_PATH)?;
+        Recorder1::recording_saved(&emitter, &path.to_string_lossy()).await?;
+        Ok(())
+    }
+
+    pub async fn transcript_ready(
+        &self,
+        recording: &Path,
+        transcript: &Path,
+    ) -> Result<()> {
+        let emitter = SignalEmitter::new(&self.connection, OBJECT_PATH)?;
+        Recorder1::transcript_ready(
+            &emitter,
+            &recording.to_string_lossy(),
+            &transcript.to_string_lossy(),
+        )
+        .await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{BufRead, BufReader},
+        process::{Child, Command, Stdio},
+    };
+
+    use zbus::proxy::{self, CacheProperties};
+
+    use super::*;
+
+    /// A `dbus-daemon` of our own, killed on drop.
+    struct PrivateBus {
+        daemon: Child,
+        address: String,
+    }
+
+    impl PrivateBus {
+        fn start() -> Self {
+            let mut daemon = Command::new("dbus-daemon")
+                .args(["--session", "--print-address", "--nofork"])
+                .stdout(Stdio::piped())
+                .stderr(Stdio::null())
+                .spawn()
+                .expect("dbus-daemon should be installed");
+
+            let mut address = String::new();
+            let stdout = daemon.stdout.take().unwrap();
+            BufReader::new(stdout).read_line(&mut address).unwrap();
+
+            Self {
+                daemon,
+                address: address.trim().to_string(),
+            }
+        }
+
+        fn connect(&self) -> connection::Builder<'_> {
+            connection::Builder::address(self.address.as_str()).unwrap()
+        }
+    }
+
+    impl Drop for PrivateBus {
+        fn drop(&mut self) {
+            let _ = self.daemon.kill();
+            let _ = self.daemon.wait();
+        }
+    }
+
+    #[tokio::test]
+    #[ignore = "needs dbus-daemon"]
+    async fn methods_and_state_over_a_private_bus() {
+        let bus = PrivateBus::start();
+
+        // Stands in for the session: accepts everything but `Cancel`.
+        let (requests, mut incoming) = mpsc::channel::<Incoming>(8);
+        let (seen, mut received) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            while let Some(incoming) = incoming.recv().await {
+                let request = incoming.request();
+                let _ = seen.send(request);
+                incoming.respond(match request {
+                    Request::Cancel => {
+                        Response::Error("no recording in progress".into())
+                    }
+                    _ => Response::Ok(String::new()),
+                });
+            }
+        });
+
+        let service = DbusService::serve(bus.connect(), requests, State::Idle)
+            .await
+            .unwrap();
+        let client = bus.connect().build().await.unwrap();
+        let recorder: zbus::Proxy = proxy::Builder::new(&client)
+            .destination(BUS_NAME)
+            .unwrap()
+            .path(OBJECT_PATH)
+            .unwrap()
+            .interface(BUS_NAME)
+            .unwrap()
+            .cache_properties(CacheProperties::No)
+            .build()
+            .await
+            .unwrap();
+
+        let state: String = recorder.get_property("State").await.unwrap();
+        assert_eq!(state, "idle");
+
+        let () = recorder.call("Start", &()).await.unwrap();
+        service
+            .update(State::Recording, Duration::from_millis(1500))
+            .await
+            .unwrap();
+        let state: String = recorder.get_property("State").await.unwrap();
+        let elapsed: f64 = recorder.get_property("Elapsed").await.unwrap();
+        assert_eq!(state, "recording");
+        assert_eq!(elapsed, 1.5);
+
+        let () = recorder.call("Stop", &()).await.unwrap();
+        let error = recorder.call::<_, _, ()>("Cancel", &()).await.unwrap_err();
+        assert!(error.to_string().contains("no recording in progress"));
+
+        let mut requests = Vec::new();
+        while let Ok(request) = received.try_recv() {
+            requests.push(request);
+        }
+        assert_eq!(requests, [Request::Start, Request::Save, Request::Cancel]);
+    }
+}
@@ -0,0 +1,351 @@
+use std::{
+    env,
+    fs,
+    os::unix::fs::PermissionsExt,
+    path::{Path, PathBuf},
+    process,
+};
+
+#[cfg(feature = "portal")]
+use ashpd::desktop::screencast::{Screencast, SourceType};
+#[cfg(feature = "cpal")]
+use cpal::traits::{DeviceTrait, HostTrait};
+use gstreamer::{self as gst, prelude::*};
+
+use crate::{
+    capture::{self, Source},
+    naming,
+    pipeline::{self, ELEMENTS},
+    transcriber::Transcriber,
+    utils,
+    Error,
+    Result,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Status {
+    Ok,
+    Warn,
+    Fail,
+}
+
+/// Prints the outcome of each check as it runs and counts the failures.
+#[derive(Default)]
+struct Report {
+    failures: usize,
+    warnings: usize,
+}
+
+impl Report {
+    fn ok(&mut self, check: &str, detail: &str) {
+        self.print(Status::Ok, check, detail, None);
+    }
+
+    fn warn(&mut self, check: &str, detail: &str, fix: &str) {
+        self.print(Status::Warn, check, detail, Some(fix));
+    }
+
+    fn fail(&mut self, check: &str, detail: &str, fix: &str) {
+        self.print(Status::Fail, check, detail, Some(fix));
+    }
+
+    fn print(
+        &mut self,
+        status: Status,
+        check: &str,
+        detail: &str,
+        fix: Option<&str>,
+    ) {
+        let label = match status {
+            Status::Ok => " ok ",
+            Status::Warn => "warn",
+            Status::Fail => "FAIL",
+        };
+        match status {
+            Status::Ok => (),
+            Status::Warn => self.warnings += 1,
+            Status::Fail => self.failures += 1,
+        }
+
+        println!("[{label}] {check}: {detail}");
+        if let Some(fix) = fix {
+            println!("       fix: {fix}");
+        }
+    }
+}
+
+/// Checks everything a recording session depends on, printing a fix for
+/// each problem. Fails with [`Error::HealthCheck`] if a session could not
+/// work as configured.
+pub async fn run() -> Result<()> {
+    let mut report = Report::default();
+
+    check_elements(&mut report);
+    check_compositor(&mut report);
+    check_binaries(&mut report);
+    #[cfg(feature = "portal")]
+    let portal_audio = check_portal(&mut report).await;
+    #[cfg(not(feature = "portal"))]
+    let portal_audio = false;
+    #[cfg(feature = "cpal")]
+    check_input_devices(&mut report, portal_audio);
+    #[cfg(not(feature = "cpal"))]
+    let _ = portal_audio;
+    check_writable(
+        &mut report,
+        "Output directory",
+        &naming::recordings_dir(),
+        "set HYPR_RECORDER_OUTPUT_DIR to a directory you can write to",
+    );
+    check_writable(
+        &mut report,
+        "State directory",
+        &utils::state_dir(),
+        "set XDG_STATE_HOME to a directory you can write to",
+    );
+
+    println!(
+        "\n{} failure(s), {} warning(s)",
+        report.failures, report.warnings
+    );
+
+    if report.failures > 0 {
+        return Err(Error::HealthCheck(report.failures));
+    }
+
+    Ok(())
+}
+
+fn check_elements(report: &mut Report) {
+    if let Err(e) = gst::init() {
+        report.fail(
+            "GStreamer",
+            &format!("failed to initialise: {e}"),
+            "install the GStreamer runtime (gstreamer)",
+        );
+        return;
+    }
+
+    // The default source is what `record` and `session` use unless told
+    // otherwise.
+    let source = capture::open(Source::default(), false);
+    let mut complete = true;
+    for (element, package) in source.elements().iter().chain(ELEMENTS) {
+        let check = format!("GStreamer element {element}");
+
+        match gst::ElementFactory::find(element) {
+            Some(factory) => {
+                let plugin = factory
+                    .plugin_name()
+                    .map(|name| name.to_string())
+                    .unwrap_or_else(|| "unknown plugin".into());
+                report.ok(&check, &format!("found in {plugin}"));
+            }
+            None => {
+                complete = false;
+                report.fail(
+                    &check,
+                    "missing",
+                    &format!(
+                        "install {package}, then clear the registry cache \
+                         with `rm -r ~/.cache/gstreamer-1.0`"
+                    ),
+                );
+            }
+        }
+    }
+
+    if !complete {
+        return;
+    }
+
+    if let Some(element) = pipeline::missing_test_element() {
+        report.warn(
+            "Pipeline dry run",
+            &format!("skipped, {element} is not installed"),
+            "install gst-plugins-base to check that recordings stop cleanly",
+        );
+        return;
+    }
+
+    match pipeline::dry_run() {
+        Ok(()) => report.ok(
+            "Pipeline dry run",
+            "test recording stopped cleanly and verified",
+        ),
+        Err(e) => report.fail(
+            "Pipeline dry run",
+            &e.to_string(),
+            "check that the GStreamer plugins are of matching versions, \
+             e.g. after a partial upgrade",
+        ),
+    }
+}
+
+fn check_compositor(report: &mut Report) {
+    if env::var("HYPRLAND_INSTANCE_SIGNATURE").is_ok() {
+        report.ok("Compositor", "Hyprland");
+
+        match find_program("hyprctl") {
+            Some(path) => report.ok("hyprctl", &path.display().to_string()),
+            None => report.warn(
+                "hyprctl",
+                "not on PATH",
+                "install the Hyprland command line tools so bindings can be inspected",
+            ),
+        }
+    } else if env::var("SWAYSOCK").is_ok() {
+        report.ok("Compositor", "sway");
+    } else {
+        report.warn(
+            "Compositor",
+            "neither Hyprland nor sway detected",
+            "add yourself to the `input` group so the libinput key listener can run",
+        );
+    }
+}
+
+fn check_binaries(report: &mut Report) {
+    match find_program("swayosd-client") {
+        Some(path) => report.ok("swayosd-client", &path.display().to_string()),
+        None => report.fail(
+            "swayosd-client",
+            "not on PATH; sessions cannot show their progress",
+            "install swayosd and start swayosd-server",
+        ),
+    }
+
+    let whisper = Transcriber::new().command().to_string();
+    let check = format!("Transcriber {whisper}");
+    match (
+        find_program(&whisper),
+        env::var("HYPR_RECORDER_TRANSCRIBE").is_ok(),
+    ) {
+        (Some(path), _) => report.ok(&check, &path.display().to_string()),
+        (None, true) => report.fail(
+            &check,
+            "not on PATH but HYPR_RECORDER_TRANSCRIBE is set",
+            "install openai-whisper or point WHISPER_COMMAND at your whisper",
+        ),
+        (None, false) => report.warn(
+            &check,
+            "not on PATH; transcription is disabled anyway",
+            "install openai-whisper before setting HYPR_RECORDER_TRANSCRIBE",
+        ),
+    }
+
+    if env::var_os("HYPR_RECORDER_S3_ENDPOINT").is_some()
+        && find_program("wl-copy").is_none()
+    {
+        report.warn(
+            "wl-copy",
+            "not on PATH; upload links will not reach the clipboard",
+            "install wl-clipboard",
+        );
+    }
+}
+
+/// Returns whether the portal can capture audio itself.
+#[cfg(feature = "portal")]
+async fn check_portal(report: &mut Report) -> bool {
+    const FIX: &str = "install xdg-desktop-portal and a backend for your \
+                       compositor (xdg-desktop-portal-hyprland or \
+                       xdg-desktop-portal-wlr), then restart it";
+
+    let types = match Screencast::new().await {
+        Ok(screencast) => screencast.available_source_types().await,
+        Err(e) => Err(e),
+    };
+    let types = match types {
+        Ok(types) => types,
+        Err(e) => {
+            report.fail("ScreenCast portal", &format!("unavailable: {e}"), FIX);
+            return false;
+        }
+    };
+
+    let names: Vec<_> = [
+        (SourceType::Monitor, "monitor"),
+        (SourceType::Window, "window"),
+        (SourceType::Virtual, "virtual"),
+    ]
+    .into_iter()
+    .filter(|(kind, _)| types.contains(*kind))
+    .map(|(_, name)| name)
+    .collect();
+    let detail = format!("sources: {}", names.join(", "));
+
+    if !types.contains(SourceType::Monitor)
+        && !types.contains(SourceType::Window)
+    {
+        report.fail("ScreenCast portal", &detail, FIX);
+    } else {
+        report.ok("ScreenCast portal", &detail);
+    }
+
+    types.contains(SourceType::Virtual)
+}
+
+/// The microphone is only needed when the portal cannot capture audio.
+#[cfg(feature = "cpal")]
+fn check_input_devices(report: &mut Report, portal_audio: bool) {
+    let host = cpal::default_host();
+    let count = host
+        .input_devices()
+        .map(|devices| devices.count())
+        .unwrap_or_default();
+    let default = host
+        .default_input_device()
+        .and_then(|device| device.name().ok());
+
+    match default {
+        Some(name) => report.ok(
+            "Audio input",
+            &format!("{name} (default of {count} device(s))"),
+        ),
+        None if portal_audio => report.warn(
+            "Audio input",
+            "no default input device; the portal audio is used instead",
+            "connect a microphone or set a default source in PipeWire",
+        ),
+        None => report.fail(
+            "Audio input",
+            "no default input device and no portal audio to fall back on",
+            "connect a microphone or set a default source in PipeWire, e.g. \
+             with `wpctl set-default`",
+        ),
+    }
+}
+
+fn check_writable(report: &mut Report, check: &str, dir: &Path, fix: &str) {
+    let probe = dir.join(format!(".hypr-recorder-doctor-{}", process::id()));
+    let result = fs::create_dir_all(dir)
+        .and_then(|()| fs::write(&probe, b""))
+        .and_then(|()| fs::remove_file(&probe));
+
+    match result {
+        Ok(()) => report.ok(check, &dir.display().to_string()),
+        Err(e) => report.fail(check, &format!("{}: {e}", dir.display()), fix),
+    }
+}
+
+/// Resolves `program` the way a shell would, through `PATH` unless it
+/// contains a `/`.
+fn find_program(program: &str) -> Option<PathBuf> {
+    let is_executable = |path: &Path| {
+        fs::metadata(path)
+            .map(|metadata| {
+                metadata.is_file() && metadata.permissions().mode() & 0o111 != 0
+            })
+            .unwrap_or(false)
+    };
+
+    if program.contains('/') {
+        let path = PathBuf::from(program);
+        return is_executable(&path).then_some(path);
+    }
+
+    env::split_paths(&env::var_os("PATH")?)
+        .map(|dir| dir.join(program))
+        .find(|path| is_executable(path))
+}
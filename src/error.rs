@@ -2,32 +2,101 @@
 pub enum Error {
     #[error("Hyprland is required but not running")]
     HyprlandNotRunning,
+    #[error("Hyprland socket {path} failed: {source}")]
+    HyprlandSocket {
+        path: std::path::PathBuf,
+        source: std::io::Error,
+    },
+    #[error("Hyprland rejected `{command}`: {reply}")]
+    HyprlandCommand { command: String, reply: String },
+    #[error("Malformed Hyprland event: {0}")]
+    HyprlandEvent(String),
+    #[error("Sway is required but not running")]
+    SwayNotRunning,
+    #[error("Sway socket {path} failed: {source}")]
+    SwaySocket {
+        path: std::path::PathBuf,
+        source: std::io::Error,
+    },
+    #[error("Sway rejected `{command}`: {error}")]
+    SwayCommand { command: String, error: String },
+    #[error("Unexpected sway reply: {0}")]
+    SwayReply(String),
+    #[error("No supported compositor detected (Hyprland or sway)")]
+    NoCompositor,
     #[error("I/O error: {0}")]
     Io(#[from] std::io::Error),
+    #[error("Invalid keybinding: {0}")]
+    InvalidBinding(String),
+    #[error("JSON error: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("libinput error: {0}")]
+    Libinput(String),
     #[error("Missing input device: {0}")]
     MissingInputDevice(String),
+    #[cfg(feature = "cpal")]
     #[error("CPAL device name error: {0}")]
     CpalDeviceName(#[from] cpal::DeviceNameError),
+    #[cfg(feature = "cpal")]
     #[error("CPAL default config error: {0}")]
     CpalDefaultConfig(#[from] cpal::DefaultStreamConfigError),
+    #[cfg(feature = "cpal")]
     #[error("CPAL build stream error: {0}")]
     CpalBuildStream(#[from] cpal::BuildStreamError),
+    #[cfg(feature = "cpal")]
     #[error("CPAL stream playback error: {0}")]
     CpalPlayStream(#[from] cpal::PlayStreamError),
+    #[cfg(feature = "cpal")]
     #[error("Audio encoding error: {0}")]
     AudioEncoding(#[from] hound::Error),
     #[error("Notification error: {0}")]
     Notification(String),
+    #[cfg(feature = "portal")]
     #[error("Portal error: {0}")]
     Portal(#[from] ashpd::Error),
     #[error("GStreamer error: {0}")]
     Gstreamer(#[from] gstreamer::glib::Error),
     #[error("GStreamer state change error: {0}")]
     GstreamerState(#[from] gstreamer::StateChangeError),
+    #[error("Missing GStreamer elements: {}", .0.join(", "))]
+    MissingElements(Vec<String>),
+    #[error("Pipeline preflight failed: {0}")]
+    Preflight(String),
+    #[error("Capture source {element} failed: {message}")]
+    CaptureSource { element: String, message: String },
+    #[error("Pipeline error in {element}: {message}")]
+    Pipeline { element: String, message: String },
+    #[error("Pipeline warning from {element}: {message}")]
+    PipelineWarning { element: String, message: String },
+    #[error("Recording did not verify: {0}")]
+    Verification(String),
+    #[error("Failed to write chapters: {0}")]
+    Chapters(String),
     #[error("Screen capture error: {0}")]
     ScreenCapture(String),
     #[error("System time error: {0}")]
     SystemTime(#[from] std::time::SystemTimeError),
+    #[error("Another recording session is already running")]
+    AlreadyRunning,
+    #[error("Control socket error: {0}")]
+    Control(String),
+    #[cfg(feature = "cli")]
+    #[error("D-Bus error: {0}")]
+    Dbus(#[from] zbus::Error),
+    #[error("No recording with id `{0}`")]
+    RecordingNotFound(String),
+    #[cfg(feature = "cli")]
+    #[error("Search index error: {0}")]
+    Search(#[from] rusqlite::Error),
+    #[error("Player error: {0}")]
+    Player(String),
+    #[error("Upload error: {0}")]
+    Upload(String),
+    #[cfg(feature = "cli")]
+    #[error("HTTP error: {0}")]
+    Http(#[from] reqwest::Error),
+    #[error("{0} health check(s) failed")]
+    HealthCheck(usize),
     #[error("Transcription error: {0}")]
     Transcription(String),
 }
@@ -0,0 +1,160 @@
+use std::{env, time::Duration};
+
+use tokio::{process::Command, sync::broadcast, time::timeout};
+use tracing::{info, warn};
+
+use crate::{
+    metadata::{self, Metadata},
+    notification::Notification,
+    session,
+    utils::run_command_with_input,
+};
+
+/// How long a hook may run unless `HYPR_RECORDER_HOOK_TIMEOUT` says
+/// otherwise.
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// When a hook runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event {
+    /// The recording was written.
+    Saved,
+    /// Whisper finished the transcript.
+    Transcribed,
+    /// The recording was discarded on request.
+    Cancelled,
+    /// The session failed; the recording may be missing or incomplete.
+    Failed,
+}
+
+impl Event {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Saved => "saved",
+            Self::Transcribed => "transcribed",
+            Self::Cancelled => "cancelled",
+            Self::Failed => "failed",
+        }
+    }
+
+    /// Cancellation and failure share a hook; `HYPR_RECORDER_EVENT` tells
+    /// them apart.
+    fn variable(self) -> &'static str {
+        match self {
+            Self::Saved => "HYPR_RECORDER_HOOK_SAVED",
+            Self::Transcribed => "HYPR_RECORDER_HOOK_TRANSCRIBED",
+            Self::Cancelled | Self::Failed => "HYPR_RECORDER_HOOK_FAILED",
+        }
+    }
+}
+
+/// Runs the hooks for a session's outcome until it ends.
+pub async fn follow(mut events: broadcast::Receiver<session::Event>) {
+    while let Some(event) = session::next_event(&mut events).await {
+        match &event {
+            session::Event::Saved(metadata) => {
+                run(Event::Saved, metadata, None).await
+            }
+            session::Event::Transcribed(metadata) => {
+                run(Event::Transcribed, metadata, None).await
+            }
+            session::Event::Cancelled(metadata) => {
+                run(Event::Cancelled, metadata, None).await
+            }
+            session::Event::Failed { message, metadata } => {
+                run(Event::Failed, metadata, Some(message)).await
+            }
+            _ => (),
+        }
+
+        if event.ends_session() {
+            break;
+        }
+    }
+}
+
+/// Runs the hook configured for `event`, if any, through `sh -c`.
+///
+/// The hook gets the metadata JSON on stdin and the essentials in
+/// `HYPR_RECORDER_*` variables. Failures and timeouts are reported, both
+/// on stderr and as a notification, but never abort the session.
+pub async fn run(event: Event, metadata: &Metadata, error: Option<&str>) {
+    let Some(script) = env::var(event.variable())
+        .ok()
+        .filter(|script| !script.trim().is_empty())
+    else {
+        return;
+    };
+
+    info!("Running {} hook", event.name());
+
+    let mut command = Command::new("sh");
+    command
+        .arg("-c")
+        .arg(&script)
+        .env("HYPR_RECORDER_EVENT", event.name())
+        .env(
+            "HYPR_RECORDER_DURATION",
+            format!("{:.3}", metadata.capture.duration_seconds),
+        )
+        .kill_on_drop(true);
+    // Cancelled and failed sessions may not have left a recording behind.
+    if metadata.recording.exists() {
+        command.env("HYPR_RECORDER_RECORDING", &metadata.recording);
+
+        let sidecar = metadata::sidecar_path(&metadata.recording);
+        if sidecar.exists() {
+            command.env("HYPR_RECORDER_METADATA", sidecar);
+        }
+    }
+    if let Some(transcript) = &metadata.transcript {
+        command.env("HYPR_RECORDER_TRANSCRIPT", transcript);
+    }
+    if let Some(error) = error {
+        command.env("HYPR_RECORDER_ERROR", error);
+    }
+
+    let input = match serde_json::to_vec(metadata) {
+        Ok(input) => input,
+        Err(e) => {
+            report(event, &format!("failed to encode metadata: {e}"));
+            return;
+        }
+    };
+
+    let limit = hook_timeout();
+    match timeout(limit, run_command_with_input(command, &input)).await {
+        Ok(Ok(output)) if output.is_success() => (),
+        Ok(Ok(output)) => report(
+            event,
+            &format!(
+                "exited with status {}: {}",
+                output.status,
+                output.stderr.trim()
+            ),
+        ),
+        Ok(Err(e)) => report(event, &format!("could not run: {e}")),
+        Err(_) => {
+            report(event, &format!("timed out after {}s", limit.as_secs_f64()))
+        }
+    }
+}
+
+/// `HYPR_RECORDER_HOOK_TIMEOUT`, in seconds.
+fn hook_timeout() -> Duration {
+    env::var("HYPR_RECORDER_HOOK_TIMEOUT")
+        .ok()
+        .and_then(|secs| secs.trim().parse::<f64>().ok())
+        .filter(|secs| secs.is_finite() && *secs > 0.0)
+        .map(Duration::from_secs_f64)
+        .unwrap_or(DEFAULT_TIMEOUT)
+}
+
+fn report(event: Event, problem: &str) {
+    warn!("The {} hook {}", event.name(), problem);
+
+    let message = format!("Recording {} hook failed", event.name());
+    if let Err(e) = Notification::error(&message) {
+        warn!("Failed to show hook failure: {}", e);
+    }
+}
@@ -0,0 +1,632 @@
+use std::{
+    env,
+    io::{self, Read, Write},
+    os::unix::net::UnixStream as StdUnixStream,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader, Lines},
+    net::UnixStream,
+    time::timeout,
+};
+
+use crate::{Error, Result};
+
+const REQUEST_SOCKET: &str = ".socket.sock";
+const EVENT_SOCKET: &str = ".socket2.sock";
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Client for Hyprland's IPC sockets.
+///
+/// Requests go to `.socket.sock`, one connection per request, and the reply
+/// is read until Hyprland closes the connection. Events are streamed from
+/// `.socket2.sock` as `NAME>>DATA` lines.
+#[derive(Debug, Clone)]
+pub struct HyprlandClient {
+    socket_dir: PathBuf,
+}
+
+impl HyprlandClient {
+    /// Locates the sockets of the Hyprland instance this process runs under.
+    pub fn from_env() -> Result<Self> {
+        let signature = env::var("HYPRLAND_INSTANCE_SIGNATURE")
+            .map_err(|_| Error::HyprlandNotRunning)?;
+
+        // Newer Hyprland releases keep their sockets in the runtime dir, older
+        // ones used /tmp/hypr; prefer the former but accept either.
+        let candidates = env::var("XDG_RUNTIME_DIR")
+            .map(|dir| PathBuf::from(dir).join("hypr").join(&signature))
+            .into_iter()
+            .chain([PathBuf::from("/tmp/hypr").join(&signature)]);
+
+        for dir in candidates {
+            if dir.join(REQUEST_SOCKET).exists() {
+                return Ok(Self::with_socket_dir(dir));
+            }
+        }
+
+        Err(Error::HyprlandNotRunning)
+    }
+
+    /// Uses the sockets in `socket_dir`, e.g. ones served by a fake server.
+    pub fn with_socket_dir(socket_dir: impl Into<PathBuf>) -> Self {
+        Self {
+            socket_dir: socket_dir.into(),
+        }
+    }
+
+    pub fn request_socket(&self) -> PathBuf {
+        self.socket_dir.join(REQUEST_SOCKET)
+    }
+
+    pub fn event_socket(&self) -> PathBuf {
+        self.socket_dir.join(EVENT_SOCKET)
+    }
+
+    /// Sends a raw request and returns Hyprland's reply verbatim.
+    pub async fn request(&self, request: &str) -> Result<String> {
+        let path = self.request_socket();
+
+        timeout(REQUEST_TIMEOUT, async {
+            let mut stream = UnixStream::connect(&path).await?;
+            stream.write_all(request.as_bytes()).await?;
+
+            let mut reply = String::new();
+            stream.read_to_string(&mut reply).await?;
+            Ok(reply)
+        })
+        .await
+        .unwrap_or_else(|_| Err(io::ErrorKind::TimedOut.into()))
+        .map_err(|source| socket_error(&path, source))
+    }
+
+    /// Blocking variant of [`HyprlandClient::request`] for use in `Drop`.
+    pub fn request_blocking(&self, request: &str) -> Result<String> {
+        let path = self.request_socket();
+
+        (|| -> io::Result<String> {
+            let mut stream = StdUnixStream::connect(&path)?;
+            stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+            stream.set_write_timeout(Some(REQUEST_TIMEOUT))?;
+            stream.write_all(request.as_bytes())?;
+
+            let mut reply = String::new();
+            stream.read_to_string(&mut reply)?;
+            Ok(reply)
+        })()
+        .map_err(|source| socket_error(&path, source))
+    }
+
+    /// Runs several `ok`-acknowledged commands in order, batching them into
+    /// as few `[[BATCH]]` requests as their contents allow.
+    pub async fn batch(&self, commands: &[String]) -> Result<()> {
+        for (request, count) in batch_requests(commands) {
+            let reply = self.request(&request).await?;
+            check_batch_reply(&request, &reply, count)?;
+        }
+        Ok(())
+    }
+
+    pub fn batch_blocking(&self, commands: &[String]) -> Result<()> {
+        for (request, count) in batch_requests(commands) {
+            let reply = self.request_blocking(&request)?;
+            check_batch_reply(&request, &reply, count)?;
+        }
+        Ok(())
+    }
+
+    /// Sends a JSON query such as `binds` and returns the raw JSON reply.
+    pub async fn query_json(&self, query: &str) -> Result<String> {
+        self.request(&format!("j/{query}")).await
+    }
+
+    /// All keybindings currently known to Hyprland.
+    pub async fn binds(&self) -> Result<Vec<Bind>> {
+        let reply = self.query_json("binds").await?;
+        Ok(serde_json::from_str(&reply)?)
+    }
+
+    pub fn binds_blocking(&self) -> Result<Vec<Bind>> {
+        let reply = self.request_blocking("j/binds")?;
+        Ok(serde_json::from_str(&reply)?)
+    }
+
+    pub async fn subscribe(&self) -> Result<EventStream> {
+        let path = self.event_socket();
+        let stream = UnixStream::connect(&path)
+            .await
+            .map_err(|source| socket_error(&path, source))?;
+
+        Ok(EventStream {
+            path,
+            lines: BufReader::new(stream).lines(),
+        })
+    }
+}
+
+/// Modifier names accepted by Hyprland, with their `modmask` bits.
+pub const MODIFIERS: [(u32, &str); 8] = [
+    (1 << 0, "SHIFT"),
+    (1 << 1, "CAPS"),
+    (1 << 2, "CTRL"),
+    (1 << 3, "ALT"),
+    (1 << 4, "MOD2"),
+    (1 << 5, "MOD3"),
+    (1 << 6, "SUPER"),
+    (1 << 7, "MOD5"),
+];
+
+/// A keybinding as reported by `hyprctl binds -j`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Bind {
+    #[serde(default)]
+    pub locked: bool,
+    #[serde(default)]
+    pub mouse: bool,
+    #[serde(default)]
+    pub release: bool,
+    #[serde(default)]
+    pub repeat: bool,
+    #[serde(default, rename = "longPress")]
+    pub long_press: bool,
+    #[serde(default)]
+    pub non_consuming: bool,
+    #[serde(default)]
+    pub has_description: bool,
+    pub modmask: u32,
+    #[serde(default)]
+    pub submap: String,
+    #[serde(default)]
+    pub submap_universal: bool,
+    pub key: String,
+    #[serde(default)]
+    pub keycode: u32,
+    #[serde(default)]
+    pub catch_all: bool,
+    #[serde(default)]
+    pub description: String,
+    pub dispatcher: String,
+    #[serde(default)]
+    pub arg: String,
+}
+
+impl Bind {
+    /// The `MODS,key` pair used by `bind` and `unbind`.
+    pub fn combo(&self) -> String {
+        let mods = modmask_names(self.modmask);
+
+        if self.catch_all {
+            format!("{mods},catchall")
+        } else if self.key.is_empty() && self.keycode != 0 {
+            format!("{mods},code:{}", self.keycode)
+        } else {
+            format!("{mods},{}", self.key)
+        }
+    }
+
+    /// The `keyword bind…` command that recreates this binding.
+    pub fn keyword(&self) -> String {
+        let flags = [
+            (self.locked, 'l'),
+            (self.mouse, 'm'),
+            (self.release, 'r'),
+            (self.repeat, 'e'),
+            (self.long_press, 'o'),
+            (self.non_consuming, 'n'),
+            (self.has_description, 'd'),
+            (self.submap_universal, 'u'),
+        ]
+        .iter()
+        .filter(|(set, _)| *set)
+        .map(|(_, flag)| *flag)
+        .collect::<String>();
+
+        let description = if self.has_description {
+            format!("{},", self.description)
+        } else {
+            String::new()
+        };
+
+        format!(
+            "keyword bind{flags} {},{description}{},{}",
+            self.combo(),
+            self.dispatcher,
+            self.arg
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Event {
+    pub name: String,
+    pub data: String,
+}
+
+impl Event {
+    fn parse(line: &str) -> Result<Self> {
+        let (name, data) = line
+            .split_once(">>")
+            .ok_or_else(|| Error::HyprlandEvent(line.to_string()))?;
+
+        Ok(Self {
+            name: name.to_string(),
+            data: data.to_string(),
+        })
+    }
+}
+
+pub struct EventStream {
+    path: PathBuf,
+    lines: Lines<BufReader<UnixStream>>,
+}
+
+impl EventStream {
+    /// Waits for the next event; `None` once Hyprland closes the socket.
+    pub async fn next(&mut self) -> Result<Option<Event>> {
+        let line = self
+            .lines
+            .next_line()
+            .await
+            .map_err(|source| socket_error(&self.path, source))?;
+
+        line.map(|line| Event::parse(&line)).transpose()
+    }
+}
+
+/// Parses a space-separated modifier list such as `SUPER SHIFT`.
+pub fn parse_modmask(mods: &str) -> Option<u32> {
+    mods.split_whitespace().try_fold(0, |mask, name| {
+        let name = match name.to_ascii_uppercase().as_str() {
+            "CONTROL" => "CTRL".to_string(),
+            "WIN" | "LOGO" | "MOD4" => "SUPER".to_string(),
+            "MOD1" => "ALT".to_string(),
+            other => other.to_string(),
+        };
+
+        MODIFIERS
+            .iter()
+            .find(|(_, known)| *known == name)
+            .map(|(bit, _)| mask | bit)
+    })
+}
+
+pub fn modmask_names(modmask: u32) -> String {
+    MODIFIERS
+        .iter()
+        .filter(|(mask, _)| modmask & mask != 0)
+        .map(|(_, name)| *name)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Wraps `commands` so they apply inside `submap` rather than globally.
+pub fn in_submap(submap: &str, commands: Vec<String>) -> Vec<String> {
+    if submap.is_empty() {
+        return commands;
+    }
+
+    let mut wrapped = Vec::with_capacity(commands.len() + 2);
+    wrapped.push(format!("keyword submap {submap}"));
+    wrapped.extend(commands);
+    wrapped.push("keyword submap reset".to_string());
+    wrapped
+}
+
+/// Groups `commands` into requests with the number of replies each expects.
+/// `[[BATCH]]` splits on `;`, so a command containing one, such as a bind
+/// running `exec a; b`, is sent on its own.
+fn batch_requests(commands: &[String]) -> Vec<(String, usize)> {
+    let mut requests = Vec::new();
+    let mut batch: Vec<&str> = Vec::new();
+
+    for command in commands {
+        if !command.contains(';') {
+            batch.push(command);
+            continue;
+        }
+
+        if !batch.is_empty() {
+            requests
+                .push((format!("[[BATCH]]{}", batch.join(";")), batch.len()));
+            batch.clear();
+        }
+        requests.push((command.clone(), 1));
+    }
+
+    if !batch.is_empty() {
+        requests.push((format!("[[BATCH]]{}", batch.join(";")), batch.len()));
+    }
+    requests
+}
+
+fn check_batch_reply(request: &str, reply: &str, count: usize) -> Result<()> {
+    // Batched replies are concatenated, with or without separators depending
+    // on the Hyprland version, so compare with the whitespace removed.
+    let compact: String =
+        reply.chars().filter(|c| !c.is_whitespace()).collect();
+
+    if compact == "ok".repeat(count) {
+        return Ok(());
+    }
+
+    Err(Error::HyprlandCommand {
+        command: request.to_string(),
+        reply: reply.trim().to_string(),
+    })
+}
+
+fn socket_error(path: &Path, source: io::Error) -> Error {
+    Error::HyprlandSocket {
+        path: path.to_path_buf(),
+        source,
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use tempfile::TempDir;
+    use tokio::{io::AsyncWriteExt, net::UnixListener, task::JoinHandle};
+
+    use super::*;
+
+    /// Answers one request per reply on a fake `.socket.sock`, returning the
+    /// requests it received.
+    pub(crate) fn serve(
+        dir: &TempDir,
+        replies: &[&str],
+    ) -> JoinHandle<Vec<String>> {
+        let listener =
+            UnixListener::bind(dir.path().join(REQUEST_SOCKET)).unwrap();
+        let replies: Vec<String> =
+            replies.iter().map(ToString::to_string).collect();
+
+        tokio::spawn(async move {
+            let mut requests = Vec::new();
+            for reply in replies {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut request = vec![0; 4096];
+                let length = stream.read(&mut request).await.unwrap();
+                requests.push(
+                    String::from_utf8_lossy(&request[..length]).into_owned(),
+                );
+                stream.write_all(reply.as_bytes()).await.unwrap();
+            }
+            requests
+        })
+    }
+
+    #[tokio::test]
+    async fn request_returns_the_reply_verbatim() {
+        let dir = TempDir::new().unwrap();
+        let server = serve(&dir, &["{\"address\": \"0x1\"}\n"]);
+        let client = HyprlandClient::with_socket_dir(dir.path());
+
+        let reply = client.query_json("activewindow").await.unwrap();
+
+        assert_eq!(reply, "{\"address\": \"0x1\"}\n");
+        assert_eq!(server.await.unwrap(), ["j/activewindow"]);
+    }
+
+    #[tokio::test]
+    async fn batch_sends_one_request() {
+        let dir = TempDir::new().unwrap();
+        let server = serve(&dir, &["ok\n\nok"]);
+        let client = HyprlandClient::with_socket_dir(dir.path());
+
+        let commands = [
+            "keyword bind SUPER,R,submap,record".to_string(),
+            "keyword unbind SUPER,Q".to_string(),
+        ];
+        client.batch(&commands).await.unwrap();
+
+        assert_eq!(
+            server.await.unwrap(),
+            [
+                "[[BATCH]]keyword bind SUPER,R,submap,record;keyword unbind \
+              SUPER,Q"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn batch_fails_unless_every_command_is_ok() {
+        let dir = TempDir::new().unwrap();
+        let _server = serve(&dir, &["okinvalid dispatcher"]);
+        let client = HyprlandClient::with_socket_dir(dir.path());
+
+        let commands = [
+            "keyword unbind SUPER,R".to_string(),
+            "keyword bind SUPER,R,nope,".to_string(),
+        ];
+        let error = client.batch(&commands).await.unwrap_err();
+
+        assert!(matches!(
+            error,
+            Error::HyprlandCommand { reply, .. } if reply == "okinvalid dispatcher"
+        ));
+    }
+
+    #[tokio::test]
+    async fn binds_are_parsed() {
+        let dir = TempDir::new().unwrap();
+        let _server = serve(
+            &dir,
+            &[r#"[{"locked": false, "mouse": false, "release": false,
+                   "repeat": true, "non_consuming": false,
+                   "has_description": false, "modmask": 64, "submap": "",
+                   "key": "Q", "keycode": 0, "catch_all": false,
+                   "description": "", "dispatcher": "exec",
+                   "arg": "kitty"}]"#],
+        );
+        let client = HyprlandClient::with_socket_dir(dir.path());
+
+        let binds = client.binds().await.unwrap();
+
+        assert_eq!(binds.len(), 1);
+        assert!(binds[0].repeat);
+        assert_eq!(binds[0].combo(), "SUPER,Q");
+        assert_eq!(binds[0].keyword(), "keyword binde SUPER,Q,exec,kitty");
+    }
+
+    #[tokio::test]
+    async fn blocking_requests_reach_the_same_socket() {
+        let dir = TempDir::new().unwrap();
+        let server = serve(&dir, &["ok", "[]"]);
+        let client = HyprlandClient::with_socket_dir(dir.path());
+
+        let binds = tokio::task::spawn_blocking(move || {
+            client.batch_blocking(&["dispatch submap reset".to_string()])?;
+            client.binds_blocking()
+        })
+        .await
+        .unwrap()
+        .unwrap();
+
+        assert!(binds.is_empty());
+        assert_eq!(
+            server.await.unwrap(),
+            ["[[BATCH]]dispatch submap reset", "j/binds"]
+        );
+    }
+
+    #[tokio::test]
+    async fn commands_with_semicolons_are_sent_alone() {
+        let dir = TempDir::new().unwrap();
+        let server = serve(&dir, &["ok", "ok", "okok"]);
+        let client = HyprlandClient::with_socket_dir(dir.path());
+
+        let commands = [
+            "keyword submap reset".to_string(),
+            "keyword bind SUPER,T,exec,notify-send a; notify-send b"
+                .to_string(),
+            "keyword unbind SUPER,R".to_string(),
+            "keyword unbind SUPER,Q".to_string(),
+        ];
+        client.batch(&commands).await.unwrap();
+
+        assert_eq!(
+            server.await.unwrap(),
+            [
+                "[[BATCH]]keyword submap reset",
+                "keyword bind SUPER,T,exec,notify-send a; notify-send b",
+                "[[BATCH]]keyword unbind SUPER,R;keyword unbind SUPER,Q",
+            ]
+        );
+    }
+
+    fn bind(key: &str, dispatcher: &str, arg: &str) -> Bind {
+        Bind {
+            locked: false,
+            mouse: false,
+            release: false,
+            repeat: false,
+            long_press: false,
+            non_consuming: false,
+            has_description: false,
+            modmask: 64,
+            submap: String::new(),
+            submap_universal: false,
+            key: key.to_string(),
+            keycode: 0,
+            catch_all: false,
+            description: String::new(),
+            dispatcher: dispatcher.to_string(),
+            arg: arg.to_string(),
+        }
+    }
+
+    #[test]
+    fn keyword_keeps_every_flag() {
+        let bind = Bind {
+            locked: true,
+            mouse: true,
+            release: true,
+            repeat: true,
+            long_press: true,
+            non_consuming: true,
+            has_description: true,
+            submap_universal: true,
+            description: "Terminal".to_string(),
+            ..bind("T", "exec", "kitty")
+        };
+
+        assert_eq!(
+            bind.keyword(),
+            "keyword bindlmreondu SUPER,T,Terminal,exec,kitty"
+        );
+    }
+
+    #[test]
+    fn long_press_is_read_from_hyprctl() {
+        let bind: Bind = serde_json::from_str(
+            r#"{"longPress": true, "modmask": 0, "key": "F1",
+                "dispatcher": "exec", "arg": "help"}"#,
+        )
+        .unwrap();
+
+        assert_eq!(bind.keyword(), "keyword bindo ,F1,exec,help");
+    }
+
+    #[test]
+    fn combo_spells_keycodes_and_catchall() {
+        let by_code = Bind {
+            keycode: 36,
+            ..bind("", "exec", "kitty")
+        };
+        let catch_all = Bind {
+            catch_all: true,
+            ..bind("", "submap", "reset")
+        };
+
+        assert_eq!(by_code.combo(), "SUPER,code:36");
+        assert_eq!(catch_all.combo(), "SUPER,catchall");
+    }
+
+    #[tokio::test]
+    async fn missing_socket_is_reported() {
+        let dir = TempDir::new().unwrap();
+        let client = HyprlandClient::with_socket_dir(dir.path());
+
+        let error = client.request("version").await.unwrap_err();
+
+        assert!(matches!(
+            error,
+            Error::HyprlandSocket { path, .. } if path == client.request_socket()
+        ));
+    }
+
+    #[tokio::test]
+    async fn events_are_split_into_name_and_data() {
+        let dir = TempDir::new().unwrap();
+        let listener =
+            UnixListener::bind(dir.path().join(EVENT_SOCKET)).unwrap();
+        let server = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            stream
+                .write_all(b"workspace>>2\nconfigreloaded>>\ngarbage\n")
+                .await
+                .unwrap();
+        });
+        let client = HyprlandClient::with_socket_dir(dir.path());
+
+        let mut events = client.subscribe().await.unwrap();
+        server.await.unwrap();
+
+        let event = events.next().await.unwrap().unwrap();
+        assert_eq!(
+            (event.name.as_str(), event.data.as_str()),
+            ("workspace", "2")
+        );
+        let event = events.next().await.unwrap().unwrap();
+        assert_eq!(event.name, "configreloaded");
+        assert!(event.data.is_empty());
+        assert!(matches!(
+            events.next().await,
+            Err(Error::HyprlandEvent(line)) if line == "garbage"
+        ));
+        assert!(events.next().await.unwrap().is_none());
+    }
+}
@@ -1,10 +1,22 @@
-use std::{env, path::PathBuf, time::Duration};
+mod keybind;
+#[cfg(feature = "libinput")]
+mod libinput;
 
-use tempfile::NamedTempFile;
-use tokio::{fs, time::interval};
+use std::{env, fmt, str::FromStr};
 
+use async_trait::async_trait;
+#[cfg(feature = "cli")]
+use clap::ValueEnum;
+use tokio::sync::{broadcast, mpsc};
+use tracing::{error, info, warn};
+
+pub use self::keybind::KeybindInput;
+#[cfg(feature = "libinput")]
+pub use self::libinput::LibinputInput;
 use crate::{
-    utils::{run, run_async},
+    compositor::Compositor,
+    hyprland as hypr,
+    session::{self, Event},
     Error,
     Result,
 };
@@ -13,174 +25,256 @@ use crate::{
 pub enum Action {
     Save,
     Cancel,
+    Pause,
+    Marker,
 }
 
-pub struct Input {
-    temp_file: Option<NamedTempFile>,
-    bindings_registered: bool,
+impl Action {
+    /// The `hypr-recorder ctl` request a binding sends for this action.
+    pub(crate) fn request(&self) -> &'static str {
+        match self {
+            Self::Save => "save",
+            Self::Cancel => "cancel",
+            Self::Pause => "pause",
+            Self::Marker => "marker",
+        }
+    }
 }
 
-impl Input {
-    pub async fn new() -> Result<Self> {
-        let runtime_dir = env::var("XDG_RUNTIME_DIR")
-            .map_err(|_| Error::HyprlandNotRunning)?;
+/// A press of the enter or leave key on compositors that emulate the
+/// controls mode, sent back over the control socket.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Arming {
+    Arm,
+    Disarm,
+}
 
-        let hyprland_instance = env::var("HYPRLAND_INSTANCE_SIGNATURE")
-            .map_err(|_| Error::HyprlandNotRunning)?;
+/// A key plus modifiers, written the way Hyprland configs do: `SUPER ALT, R`.
+/// The modifier list may be empty (`, Return`) or left out (`Return`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyBinding {
+    pub modmask: u32,
+    pub key: String,
+}
 
-        let socket_path = PathBuf::from(runtime_dir)
-            .join("hypr")
-            .join(&hyprland_instance)
-            .join(".socket.sock");
+impl KeyBinding {
+    /// The `MODS,key` pair used by `bind` and `unbind`.
+    pub fn combo(&self) -> String {
+        format!("{},{}", hypr::modmask_names(self.modmask), self.key)
+    }
 
-        println!("Using Hyprland socket: {}", socket_path.display());
+    #[cfg(feature = "hyprland")]
+    pub(crate) fn matches(&self, bind: &hypr::Bind) -> bool {
+        if bind.modmask != self.modmask {
+            return false;
+        }
 
-        Ok(Self {
-            temp_file: None,
-            bindings_registered: false,
-        })
+        match self.key.strip_prefix("code:") {
+            Some(code) => code.parse() == Ok(bind.keycode),
+            None => bind.key.eq_ignore_ascii_case(&self.key),
+        }
     }
+}
 
-    pub async fn register(&mut self) -> Result<()> {
-        println!("Registering global keybindings");
-
-        let temp_file = NamedTempFile::new()?;
-        let temp_path = temp_file.path().to_string_lossy();
+impl FromStr for KeyBinding {
+    type Err = Error;
 
-        let enter_cmd =
-            format!("keyword bind ,Return,exec,echo 'SAVE' > {temp_path}");
-        let escape_cmd =
-            format!("keyword bind ,Escape,exec,echo 'CANCEL' > {temp_path}");
+    fn from_str(value: &str) -> Result<Self> {
+        let (mods, key) = value.rsplit_once(',').unwrap_or(("", value));
+        let key = key.trim();
 
-        self.cmd(&enter_cmd).await?;
-        self.cmd(&escape_cmd).await?;
+        let modmask = hypr::parse_modmask(mods).ok_or_else(|| {
+            Error::InvalidBinding(format!("unknown modifier in `{value}`"))
+        })?;
 
-        self.temp_file = Some(temp_file);
-        self.bindings_registered = true;
+        if key.is_empty() {
+            return Err(Error::InvalidBinding(format!(
+                "missing key in `{value}`"
+            )));
+        }
 
-        println!("Global keybindings registered successfully");
-        Ok(())
+        Ok(Self {
+            modmask,
+            key: key.to_string(),
+        })
     }
+}
 
-    pub async fn wait_for_input(&self) -> Result<Action> {
-        let temp_file = self.temp_file.as_ref().unwrap();
-        let temp_path = temp_file.path();
+impl fmt::Display for KeyBinding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mods = hypr::modmask_names(self.modmask);
 
-        let mut interval = interval(Duration::from_millis(50));
+        if mods.is_empty() {
+            f.write_str(&self.key)
+        } else {
+            write!(f, "{}+{}", mods, self.key)
+        }
+    }
+}
 
-        loop {
-            interval.tick().await;
+/// Keys used to drive a recording.
+///
+/// `enter` arms the controls and every other key only acts while they are
+/// armed, so typing is unaffected until the chord is pressed. Each action
+/// disarms them again, as does `leave`. On Hyprland this maps onto a submap,
+/// on sway the action keys are bound and unbound as the controls toggle.
+#[derive(Debug, Clone)]
+pub struct Bindings {
+    pub enter: KeyBinding,
+    pub leave: KeyBinding,
+    pub save: KeyBinding,
+    pub cancel: KeyBinding,
+    pub pause: KeyBinding,
+    pub marker: KeyBinding,
+}
 
-            if let Ok(content) = fs::read_to_string(temp_path).await {
-                let content = content.trim();
-                if !content.is_empty() {
-                    let _ = fs::write(temp_path, "").await;
+impl Bindings {
+    /// Reads `HYPR_RECORDER_BIND_{ENTER,LEAVE,SAVE,CANCEL,PAUSE,MARKER}`.
+    pub fn from_env() -> Result<Self> {
+        let enter =
+            binding_from_env("HYPR_RECORDER_BIND_ENTER", "SUPER ALT, R")?;
+        let leave = match env::var("HYPR_RECORDER_BIND_LEAVE") {
+            Ok(value) if !value.trim().is_empty() => value.parse()?,
+            _ => enter.clone(),
+        };
 
-                    match content {
-                        "SAVE" => return Ok(Action::Save),
-                        "CANCEL" => return Ok(Action::Cancel),
-                        _ => {
-                            eprintln!("Unknown key action: {}", content);
-                            continue;
-                        }
-                    }
-                }
-            }
-        }
+        Ok(Self {
+            enter,
+            leave,
+            save: binding_from_env("HYPR_RECORDER_BIND_SAVE", "Return")?,
+            cancel: binding_from_env("HYPR_RECORDER_BIND_CANCEL", "Escape")?,
+            pause: binding_from_env("HYPR_RECORDER_BIND_PAUSE", "P")?,
+            marker: binding_from_env("HYPR_RECORDER_BIND_MARKER", "M")?,
+        })
     }
 
-    pub async fn cleanup(&mut self) -> Result<()> {
-        if !self.bindings_registered {
-            return Ok(());
-        }
+    pub fn actions(&self) -> [(&KeyBinding, Action); 4] {
+        [
+            (&self.save, Action::Save),
+            (&self.cancel, Action::Cancel),
+            (&self.pause, Action::Pause),
+            (&self.marker, Action::Marker),
+        ]
+    }
 
-        println!("Cleaning up global keybindings");
+    pub(crate) fn all(&self) -> Vec<&KeyBinding> {
+        let mut all = vec![&self.enter, &self.leave];
+        all.extend(self.actions().map(|(binding, _)| binding));
+        all
+    }
+}
 
-        let remove_enter = "keyword unbind ,Return";
-        let remove_escape = "keyword unbind ,Escape";
+fn binding_from_env(name: &str, default: &str) -> Result<KeyBinding> {
+    match env::var(name) {
+        Ok(value) if !value.trim().is_empty() => value.parse(),
+        _ => default.parse(),
+    }
+}
 
-        let mut had_error = false;
+/// Which keybinding backend to use.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(ValueEnum))]
+pub enum Backend {
+    /// The compositor's own bindings when it is supported, libinput otherwise
+    Auto,
+    /// Temporary bindings registered over Hyprland or sway IPC
+    #[cfg_attr(feature = "cli", value(alias = "hyprland", alias = "sway"))]
+    Compositor,
+    /// Passive key listener reading evdev devices through libinput
+    Libinput,
+}
 
-        if let Err(e) = self.cmd(remove_enter).await {
-            eprintln!(
-                "Failed to remove Enter keybinding asynchronously: {}",
-                e
-            );
-            had_error = true;
-        }
+/// A source of recording control actions.
+#[async_trait(?Send)]
+pub trait InputBackend {
+    fn bindings(&self) -> &Bindings;
 
-        if let Err(e) = self.cmd(remove_escape).await {
-            eprintln!(
-                "Failed to remove Escape keybinding asynchronously: {}",
-                e
-            );
-            had_error = true;
-        }
+    async fn register(&mut self) -> Result<()>;
 
-        if had_error {
-            eprintln!("Falling back to blocking keybinding cleanup");
-            self.cleanup_blocking();
-        } else {
-            self.finish_cleanup();
-        }
+    async fn wait_for_input(&mut self) -> Result<Action>;
 
+    /// Activates or deactivates the action keys, for backends whose
+    /// bindings report back over the control socket.
+    async fn arm(&mut self, _arming: Arming) -> Result<()> {
         Ok(())
     }
 
-    async fn cmd(&self, command: &str) -> Result<String> {
-        // Use `hyprctl` to send commands (more reliable than direct socket)
-        let output = run_async!("hyprctl", "--batch", command)?;
-
-        if output.is_failure() {
-            return Err(Error::HyprlandNotRunning);
-        }
+    async fn cleanup(&mut self) -> Result<()>;
+}
 
-        let response = output.stdout;
+pub fn open(
+    backend: Backend,
+    compositor: Option<Box<dyn Compositor>>,
+) -> Result<Box<dyn InputBackend>> {
+    let bindings = Bindings::from_env()?;
 
-        Ok(response)
-    }
-
-    fn cleanup_blocking(&mut self) {
-        if !self.bindings_registered {
-            return;
+    match (backend, compositor) {
+        #[cfg(feature = "libinput")]
+        (Backend::Libinput, _) | (Backend::Auto, None) => {
+            Ok(Box::new(LibinputInput::new(bindings)?))
         }
-
-        for (command, name) in [
-            ("keyword unbind ,Return", "Enter"),
-            ("keyword unbind ,Escape", "Escape"),
-        ] {
-            if let Err(e) = Self::cmd_blocking(command) {
-                eprintln!(
-                    "Failed to remove {name} keybinding in blocking fallback: {}",
-                    e
-                );
-            }
+        #[cfg(not(feature = "libinput"))]
+        (Backend::Libinput, _) | (Backend::Auto, None) => {
+            Err(Error::Libinput("built without the libinput feature".into()))
         }
-
-        self.finish_cleanup();
-    }
-
-    fn finish_cleanup(&mut self) {
-        self.bindings_registered = false;
-        self.temp_file = None;
-        println!("Keybinding cleanup completed");
+        (_, Some(compositor)) => {
+            Ok(Box::new(KeybindInput::new(compositor, bindings)))
+        }
+        (Backend::Compositor, None) => Err(Error::NoCompositor),
     }
+}
 
-    fn cmd_blocking(command: &str) -> Result<String> {
-        let output = run!("hyprctl", "--batch", command)?;
+/// Forwards key presses to `actions` while a session records, releasing the
+/// keys once it stops. A failing backend cancels the recording. `arming`
+/// carries the enter and leave keys when they report over the control
+/// socket; every action the session takes disarms the controls again.
+pub async fn drive(
+    backend: &mut dyn InputBackend,
+    mut events: broadcast::Receiver<Event>,
+    actions: mpsc::Sender<Action>,
+    mut arming: mpsc::Receiver<Arming>,
+) {
+    loop {
+        tokio::select! {
+            event = session::next_event(&mut events) => match event {
+                Some(Event::Started) => {
+                    let bindings = backend.bindings();
+                    info!(
+                        "Recording started. Press {} then {} to save, {} to cancel, {} to pause or {} for a marker.",
+                        bindings.enter, bindings.save, bindings.cancel, bindings.pause, bindings.marker
+                    );
+                }
+                Some(Event::Stopping) | None => break,
+                Some(event) if event.ends_session() => break,
+                Some(Event::Paused | Event::Resumed | Event::Marker(_)) => {
+                    if let Err(e) = backend.arm(Arming::Disarm).await {
+                        warn!("Failed to disarm the controls: {}", e);
+                    }
+                }
+                Some(_) => (),
+            },
 
-        if output.is_failure() {
-            return Err(Error::HyprlandNotRunning);
-        }
+            Some(request) = arming.recv() => {
+                if let Err(e) = backend.arm(request).await {
+                    warn!("Failed to switch the controls: {}", e);
+                }
+            }
 
-        let response = output.stdout;
+            action = backend.wait_for_input() => {
+                let action = action.unwrap_or_else(|e| {
+                    error!("Key handler error: {}", e);
+                    Action::Cancel
+                });
+                let stop = action == Action::Cancel;
 
-        Ok(response)
+                if actions.send(action).await.is_err() || stop {
+                    break;
+                }
+            }
+        }
     }
-}
 
-impl Drop for Input {
-    fn drop(&mut self) {
-        self.cleanup_blocking();
+    if let Err(e) = backend.cleanup().await {
+        warn!("Failed to cleanup keybindings: {}", e);
     }
 }
@@ -0,0 +1,74 @@
+use std::future;
+
+use async_trait::async_trait;
+use tracing::warn;
+
+use super::{Action, Arming, Bindings, InputBackend};
+use crate::{compositor::Compositor, Result};
+
+/// Temporary keybindings registered with the running compositor.
+///
+/// Each binding runs `hypr-recorder ctl`, so its action reaches the session
+/// through the control socket rather than through this backend.
+pub struct KeybindInput {
+    compositor: Box<dyn Compositor>,
+    bindings: Bindings,
+    maintaining: bool,
+}
+
+impl KeybindInput {
+    pub fn new(compositor: Box<dyn Compositor>, bindings: Bindings) -> Self {
+        Self {
+            compositor,
+            bindings,
+            maintaining: false,
+        }
+    }
+}
+
+#[async_trait(?Send)]
+impl InputBackend for KeybindInput {
+    fn bindings(&self) -> &Bindings {
+        &self.bindings
+    }
+
+    async fn register(&mut self) -> Result<()> {
+        self.compositor.bind(&self.bindings).await?;
+        self.maintaining = true;
+        Ok(())
+    }
+
+    async fn wait_for_input(&mut self) -> Result<Action> {
+        if self.maintaining {
+            if let Err(e) = self.compositor.maintain().await {
+                warn!(
+                    "Failed to read {} events: {}",
+                    self.compositor.name(),
+                    e
+                );
+            }
+            self.maintaining = false;
+        }
+
+        future::pending().await
+    }
+
+    async fn arm(&mut self, arming: Arming) -> Result<()> {
+        match arming {
+            Arming::Arm => self.compositor.arm().await,
+            Arming::Disarm => self.compositor.disarm().await,
+        }
+    }
+
+    async fn cleanup(&mut self) -> Result<()> {
+        let result = self.compositor.unbind().await;
+        self.maintaining = false;
+        result
+    }
+}
+
+impl Drop for KeybindInput {
+    fn drop(&mut self) {
+        self.compositor.unbind_blocking();
+    }
+}
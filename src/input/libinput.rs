@@ -0,0 +1,441 @@
+use std::{
+    collections::VecDeque,
+    env,
+    fs::{File, OpenOptions},
+    os::{
+        fd::{AsRawFd, OwnedFd, RawFd},
+        unix::fs::OpenOptionsExt,
+    },
+    path::Path,
+};
+
+use ::input::{
+    event::{
+        device::DeviceEvent,
+        keyboard::{KeyState, KeyboardEvent, KeyboardEventTrait},
+        Event,
+        EventTrait,
+    },
+    DeviceCapability,
+    Libinput,
+    LibinputInterface,
+};
+use async_trait::async_trait;
+use tokio::io::unix::AsyncFd;
+use tracing::{debug, info};
+
+use super::{Action, Bindings, InputBackend, KeyBinding};
+use crate::{Error, Result};
+
+/// Evdev codes of modifier keys, with the Hyprland `modmask` bit they set.
+const MODIFIER_KEYS: [(u32, u32); 8] = [
+    (42, 1 << 0),  // KEY_LEFTSHIFT
+    (54, 1 << 0),  // KEY_RIGHTSHIFT
+    (29, 1 << 2),  // KEY_LEFTCTRL
+    (97, 1 << 2),  // KEY_RIGHTCTRL
+    (56, 1 << 3),  // KEY_LEFTALT
+    (100, 1 << 3), // KEY_RIGHTALT
+    (125, 1 << 6), // KEY_LEFTMETA
+    (126, 1 << 6), // KEY_RIGHTMETA
+];
+
+/// Listens to keyboards through libinput without grabbing them.
+///
+/// Key presses still reach the compositor and focused application as usual;
+/// this backend only watches for the configured bindings. It needs read
+/// access to `/dev/input/event*`, which usually means membership in the
+/// `input` group.
+///
+/// libinput reports physical keys without a keymap, so named keys are
+/// matched by their position on a US QWERTY layout: `Q` is the key right of
+/// Tab even on AZERTY. Use `code:N` to pick a key by its XKB keycode.
+pub struct LibinputInput {
+    bindings: Bindings,
+    keys: Vec<(ResolvedKey, Option<Action>)>,
+    enter: ResolvedKey,
+    context: Option<Libinput>,
+    fd: Option<AsyncFd<RawFd>>,
+    pending: VecDeque<Action>,
+    /// Which of [`MODIFIER_KEYS`] are down, so that letting go of one side
+    /// keeps the modifier while the other is still held.
+    held: [bool; MODIFIER_KEYS.len()],
+    armed: bool,
+}
+
+impl LibinputInput {
+    pub fn new(bindings: Bindings) -> Result<Self> {
+        let enter = ResolvedKey::new(&bindings.enter)?;
+
+        let mut keys = Vec::new();
+        for (binding, action) in bindings.actions() {
+            keys.push((ResolvedKey::new(binding)?, Some(action)));
+        }
+        keys.push((ResolvedKey::new(&bindings.leave)?, None));
+
+        Ok(Self {
+            bindings,
+            keys,
+            enter,
+            context: None,
+            fd: None,
+            pending: VecDeque::new(),
+            held: [false; MODIFIER_KEYS.len()],
+            armed: false,
+        })
+    }
+
+    /// The `modmask` of the modifier keys held down.
+    fn modifiers(&self) -> u32 {
+        MODIFIER_KEYS
+            .iter()
+            .zip(self.held)
+            .filter(|(_, held)| *held)
+            .fold(0, |modmask, ((_, bit), _)| modmask | bit)
+    }
+
+    /// Drains queued libinput events, turning key presses into actions.
+    fn process_events(&mut self) -> Result<()> {
+        let Some(context) = self.context.as_mut() else {
+            return Ok(());
+        };
+
+        context.dispatch()?;
+
+        let presses: Vec<(u32, KeyState)> = context
+            .filter_map(|event| match event {
+                Event::Keyboard(KeyboardEvent::Key(key)) => {
+                    Some((key.key(), key.key_state()))
+                }
+                _ => None,
+            })
+            .collect();
+
+        for (code, state) in presses {
+            if let Some(action) = self.handle_key(code, state) {
+                self.pending.push_back(action);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn handle_key(&mut self, code: u32, state: KeyState) -> Option<Action> {
+        if let Some(index) =
+            MODIFIER_KEYS.iter().position(|(key, _)| *key == code)
+        {
+            self.held[index] = state == KeyState::Pressed;
+            return None;
+        }
+
+        if state != KeyState::Pressed {
+            return None;
+        }
+
+        let modifiers = self.modifiers();
+        if !self.armed {
+            if self.enter.matches(code, modifiers) {
+                debug!("Recording controls armed");
+                self.armed = true;
+            }
+            return None;
+        }
+
+        let (_, action) = self
+            .keys
+            .iter()
+            .find(|(key, _)| key.matches(code, modifiers))?;
+
+        self.armed = false;
+        action.clone()
+    }
+}
+
+#[async_trait(?Send)]
+impl InputBackend for LibinputInput {
+    fn bindings(&self) -> &Bindings {
+        &self.bindings
+    }
+
+    async fn register(&mut self) -> Result<()> {
+        info!("Opening keyboards through libinput");
+
+        let seat = env::var("XDG_SEAT").unwrap_or_else(|_| "seat0".into());
+
+        let mut context = Libinput::new_with_udev(Interface);
+        context.udev_assign_seat(&seat).map_err(|()| {
+            Error::Libinput(format!("Failed to assign udev seat {seat}"))
+        })?;
+        context.dispatch()?;
+
+        let keyboards = context
+            .by_ref()
+            .filter(|event| match event {
+                Event::Device(DeviceEvent::Added(added)) => {
+                    added.device().has_capability(DeviceCapability::Keyboard)
+                }
+                _ => false,
+            })
+            .count();
+
+        if keyboards == 0 {
+            return Err(Error::Libinput(
+                "No keyboard could be opened; make sure your user is in the `input` group"
+                    .into(),
+            ));
+        }
+
+        info!("Listening on {keyboards} keyboard(s)");
+
+        self.fd = Some(AsyncFd::new(context.as_raw_fd())?);
+        self.context = Some(context);
+        self.held = [false; MODIFIER_KEYS.len()];
+        self.armed = false;
+
+        info!(
+            "Press {} to arm the recording controls",
+            self.bindings.enter
+        );
+        Ok(())
+    }
+
+    async fn wait_for_input(&mut self) -> Result<Action> {
+        loop {
+            if let Some(action) = self.pending.pop_front() {
+                return Ok(action);
+            }
+
+            let Some(fd) = self.fd.as_ref() else {
+                return std::future::pending().await;
+            };
+
+            let mut guard = fd.readable().await?;
+            guard.clear_ready();
+            drop(guard);
+
+            self.process_events()?;
+        }
+    }
+
+    async fn cleanup(&mut self) -> Result<()> {
+        // The fd belongs to the libinput context, so deregister it first.
+        self.fd = None;
+        self.context = None;
+        self.pending.clear();
+        self.armed = false;
+        Ok(())
+    }
+}
+
+/// A binding translated to an evdev key code.
+struct ResolvedKey {
+    code: u32,
+    modmask: u32,
+}
+
+impl ResolvedKey {
+    fn new(binding: &KeyBinding) -> Result<Self> {
+        let code = key_code(&binding.key).ok_or_else(|| {
+            Error::InvalidBinding(format!(
+                "key `{}` is not supported by the libinput backend",
+                binding.key
+            ))
+        })?;
+
+        Ok(Self {
+            code,
+            modmask: binding.modmask,
+        })
+    }
+
+    fn matches(&self, code: u32, modifiers: u32) -> bool {
+        self.code == code && self.modmask == modifiers
+    }
+}
+
+/// Maps an XKB key name, as used in Hyprland binds, to the evdev code of the
+/// key with that name on a US QWERTY layout.
+fn key_code(name: &str) -> Option<u32> {
+    // `code:N` uses XKB keycodes, which are offset from evdev's by 8.
+    if let Some(code) = name.strip_prefix("code:") {
+        return code.parse::<u32>().ok()?.checked_sub(8);
+    }
+
+    const LETTERS: &str = "QWERTYUIOPASDFGHJKLZXCVBNM";
+    const LETTER_CODES: [u32; 26] = [
+        16, 17, 18, 19, 20, 21, 22, 23, 24, 25, 30, 31, 32, 33, 34, 35, 36, 37,
+        38, 44, 45, 46, 47, 48, 49, 50,
+    ];
+
+    let upper = name.to_ascii_uppercase();
+
+    if upper.len() == 1 {
+        let c = upper.chars().next()?;
+        if let Some(index) = LETTERS.find(c) {
+            return Some(LETTER_CODES[index]);
+        }
+        if let Some(digit) = c.to_digit(10) {
+            return Some(if digit == 0 { 11 } else { digit + 1 });
+        }
+    }
+
+    if let Some(n) = upper.strip_prefix('F').and_then(|n| n.parse().ok()) {
+        return match n {
+            1..=10 => Some(58 + n),
+            11 | 12 => Some(76 + n),
+            _ => None,
+        };
+    }
+
+    let code = match upper.as_str() {
+        "ESCAPE" => 1,
+        "MINUS" => 12,
+        "EQUAL" => 13,
+        "BACKSPACE" => 14,
+        "TAB" => 15,
+        "BRACKETLEFT" => 26,
+        "BRACKETRIGHT" => 27,
+        "RETURN" | "ENTER" => 28,
+        "SEMICOLON" => 39,
+        "APOSTROPHE" => 40,
+        "GRAVE" => 41,
+        "BACKSLASH" => 43,
+        "COMMA" => 51,
+        "PERIOD" => 52,
+        "SLASH" => 53,
+        "SPACE" => 57,
+        "KP_ENTER" => 96,
+        "PRINT" => 99,
+        "HOME" => 102,
+        "UP" => 103,
+        "PRIOR" | "PAGE_UP" => 104,
+        "LEFT" => 105,
+        "RIGHT" => 106,
+        "END" => 107,
+        "DOWN" => 108,
+        "NEXT" | "PAGE_DOWN" => 109,
+        "INSERT" => 110,
+        "DELETE" => 111,
+        "PAUSE" => 119,
+        _ => return None,
+    };
+
+    Some(code)
+}
+
+struct Interface;
+
+impl LibinputInterface for Interface {
+    fn open_restricted(
+        &mut self,
+        path: &Path,
+        flags: i32,
+    ) -> std::result::Result<OwnedFd, i32> {
+        let access = flags & libc::O_ACCMODE;
+
+        OpenOptions::new()
+            .custom_flags(flags)
+            .read(access == libc::O_RDONLY || access == libc::O_RDWR)
+            .write(access == libc::O_WRONLY || access == libc::O_RDWR)
+            .open(path)
+            .map(Into::into)
+            .map_err(|err| err.raw_os_error().unwrap_or(libc::EIO))
+    }
+
+    fn close_restricted(&mut self, fd: OwnedFd) {
+        drop(File::from(fd));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LEFT_ALT: u32 = 56;
+    const RIGHT_ALT: u32 = 100;
+    const LEFT_META: u32 = 125;
+    const LEFT_CTRL: u32 = 29;
+    const R: u32 = 19;
+    const RETURN: u32 = 28;
+
+    fn input() -> LibinputInput {
+        LibinputInput::new(Bindings {
+            enter: "SUPER ALT, R".parse().unwrap(),
+            leave: "SUPER ALT, R".parse().unwrap(),
+            save: "Return".parse().unwrap(),
+            cancel: "Escape".parse().unwrap(),
+            pause: "P".parse().unwrap(),
+            marker: "M".parse().unwrap(),
+        })
+        .unwrap()
+    }
+
+    /// Presses `keys` in order, then releases them in reverse, returning
+    /// the actions they triggered.
+    fn chord(input: &mut LibinputInput, keys: &[u32]) -> Vec<Action> {
+        let mut actions: Vec<Action> = keys
+            .iter()
+            .filter_map(|code| input.handle_key(*code, KeyState::Pressed))
+            .collect();
+        actions.extend(
+            keys.iter()
+                .rev()
+                .filter_map(|code| input.handle_key(*code, KeyState::Released)),
+        );
+        actions
+    }
+
+    #[test]
+    fn the_enter_chord_arms_one_action() {
+        let mut input = input();
+
+        assert!(chord(&mut input, &[RETURN]).is_empty());
+        assert!(chord(&mut input, &[LEFT_META, LEFT_ALT, R]).is_empty());
+        assert!(input.armed);
+
+        assert_eq!(chord(&mut input, &[RETURN]), [Action::Save]);
+        assert!(!input.armed);
+        assert!(chord(&mut input, &[RETURN]).is_empty());
+    }
+
+    #[test]
+    fn the_leave_chord_disarms() {
+        let mut input = input();
+        chord(&mut input, &[LEFT_META, LEFT_ALT, R]);
+
+        assert!(chord(&mut input, &[LEFT_META, LEFT_ALT, R]).is_empty());
+        assert!(!input.armed);
+    }
+
+    #[test]
+    fn modifiers_stay_held_while_either_side_is_down() {
+        let mut input = input();
+        input.handle_key(LEFT_META, KeyState::Pressed);
+        input.handle_key(LEFT_ALT, KeyState::Pressed);
+        input.handle_key(RIGHT_ALT, KeyState::Pressed);
+        input.handle_key(LEFT_ALT, KeyState::Released);
+
+        assert_eq!(input.handle_key(R, KeyState::Pressed), None);
+        assert!(input.armed);
+
+        input.handle_key(RIGHT_ALT, KeyState::Released);
+        assert_eq!(input.modifiers(), 1 << 6);
+    }
+
+    #[test]
+    fn extra_modifiers_do_not_match() {
+        let mut input = input();
+
+        chord(&mut input, &[LEFT_CTRL, LEFT_META, LEFT_ALT, R]);
+
+        assert!(!input.armed);
+    }
+
+    #[test]
+    fn releases_do_not_trigger_actions() {
+        let mut input = input();
+        chord(&mut input, &[LEFT_META, LEFT_ALT, R]);
+
+        assert_eq!(input.handle_key(RETURN, KeyState::Released), None);
+        assert!(input.armed);
+    }
+}
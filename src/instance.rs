@@ -0,0 +1,90 @@
+use std::{
+    fs::{File, OpenOptions},
+    io,
+    os::fd::AsRawFd,
+    path::PathBuf,
+};
+
+use clap::ValueEnum;
+use tracing::{info, warn};
+
+use crate::{
+    control::{self, Request, Response},
+    Error,
+    Result,
+};
+
+const LOCK_NAME: &str = "hypr-recorder.lock";
+
+/// What to do when another recorder already holds the instance lock.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum IfRunning {
+    /// Ask the running recorder to save, turning the launch into a toggle;
+    /// an idle daemon starts recording instead
+    Save,
+    /// Ask the running recorder to discard its recording
+    Cancel,
+    /// Leave the running recorder alone and exit with an error
+    Refuse,
+}
+
+/// Exclusive `flock` held for the lifetime of a recording session.
+///
+/// The lock is released by the kernel when the file is closed, so a crashed
+/// session never leaves a stale lock behind.
+pub struct InstanceLock {
+    _file: File,
+}
+
+impl InstanceLock {
+    /// Takes the lock, or returns `None` if another instance holds it.
+    pub fn acquire() -> Result<Option<Self>> {
+        let path = lock_path()?;
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&path)?;
+
+        let locked = unsafe {
+            libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB)
+        };
+
+        if locked != 0 {
+            let err = io::Error::last_os_error();
+            if err.kind() == io::ErrorKind::WouldBlock {
+                return Ok(None);
+            }
+            return Err(Error::Io(err));
+        }
+
+        Ok(Some(Self { _file: file }))
+    }
+}
+
+/// Applies the `if_running` policy to the instance that holds the lock.
+pub async fn signal_running(if_running: IfRunning) -> Result<()> {
+    let request = match if_running {
+        IfRunning::Save => Request::Toggle,
+        IfRunning::Cancel => Request::Cancel,
+        IfRunning::Refuse => return Err(Error::AlreadyRunning),
+    };
+
+    info!("Another recorder is running, sending '{request}'");
+
+    match control::send(request).await {
+        Ok(Response::Ok(_)) => Ok(()),
+        Ok(Response::Error(message)) => Err(Error::Control(message)),
+        Err(e) => {
+            warn!(
+                "The running recorder is not accepting commands yet; it may still be waiting on the portal dialog"
+            );
+            Err(e)
+        }
+    }
+}
+
+fn lock_path() -> Result<PathBuf> {
+    let socket = control::socket_path()?;
+    Ok(socket.with_file_name(LOCK_NAME))
+}
@@ -0,0 +1,87 @@
+//! Screen and audio recording for Wayland desktops, the engine behind the
+//! `hypr-recorder` tool.
+//!
+//! A [`Session`] records what a [`capture::CaptureSource`] provides into an
+//! MP4 file and, with the `whisper` feature, transcribes it. It moves
+//! through a [`session::State`] machine, and subscribers follow along
+//! through [`Event`]s.
+//!
+//! ```no_run
+//! use std::time::Duration;
+//!
+//! use hypr_recorder::{
+//!     capture::Source,
+//!     input::Action,
+//!     metadata::Metadata,
+//!     session,
+//!     Session,
+//! };
+//! use tokio::sync::mpsc;
+//!
+//! # async fn record() -> hypr_recorder::Result<()> {
+//! let mut session = Session::builder()
+//!     .source(Source::Synthetic)
+//!     .build()
+//!     .await?;
+//! let mut events = session.subscribe();
+//! tokio::spawn(async move {
+//!     while let Some(event) = session::next_event(&mut events).await {
+//!         println!("{event:?}");
+//!     }
+//! });
+//!
+//! let (actions, mut requested) = mpsc::channel(1);
+//! tokio::spawn(async move {
+//!     tokio::time::sleep(Duration::from_secs(5)).await;
+//!     let _ = actions.send(Action::Save).await;
+//! });
+//!
+//! session
+//!     .run(Metadata::new(None, None, None), &mut requested)
+//!     .await?;
+//! println!("saved to {}", session.metadata().recording.display());
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Backends are behind cargo features, all enabled by default: `portal`
+//! (XDG desktop portal capture), `cpal` (microphone fallback), `hyprland`
+//! (compositor backend), `libinput` (key listener) and `whisper`
+//! (transcription). `cli` builds the `hypr-recorder` binary.
+//!
+//! Diagnostics are emitted through [`tracing`], with spans around portal
+//! negotiation, the pipeline, muxing and transcription; install a
+//! subscriber to see them.
+
+#[cfg(feature = "cpal")]
+mod audio;
+pub mod capture;
+mod chapters;
+pub mod command;
+pub mod compositor;
+mod error;
+// Key notation for every backend; the IPC client only serves the Hyprland
+// one.
+#[cfg_attr(not(feature = "hyprland"), allow(dead_code))]
+mod hyprland;
+pub mod input;
+pub mod markers;
+pub mod metadata;
+pub mod naming;
+pub mod pipeline;
+pub mod recorder;
+pub mod session;
+mod sway;
+#[cfg(feature = "whisper")]
+pub mod transcriber;
+/// Helpers shared with the `hypr-recorder` binary; not a stable API.
+#[doc(hidden)]
+pub mod utils;
+
+#[cfg(feature = "whisper")]
+pub use crate::transcriber::Transcriber;
+pub use crate::{
+    error::{Error, Result},
+    recorder::Recorder,
+    session::{Event, Session, SessionBuilder},
+};
@@ -0,0 +1,762 @@
+use std::{
+    env,
+    fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::{Duration, SystemTime},
+};
+
+use serde::{Deserialize, Serialize};
+use tokio::{sync::broadcast, task};
+use tracing::{info, warn};
+
+use crate::{
+    metadata,
+    naming,
+    search::{self, Index},
+    session::{self, Event},
+    utils,
+    Error,
+    Result,
+};
+
+/// Prefix of the ids of recordings in [`naming::legacy_recordings_dir`].
+const LEGACY_PREFIX: &str = "Recordings/";
+
+/// Extensions of files written next to a recording, sharing its stem.
+const COMPANIONS: &[&str] = &["json", "markers.json", "srt", "txt", "wav"];
+
+/// A saved recording and what is known about it.
+#[derive(Debug, Clone, Serialize)]
+pub struct Recording {
+    /// Path relative to the recordings directory, without the extension.
+    /// Recordings left in `~/Recordings` by older versions get a
+    /// `Recordings/` prefix.
+    pub id: String,
+    pub path: PathBuf,
+    /// Local time, RFC 3339; the file's modification time if the metadata
+    /// sidecar is missing.
+    pub started_at: String,
+    pub duration_seconds: Option<f64>,
+    pub size: u64,
+    pub transcript: Option<PathBuf>,
+    pub metadata: Option<PathBuf>,
+    #[serde(skip)]
+    modified: SystemTime,
+}
+
+impl Recording {
+    /// The recording at `path`, or `None` if it is not one of ours.
+    fn load(root: &Root, path: PathBuf) -> Result<Option<Self>> {
+        let metadata_path = metadata::sidecar_path(&path);
+        let summary = fs::read(&metadata_path)
+            .ok()
+            .and_then(|bytes| serde_json::from_slice::<Summary>(&bytes).ok());
+        if summary.is_none() && !root.legacy {
+            return Ok(None);
+        }
+
+        let file = fs::metadata(&path)?;
+        let modified = file.modified()?;
+
+        let id = root.id(&path);
+
+        let transcript = summary
+            .as_ref()
+            .and_then(|summary| summary.transcript.clone())
+            .or_else(|| Some(path.with_extension("txt")))
+            .filter(|transcript| transcript.exists());
+
+        Ok(Some(Self {
+            id,
+            started_at: summary
+                .as_ref()
+                .and_then(|summary| summary.started_at.clone())
+                .unwrap_or_else(|| utils::rfc3339(modified)),
+            duration_seconds: summary
+                .as_ref()
+                .and_then(|summary| summary.duration_seconds),
+            size: file.len(),
+            transcript,
+            metadata: summary.map(|_| metadata_path),
+            path,
+            modified,
+        }))
+    }
+
+    /// The recording and every sidecar that exists for it.
+    pub fn files(&self) -> Vec<PathBuf> {
+        let stem = self
+            .path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default();
+
+        let mut files = vec![self.path.clone()];
+        files.extend(
+            COMPANIONS
+                .iter()
+                .map(|ext| self.path.with_file_name(format!("{stem}.{ext}")))
+                .filter(|path| path.exists()),
+        );
+        if let Some(transcript) = &self.transcript {
+            if !files.contains(transcript) {
+                files.push(transcript.clone());
+            }
+        }
+
+        files
+    }
+
+    fn age(&self) -> Duration {
+        SystemTime::now()
+            .duration_since(self.modified)
+            .unwrap_or_default()
+    }
+}
+
+/// The id `list` shows for the recording at `path`. Recordings outside the
+/// listed directories go by their file name, as the id ends up in upload
+/// keys and must not give away where they are.
+pub fn id_of(path: &Path) -> String {
+    roots()
+        .iter()
+        .find(|root| path.starts_with(&root.dir))
+        .map(|root| root.id(path))
+        .unwrap_or_else(|| {
+            path.file_stem()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .into_owned()
+        })
+}
+
+/// A directory recordings are listed from.
+struct Root {
+    dir: PathBuf,
+    prefix: &'static str,
+    /// Whether every MP4 in it is a recording, as in the directory older
+    /// versions kept to themselves. Elsewhere, such as in `~/Videos`, only
+    /// files with a metadata sidecar are, so the user's own videos are
+    /// never listed or pruned.
+    legacy: bool,
+}
+
+impl Root {
+    fn id(&self, path: &Path) -> String {
+        let relative = path.strip_prefix(&self.dir).unwrap_or(path);
+        format!(
+            "{}{}",
+            self.prefix,
+            relative.with_extension("").to_string_lossy()
+        )
+    }
+}
+
+/// The recordings directory, plus the one older versions used while it
+/// still exists, so that moving to the XDG videos directory loses nothing.
+fn roots() -> Vec<Root> {
+    let mut roots = vec![Root {
+        dir: naming::recordings_dir(),
+        prefix: "",
+        legacy: false,
+    }];
+    roots.extend(naming::legacy_recordings_dir().map(|dir| Root {
+        dir,
+        prefix: LEGACY_PREFIX,
+        legacy: true,
+    }));
+    roots
+}
+
+/// The fields of the metadata sidecar the library cares about.
+#[derive(Deserialize)]
+struct Summary {
+    /// Present in every sidecar this tool writes, and what tells them from
+    /// other JSON files.
+    #[serde(rename = "app_version")]
+    _app_version: String,
+    started_at: Option<String>,
+    duration_seconds: Option<f64>,
+    transcript: Option<PathBuf>,
+}
+
+/// All recordings under the recordings directory, and under `~/Recordings`
+/// if older versions left any there, oldest first.
+pub fn list() -> Result<Vec<Recording>> {
+    list_in(&roots())
+}
+
+fn list_in(roots: &[Root]) -> Result<Vec<Recording>> {
+    let mut recordings = Vec::new();
+
+    for root in roots {
+        if root.dir.is_dir() {
+            collect(root, &root.dir, &mut recordings)?;
+        }
+    }
+
+    recordings.sort_by_key(|recording| recording.modified);
+    Ok(recordings)
+}
+
+/// Adds the recordings under `dir` to `out`. Symbolic links are not
+/// followed, and subdirectories that cannot be read are skipped.
+fn collect(root: &Root, dir: &Path, out: &mut Vec<Recording>) -> Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        let file_type = entry.file_type()?;
+
+        if file_type.is_dir() {
+            if let Err(e) = collect(root, &path, out) {
+                warn!("Skipping {}: {}", path.display(), e);
+            }
+        } else if file_type.is_file() && is_recording(&path) {
+            out.extend(Recording::load(root, path)?);
+        }
+    }
+
+    Ok(())
+}
+
+fn is_recording(path: &Path) -> bool {
+    let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+        return false;
+    };
+
+    // Skip the intermediate file left while fallback audio is muxed in.
+    name.ends_with(".mp4") && !name.ends_with("_video-only.mp4")
+}
+
+/// Looks a recording up by id, or by its path.
+pub fn find(id: &str) -> Result<Recording> {
+    let wanted = Path::new(id);
+
+    list()?
+        .into_iter()
+        .find(|recording| recording.id == id || recording.path == wanted)
+        .ok_or_else(|| Error::RecordingNotFound(id.to_string()))
+}
+
+/// Removes a recording along with its sidecars and search entries.
+pub fn delete(recording: &Recording) -> Result<()> {
+    remove_files(recording)?;
+    unindex(std::slice::from_ref(recording));
+    Ok(())
+}
+
+fn remove_files(recording: &Recording) -> Result<()> {
+    for file in recording.files() {
+        fs::remove_file(&file)?;
+    }
+
+    Ok(())
+}
+
+/// Drops the search entries of removed `recordings`.
+fn unindex(recordings: &[Recording]) {
+    if recordings.is_empty() {
+        return;
+    }
+
+    let index = match Index::open() {
+        Ok(index) => index,
+        Err(e) => {
+            warn!("Failed to open the index: {}", e);
+            return;
+        }
+    };
+
+    for recording in recordings {
+        if let Err(e) = index.remove(&recording.path) {
+            warn!("Failed to remove {} from the index: {}", recording.id, e);
+        }
+    }
+}
+
+/// Which recordings to remove, automatically after each save or via `prune`.
+#[derive(Debug, Clone, Default)]
+pub struct Retention {
+    pub older_than: Option<Age>,
+    pub max_size: Option<Size>,
+}
+
+impl Retention {
+    /// Reads `HYPR_RECORDER_RETENTION_AGE` (e.g. `30d`) and
+    /// `HYPR_RECORDER_RETENTION_SIZE` (e.g. `20G`). Invalid values are
+    /// reported and ignored.
+    pub fn from_env() -> Self {
+        fn var<T: FromStr<Err = String>>(name: &str) -> Option<T> {
+            let value = env::var(name).ok()?;
+            value
+                .parse()
+                .inspect_err(|e| warn!("Ignoring {name}: {e}"))
+                .ok()
+        }
+
+        Self {
+            older_than: var("HYPR_RECORDER_RETENTION_AGE"),
+            max_size: var("HYPR_RECORDER_RETENTION_SIZE"),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.older_than.is_none() && self.max_size.is_none()
+    }
+
+    /// Recordings the policy removes, never including `keep`. Once past the
+    /// age limit, the oldest recordings go first until the rest fit.
+    pub fn select(
+        &self,
+        recordings: Vec<Recording>,
+        keep: Option<&Path>,
+    ) -> Vec<Recording> {
+        let (mut expired, kept): (Vec<_>, Vec<_>) =
+            recordings.into_iter().partition(|recording| {
+                Some(recording.path.as_path()) != keep
+                    && self
+                        .older_than
+                        .is_some_and(|Age(age)| recording.age() > age)
+            });
+
+        if let Some(Size(max_size)) = self.max_size {
+            let mut total: u64 = kept.iter().map(|rec| rec.size).sum();
+
+            for recording in kept {
+                if total <= max_size {
+                    break;
+                }
+                if Some(recording.path.as_path()) != keep {
+                    total -= recording.size;
+                    expired.push(recording);
+                }
+            }
+        }
+
+        expired
+    }
+}
+
+/// Removes what `policy` selects, sparing `keep`. Returns what was removed,
+/// or would be with `dry_run`.
+pub fn prune(
+    policy: &Retention,
+    keep: Option<&Path>,
+    dry_run: bool,
+) -> Result<Vec<Recording>> {
+    let selected = policy.select(list()?, keep);
+    if dry_run {
+        return Ok(selected);
+    }
+
+    let (removed, result) = remove_all(selected);
+    unindex(&removed);
+    result.map(|()| removed)
+}
+
+/// Removes the files of `recordings` up to the first failure, returning
+/// the recordings removed by then along with it.
+fn remove_all(recordings: Vec<Recording>) -> (Vec<Recording>, Result<()>) {
+    let mut removed = Vec::new();
+
+    for recording in recordings {
+        if let Err(e) = remove_files(&recording) {
+            return (removed, Err(e));
+        }
+        removed.push(recording);
+    }
+
+    (removed, Ok(()))
+}
+
+/// Applies the configured retention policy after `saved` was written.
+pub fn apply_retention(saved: &Path) {
+    let policy = Retention::from_env();
+    if policy.is_empty() {
+        return;
+    }
+
+    match prune(&policy, Some(saved), false) {
+        Ok(removed) => {
+            for recording in removed {
+                info!("Retention removed {}", recording.id);
+            }
+        }
+        Err(e) => warn!("Failed to apply retention policy: {}", e),
+    }
+}
+
+/// Keeps the library tidy as a session saves recordings: applies the
+/// retention policy to each and indexes its transcript.
+pub async fn follow(mut events: broadcast::Receiver<Event>) {
+    while let Some(event) = session::next_event(&mut events).await {
+        match &event {
+            Event::Saved(metadata) => {
+                let recording = metadata.recording.clone();
+                blocking(move || apply_retention(&recording)).await;
+            }
+            Event::Transcribed(metadata) => {
+                let recording = metadata.recording.clone();
+                blocking(move || search::index_transcript(&recording)).await;
+            }
+            _ => (),
+        }
+
+        if event.ends_session() {
+            break;
+        }
+    }
+}
+
+/// Runs `work`, which walks the file system or the index, off the async
+/// workers.
+async fn blocking(work: impl FnOnce() + Send + 'static) {
+    if let Err(e) = task::spawn_blocking(work).await {
+        warn!("Library upkeep failed: {}", e);
+    }
+}
+
+/// A maximum age such as `90m`, `12h`, `30d` or `4w`.
+#[derive(Debug, Clone, Copy)]
+pub struct Age(pub Duration);
+
+impl FromStr for Age {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let (number, unit) = split_unit(s);
+        let seconds = match unit {
+            "s" => 1,
+            "m" => 60,
+            "h" => 3600,
+            "" | "d" => 86_400,
+            "w" => 7 * 86_400,
+            _ => return Err(format!("unknown age unit `{unit}` in `{s}`")),
+        };
+
+        let number: u64 = number
+            .parse()
+            .map_err(|_| format!("invalid age `{s}`, expected e.g. 30d"))?;
+
+        let seconds = number
+            .checked_mul(seconds)
+            .ok_or_else(|| format!("age `{s}` is too large"))?;
+
+        Ok(Self(Duration::from_secs(seconds)))
+    }
+}
+
+/// A size such as `500M` or `20G`, in powers of 1024.
+#[derive(Debug, Clone, Copy)]
+pub struct Size(pub u64);
+
+impl FromStr for Size {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let (number, unit) = split_unit(s);
+        let shift = match unit.to_ascii_uppercase().trim_end_matches('B') {
+            "" => 0,
+            "K" => 10,
+            "M" => 20,
+            "G" => 30,
+            "T" => 40,
+            _ => return Err(format!("unknown size unit `{unit}` in `{s}`")),
+        };
+
+        let number: u64 = number
+            .parse()
+            .map_err(|_| format!("invalid size `{s}`, expected e.g. 20G"))?;
+
+        // Bits shifted out would silently wrap to a much smaller limit.
+        let bytes = number
+            .checked_shl(shift)
+            .filter(|bytes| bytes >> shift == number)
+            .ok_or_else(|| format!("size `{s}` is too large"))?;
+
+        Ok(Self(bytes))
+    }
+}
+
+fn split_unit(s: &str) -> (&str, &str) {
+    let s = s.trim();
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    s.split_at(split)
+}
+
+/// `1.2 GiB`, `340.0 MiB`, ...
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: &[&str] = &["B", "KiB", "MiB", "GiB", "TiB"];
+
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{bytes} B")
+    } else {
+        format!("{size:.1} {}", UNITS[unit])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::fs::{symlink, PermissionsExt};
+
+    use tempfile::TempDir;
+
+    use super::*;
+
+    const DAY: Duration = Duration::from_secs(86_400);
+
+    fn root(dir: &TempDir) -> Root {
+        Root {
+            dir: dir.path().to_path_buf(),
+            prefix: "",
+            legacy: false,
+        }
+    }
+
+    /// A video of `size` bytes last modified `age` ago.
+    fn video(dir: &Path, name: &str, size: usize, age: Duration) -> PathBuf {
+        let path = dir.join(name);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, vec![0; size]).unwrap();
+        fs::File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(SystemTime::now() - age)
+            .unwrap();
+        path
+    }
+
+    /// A recording of ours: a video with a metadata sidecar and subtitles.
+    fn recording(
+        dir: &Path,
+        name: &str,
+        size: usize,
+        age: Duration,
+    ) -> PathBuf {
+        let path = video(dir, name, size, age);
+        fs::write(
+            metadata::sidecar_path(&path),
+            r#"{"app_version": "0.1.0", "duration_seconds": 2.5}"#,
+        )
+        .unwrap();
+        fs::write(path.with_extension("srt"), "").unwrap();
+        path
+    }
+
+    fn ids(recordings: &[Recording]) -> Vec<&str> {
+        recordings
+            .iter()
+            .map(|recording| recording.id.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn only_recordings_with_a_sidecar_are_listed() {
+        let dir = TempDir::new().unwrap();
+        recording(dir.path(), "capture_1.mp4", 10, DAY * 2);
+        recording(dir.path(), "2024/capture_2.mp4", 10, DAY);
+        video(dir.path(), "holiday.mp4", 10, DAY);
+        video(dir.path(), "talk.mp4", 10, DAY);
+        fs::write(dir.path().join("talk.json"), r#"{"title": "talk"}"#)
+            .unwrap();
+        video(dir.path(), "capture_3_video-only.mp4", 10, DAY);
+
+        let recordings = list_in(&[root(&dir)]).unwrap();
+
+        assert_eq!(ids(&recordings), ["capture_1", "2024/capture_2"]);
+        assert_eq!(recordings[0].duration_seconds, Some(2.5));
+    }
+
+    #[test]
+    fn every_video_in_the_legacy_directory_is_listed() {
+        let dir = TempDir::new().unwrap();
+        video(dir.path(), "capture_1.mp4", 10, DAY);
+        let legacy = Root {
+            prefix: LEGACY_PREFIX,
+            legacy: true,
+            ..root(&dir)
+        };
+
+        let recordings = list_in(&[legacy]).unwrap();
+
+        assert_eq!(ids(&recordings), ["Recordings/capture_1"]);
+        assert_eq!(recordings[0].metadata, None);
+    }
+
+    #[test]
+    fn symlinked_directories_are_not_followed() {
+        let dir = TempDir::new().unwrap();
+        recording(dir.path(), "capture_1.mp4", 10, DAY);
+        symlink(dir.path(), dir.path().join("loop")).unwrap();
+
+        let recordings = list_in(&[root(&dir)]).unwrap();
+
+        assert_eq!(ids(&recordings), ["capture_1"]);
+    }
+
+    #[test]
+    fn unreadable_directories_are_skipped() {
+        let dir = TempDir::new().unwrap();
+        recording(dir.path(), "capture_1.mp4", 10, DAY);
+        let locked = dir.path().join("locked");
+        fs::create_dir(&locked).unwrap();
+        symlink("/nonexistent", dir.path().join("dangling.mp4")).unwrap();
+        fs::set_permissions(&locked, fs::Permissions::from_mode(0o000))
+            .unwrap();
+
+        // Root reads the locked directory regardless; anyone else skips it.
+        let recordings = list_in(&[root(&dir)]);
+        fs::set_permissions(&locked, fs::Permissions::from_mode(0o755))
+            .unwrap();
+
+        assert_eq!(ids(&recordings.unwrap()), ["capture_1"]);
+    }
+
+    #[test]
+    fn retention_removes_expired_recordings_except_the_kept_one() {
+        let dir = TempDir::new().unwrap();
+        recording(dir.path(), "old.mp4", 10, DAY * 10);
+        let kept = recording(dir.path(), "kept.mp4", 10, DAY * 9);
+        recording(dir.path(), "new.mp4", 10, DAY);
+        let policy = Retention {
+            older_than: Some(Age(DAY * 7)),
+            max_size: None,
+        };
+
+        let selected =
+            policy.select(list_in(&[root(&dir)]).unwrap(), Some(&kept));
+
+        assert_eq!(ids(&selected), ["old"]);
+    }
+
+    #[test]
+    fn retention_removes_the_oldest_until_the_rest_fit() {
+        let dir = TempDir::new().unwrap();
+        recording(dir.path(), "a.mp4", 40, DAY * 4);
+        recording(dir.path(), "b.mp4", 40, DAY * 3);
+        recording(dir.path(), "c.mp4", 40, DAY * 2);
+        let d = recording(dir.path(), "d.mp4", 40, DAY);
+        let policy = Retention {
+            older_than: None,
+            max_size: Some(Size(100)),
+        };
+
+        let recordings = list_in(&[root(&dir)]).unwrap();
+        assert_eq!(ids(&policy.select(recordings.clone(), None)), ["a", "b"]);
+        assert_eq!(
+            ids(&Retention::default().select(recordings.clone(), None)),
+            Vec::<&str>::new()
+        );
+
+        // The kept recording counts towards the total but is never removed.
+        let policy = Retention {
+            max_size: Some(Size(50)),
+            ..policy
+        };
+        assert_eq!(ids(&policy.select(recordings, Some(&d))), ["a", "b", "c"]);
+    }
+
+    #[test]
+    fn pruning_removes_recordings_with_their_sidecars_only() {
+        let dir = TempDir::new().unwrap();
+        let old = recording(dir.path(), "capture_1.mp4", 10, DAY * 10);
+        fs::write(old.with_extension("txt"), "").unwrap();
+        let holiday = video(dir.path(), "holiday.mp4", 10, DAY * 10);
+        fs::write(holiday.with_extension("srt"), "").unwrap();
+        let policy = Retention {
+            older_than: Some(Age(DAY)),
+            max_size: None,
+        };
+
+        let selected = policy.select(list_in(&[root(&dir)]).unwrap(), None);
+        let (removed, result) = remove_all(selected);
+
+        result.unwrap();
+        assert_eq!(ids(&removed), ["capture_1"]);
+        let mut left: Vec<_> = fs::read_dir(dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        left.sort();
+        assert_eq!(left, ["holiday.mp4", "holiday.srt"]);
+    }
+
+    #[test]
+    fn ids_are_relative_to_their_root() {
+        let legacy = Root {
+            dir: PathBuf::from("/home/user/Recordings"),
+            prefix: LEGACY_PREFIX,
+            legacy: true,
+        };
+        let current = Root {
+            dir: PathBuf::from("/home/user/Videos"),
+            prefix: "",
+            legacy: false,
+        };
+
+        assert_eq!(
+            legacy.id(Path::new("/home/user/Recordings/capture_1.mp4")),
+            "Recordings/capture_1"
+        );
+        assert_eq!(
+            current.id(Path::new("/home/user/Videos/2024/capture_2.mp4")),
+            "2024/capture_2"
+        );
+    }
+
+    #[test]
+    fn ids_elsewhere_are_file_names() {
+        assert_eq!(
+            id_of(Path::new("/tmp/hypr-recorder-elsewhere/capture_3.mp4")),
+            "capture_3"
+        );
+    }
+
+    #[test]
+    fn ages_are_parsed_in_their_unit() {
+        assert_eq!("90m".parse::<Age>().unwrap().0.as_secs(), 90 * 60);
+        assert_eq!("30".parse::<Age>().unwrap().0.as_secs(), 30 * 86_400);
+        assert_eq!("4w".parse::<Age>().unwrap().0.as_secs(), 28 * 86_400);
+    }
+
+    #[test]
+    fn invalid_ages_are_rejected() {
+        assert!("".parse::<Age>().is_err());
+        assert!("3y".parse::<Age>().is_err());
+        assert!("-1d".parse::<Age>().is_err());
+    }
+
+    #[test]
+    fn overflowing_age_is_rejected() {
+        let age = format!("{}w", u64::MAX / 1000);
+        assert_eq!(
+            age.parse::<Age>().unwrap_err(),
+            format!("age `{age}` is too large")
+        );
+    }
+
+    #[test]
+    fn sizes_are_parsed_in_powers_of_1024() {
+        assert_eq!("512".parse::<Size>().unwrap().0, 512);
+        assert_eq!("500M".parse::<Size>().unwrap().0, 500 << 20);
+        assert_eq!("20GB".parse::<Size>().unwrap().0, 20 << 30);
+        assert_eq!("1t".parse::<Size>().unwrap().0, 1 << 40);
+    }
+
+    #[test]
+    fn overflowing_size_is_rejected() {
+        // 2^24 TiB is 2^64 bytes, which would wrap to 0 and prune everything.
+        assert!("16777216T".parse::<Size>().is_err());
+        assert!("16777215T".parse::<Size>().is_ok());
+        assert!("99999999999999999999".parse::<Size>().is_err());
+    }
+}
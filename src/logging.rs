@@ -0,0 +1,118 @@
+use std::{env, fs, io};
+
+use gstreamer::{self as gst, DebugLevel};
+use tracing::{debug, error, info, trace, warn};
+use tracing_appender::{
+    non_blocking::WorkerGuard,
+    rolling::{RollingFileAppender, Rotation},
+};
+use tracing_subscriber::{fmt, prelude::*, EnvFilter};
+
+use crate::{utils, Result};
+
+/// Used when `--log-level` does not parse.
+pub const DEFAULT_LEVEL: &str = "info";
+/// Daily log files kept in the state directory; older ones are deleted.
+const KEPT_LOGS: usize = 7;
+
+/// Logs to stderr and to a daily `hypr-recorder.<date>.log` in the state
+/// directory, filtered by `level`, an `EnvFilter` directive such as `debug`
+/// or `info,gstreamer=debug`. Keep the returned guard alive until exit so
+/// the file is flushed.
+pub fn init(level: &str) -> Option<WorkerGuard> {
+    let (level, invalid) = match EnvFilter::try_new(level) {
+        Ok(_) => (level, None),
+        Err(e) => (DEFAULT_LEVEL, Some(e)),
+    };
+
+    let dir = utils::state_dir();
+    // Old logs are pruned from the directory before it would be created.
+    let _ = fs::create_dir_all(&dir);
+    let appender = RollingFileAppender::builder()
+        .rotation(Rotation::DAILY)
+        .filename_prefix("hypr-recorder")
+        .filename_suffix("log")
+        .max_log_files(KEPT_LOGS)
+        .build(&dir);
+    let (file, guard, unavailable) = match appender {
+        Ok(appender) => {
+            let (writer, guard) = tracing_appender::non_blocking(appender);
+            let layer = fmt::layer()
+                .with_writer(writer)
+                .with_ansi(false)
+                .with_filter(EnvFilter::new(level));
+            (Some(layer), Some(guard), None)
+        }
+        Err(e) => (None, None, Some(e)),
+    };
+
+    let stderr = fmt::layer()
+        .with_writer(io::stderr)
+        .without_time()
+        .with_target(false)
+        .with_filter(EnvFilter::new(level));
+
+    // Span fields are formatted once, by the first layer; let that be the
+    // file's, which has no colours.
+    tracing_subscriber::registry()
+        .with(file)
+        .with(stderr)
+        .init();
+
+    if let Some(e) = invalid {
+        warn!("Invalid log level, using {DEFAULT_LEVEL}: {}", e);
+    }
+    if let Some(e) = unavailable {
+        warn!("Failed to open the log file: {}", e);
+    }
+
+    guard
+}
+
+/// Routes GStreamer's debug log into the same log, under the `gstreamer`
+/// target. `GST_DEBUG` still picks the categories; without it, only
+/// warnings and errors are forwarded.
+pub fn forward_gstreamer() -> Result<()> {
+    gst::init()?;
+
+    gst::log::remove_default_log_function();
+    if env::var_os("GST_DEBUG").is_none() {
+        gst::log::set_default_threshold(DebugLevel::Warning);
+    }
+    gst::log::set_active(true);
+
+    gst::log::add_log_function(
+        |category, level, _file, function, _line, object, message| {
+            let Some(message) = message.get() else {
+                return;
+            };
+            let category = category.name();
+            let object = object.map(ToString::to_string).unwrap_or_default();
+
+            match level {
+                DebugLevel::Error => error!(
+                    target: "gstreamer",
+                    category, %object, %function, "{message}"
+                ),
+                DebugLevel::Warning => warn!(
+                    target: "gstreamer",
+                    category, %object, %function, "{message}"
+                ),
+                DebugLevel::Fixme | DebugLevel::Info => info!(
+                    target: "gstreamer",
+                    category, %object, %function, "{message}"
+                ),
+                DebugLevel::Debug => debug!(
+                    target: "gstreamer",
+                    category, %object, %function, "{message}"
+                ),
+                _ => trace!(
+                    target: "gstreamer",
+                    category, %object, %function, "{message}"
+                ),
+            }
+        },
+    );
+
+    Ok(())
+}
@@ -1,184 +1,425 @@
-mod audio;
-mod error;
-mod input;
+mod cli;
+mod control;
+mod dbus;
+mod doctor;
+mod hooks;
+mod instance;
+mod library;
+mod logging;
 mod notification;
-mod recorder;
-mod transcriber;
-mod utils;
-
-use std::{
-    env,
-    time::{Duration, Instant},
+mod s3;
+mod search;
+mod upload;
+
+use std::{env, time::Duration};
+
+use clap::Parser;
+use cli::{Cli, Command, PruneArgs, RecordArgs, SessionArgs};
+use control::{ControlServer, Request, Response};
+use dbus::{DbusService, State};
+use hypr_recorder::{
+    capture,
+    command,
+    compositor,
+    input,
+    markers,
+    metadata::{self, Metadata},
+    naming,
+    pipeline,
+    recorder,
+    session,
+    transcriber::{self, Transcriber},
+    utils,
+    Error,
+    Result,
+    Session,
 };
-
-use input::{Action, Input};
-use notification::Notification;
-use recorder::Recorder;
-use tokio::time::{interval, sleep};
-use transcriber::Transcriber;
-
-pub use crate::error::*;
+use instance::InstanceLock;
+use library::Retention;
+use tokio::sync::{mpsc, oneshot};
+use tracing::{error, info, warn};
+use upload::Uploader;
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    println!("Starting Whisper-thing Audio Recorder");
+    let cli = Cli::parse();
+    let _log = logging::init(&cli.log_level);
+
+    match cli.command.unwrap_or(Command::Record(cli.record)) {
+        Command::Record(args) => record(args).await,
+        Command::Daemon(args) => daemon(args).await,
+        Command::Ctl { request } => ctl(request).await,
+        Command::List { json } => list(json),
+        Command::Show { id } => show(&id),
+        Command::Delete { id } => delete(&id),
+        Command::Prune(args) => prune(args),
+        Command::Search {
+            query,
+            limit,
+            json,
+            reindex,
+        } => search(&query.join(" "), limit, json, reindex),
+        Command::Upload { id } => upload(id).await,
+        Command::Doctor => doctor::run().await,
+        Command::Play { id, at } => search::play(&library::find(&id)?.path, at),
+    }
+}
+
+fn list(json: bool) -> Result<()> {
+    let recordings = library::list()?;
 
-    if env::var("HYPRLAND_INSTANCE_SIGNATURE").is_err() {
-        eprintln!(
-            "This application requires Hyprland. Please run it under Hyprland."
+    if json {
+        println!("{}", serde_json::to_string_pretty(&recordings)?);
+        return Ok(());
+    }
+
+    let width = recordings
+        .iter()
+        .map(|recording| recording.id.len())
+        .max()
+        .unwrap_or_default()
+        .max("ID".len());
+
+    println!(
+        "{:width$}  {:25}  {:>8}  {:>10}  TRANSCRIPT",
+        "ID", "STARTED", "DURATION", "SIZE"
+    );
+    for recording in &recordings {
+        println!(
+            "{:width$}  {:25}  {:>8}  {:>10}  {}",
+            recording.id,
+            recording.started_at,
+            duration(recording.duration_seconds),
+            library::format_size(recording.size),
+            if recording.transcript.is_some() {
+                "yes"
+            } else {
+                "no"
+            },
         );
+    }
+
+    Ok(())
+}
+
+fn show(id: &str) -> Result<()> {
+    let recording = library::find(id)?;
+
+    println!("ID:         {}", recording.id);
+    println!("Path:       {}", recording.path.display());
+    println!("Started:    {}", recording.started_at);
+    println!("Duration:   {}", duration(recording.duration_seconds));
+    println!("Size:       {}", library::format_size(recording.size));
+    match &recording.transcript {
+        Some(transcript) => println!("Transcript: {}", transcript.display()),
+        None => println!("Transcript: none"),
+    }
 
-        return Err(Error::HyprlandNotRunning);
+    println!("Files:");
+    for file in recording.files() {
+        println!("  {}", file.display());
     }
 
-    let mut recorder = Recorder::new().await?;
+    if let Some(metadata) = &recording.metadata {
+        println!("Metadata:");
+        println!("{}", std::fs::read_to_string(metadata)?.trim_end());
+    }
 
-    let mut notification = Notification::show()?;
+    Ok(())
+}
 
-    let mut key_handler = Input::new().await?;
+fn delete(id: &str) -> Result<()> {
+    let recording = library::find(id)?;
+    library::delete(&recording)?;
 
-    let transcriber = Transcriber::new();
+    println!("Deleted {}", recording.id);
+    Ok(())
+}
 
-    if let Err(e) = key_handler.register().await {
-        eprintln!("Failed to register keybindings: {}", e);
-        return Err(e);
+fn prune(args: PruneArgs) -> Result<()> {
+    let policy = if args.older_than.is_some() || args.max_size.is_some() {
+        Retention {
+            older_than: args.older_than,
+            max_size: args.max_size,
+        }
+    } else {
+        Retention::from_env()
+    };
+
+    if policy.is_empty() {
+        println!(
+            "Nothing to prune: pass --older-than/--max-size or set a retention policy"
+        );
+        return Ok(());
+    }
+
+    let removed = library::prune(&policy, None, args.dry_run)?;
+    let verb = if args.dry_run {
+        "Would delete"
+    } else {
+        "Deleted"
+    };
+
+    for recording in &removed {
+        println!(
+            "{verb} {} ({})",
+            recording.id,
+            library::format_size(recording.size)
+        );
+    }
+    if removed.is_empty() {
+        println!("No recordings to prune");
     }
 
-    recorder.start().await?;
+    Ok(())
+}
 
-    println!("Recording started. Press Enter to save, Esc to cancel.");
+fn search(query: &str, limit: usize, json: bool, reindex: bool) -> Result<()> {
+    if reindex {
+        let indexed = search::rebuild()?;
+        println!("Indexed {indexed} transcribed recording(s)");
 
-    let mut progress_interval = interval(Duration::from_millis(50));
-    let mut last_update = Instant::now();
+        if query.is_empty() {
+            return Ok(());
+        }
+    }
 
-    let result = loop {
-        tokio::select! {
-            _ = progress_interval.tick() => {
-                let elapsed = recorder.elapsed().unwrap_or_default();
+    let hits = search::Index::open()?.search(query, limit)?;
 
-                if elapsed >= Duration::from_secs(60) {
-                    println!("Recording reached 1-minute limit, auto-saving");
-                    if let Err(e) = key_handler.cleanup().await {
-                        eprintln!("Failed to cleanup keybindings before auto-save: {}", e);
-                    }
-                    break save_recording(&mut recorder, &mut notification, &transcriber)
-                        .await;
-                }
+    if json {
+        println!("{}", serde_json::to_string_pretty(&hits)?);
+        return Ok(());
+    }
 
-                if last_update.elapsed() >= Duration::from_millis(100) {
-                    if let Err(e) = notification.update(elapsed) {
-                        eprintln!("Failed to update notification: {}", e);
-                    }
-                    last_update = Instant::now();
-                }
+    for hit in &hits {
+        println!(
+            "{}  {}  {}",
+            hit.id,
+            markers::format_offset(hit.offset),
+            hit.snippet
+        );
+    }
 
-                if !recorder.is_recording() {
-                    println!("Recording stopped externally");
-                    if let Err(e) = key_handler.cleanup().await {
-                        eprintln!(
-                            "Failed to cleanup keybindings before external stop save: {}",
-                            e
-                        );
-                    }
-                    break save_recording(&mut recorder, &mut notification, &transcriber)
-                        .await;
-                }
-            }
+    match hits.first() {
+        Some(hit) => println!(
+            "\nOpen a match with: hypr-recorder play {} --at {}",
+            hit.id,
+            markers::format_offset(hit.offset)
+        ),
+        None => println!("No matches"),
+    }
 
-            key_result = key_handler.wait_for_input() => {
-                match key_result {
-                    Ok(Action::Save) => {
-                        println!("Save key pressed");
-                        if let Err(e) = key_handler.cleanup().await {
-                            eprintln!(
-                                "Failed to cleanup keybindings before manual save: {}",
-                                e
-                            );
-                        }
-                        break save_recording(&mut recorder, &mut notification, &transcriber)
-                            .await;
-                    }
-                    Ok(Action::Cancel) => {
-                        println!("Cancel key pressed");
-                        if let Err(e) = key_handler.cleanup().await {
-                            eprintln!(
-                                "Failed to cleanup keybindings before cancel: {}",
-                                e
-                            );
-                        }
-                        break cancel_recording(&mut recorder, &mut notification).await;
-                    }
-                    Err(e) => {
-                        eprintln!("Key handler error: {}", e);
-                        if let Err(cleanup_err) = key_handler.cleanup().await {
-                            eprintln!(
-                                "Failed to cleanup keybindings after error: {}",
-                                cleanup_err
-                            );
-                        }
-                        break cancel_recording(&mut recorder, &mut notification).await;
-                    }
-                }
-            }
+    Ok(())
+}
+
+async fn upload(id: Option<String>) -> Result<()> {
+    let uploader = Uploader::from_env()?.ok_or_else(|| {
+        Error::Upload("set HYPR_RECORDER_S3_ENDPOINT to enable uploads".into())
+    })?;
+
+    let Some(id) = id else {
+        let uploaded = uploader.retry_pending().await?;
+        for (path, url) in &uploaded {
+            println!("{}: {url}", library::id_of(path));
+        }
+        if uploaded.is_empty() {
+            println!("No queued uploads went through");
         }
+        return Ok(());
     };
 
-    if let Err(e) = key_handler.cleanup().await {
-        eprintln!("Failed to cleanup keybindings: {}", e);
+    let url = uploader.upload(&library::find(&id)?.path).await?;
+    println!("{url}");
+
+    if let Err(e) = upload::copy_to_clipboard(&url).await {
+        warn!("Failed to copy the link: {}", e);
     }
 
-    result
+    Ok(())
 }
 
-async fn save_recording(
-    recorder: &mut Recorder,
-    notification: &mut Notification,
-    transcriber: &Transcriber,
-) -> Result<()> {
-    println!("Saving recording...");
+fn duration(seconds: Option<f64>) -> String {
+    seconds
+        .map(|seconds| {
+            markers::format_offset(Duration::from_secs_f64(seconds.max(0.0)))
+        })
+        .unwrap_or_else(|| "-".into())
+}
 
-    let recording_path = match recorder.stop().await? {
-        Some(path) => path,
-        None => {
-            eprintln!("Recording did not produce any data");
-            notification.complete(false)?;
-            return Ok(());
+async fn ctl(request: Request) -> Result<()> {
+    match control::send(request).await? {
+        Response::Ok(message) => {
+            if !message.is_empty() {
+                println!("{message}");
+            }
+            Ok(())
         }
+        Response::Error(message) => Err(Error::Control(message)),
+    }
+}
+
+async fn record(args: RecordArgs) -> Result<()> {
+    info!("Starting Whisper-thing Audio Recorder");
+
+    let Some(_instance_lock) = InstanceLock::acquire()? else {
+        return instance::signal_running(args.if_running).await;
     };
 
-    println!("Recording saved to: {}", recording_path.display());
+    let mut session = build_session(&args.session).await?;
+    let mut control = ControlServer::bind().await?;
+    let dbus = start_dbus(&control, State::Recording).await;
 
-    if env::var("HYPR_RECORDER_TRANSCRIBE").is_ok() {
-        let transcript_path = match transcriber.start(&recording_path).await {
-            Ok(path) => path,
-            Err(e) => {
-                eprintln!("Failed to transcribe recording: {}", e);
-                let _ = notification.complete(false);
-                return Err(e);
-            }
-        };
+    run_session(&args.session, &mut session, &mut control, dbus.as_ref()).await
+}
 
-        println!("Transcription saved to: {}", transcript_path.display());
-    }
+/// Keeps GStreamer, the portal connection and the control endpoints alive
+/// between recordings, running a session whenever one is requested.
+async fn daemon(args: SessionArgs) -> Result<()> {
+    info!("Starting hypr-recorder daemon");
 
-    notification.complete(true)?;
+    let Some(_instance_lock) = InstanceLock::acquire()? else {
+        return Err(Error::AlreadyRunning);
+    };
 
-    sleep(Duration::from_secs(2)).await;
+    let mut session = build_session(&args).await?;
+    let mut control = ControlServer::bind().await?;
+    let dbus = start_dbus(&control, State::Idle).await;
+    let retries = tokio::spawn(upload::retry_periodically());
+
+    info!("Waiting for a start request");
+
+    while let Some(incoming) = control.recv().await {
+        match incoming.request() {
+            Request::Start | Request::Toggle => {
+                incoming.respond(Response::Ok(String::new()));
+
+                if let Err(e) = run_session(
+                    &args,
+                    &mut session,
+                    &mut control,
+                    dbus.as_ref(),
+                )
+                .await
+                {
+                    error!("Recording session failed: {}", e);
+                }
 
+                info!("Waiting for a start request");
+            }
+            Request::Status => {
+                incoming.respond(Response::Ok(State::Idle.to_string()))
+            }
+            _ => incoming
+                .respond(Response::Error("no recording in progress".into())),
+        }
+    }
+
+    retries.abort();
     Ok(())
 }
 
-async fn cancel_recording(
-    recorder: &mut Recorder,
-    notification: &mut Notification,
+async fn build_session(args: &SessionArgs) -> Result<Session> {
+    logging::forward_gstreamer()?;
+
+    let mut builder = Session::builder()
+        .source(args.source)
+        .persist_portal(args.persist_portal)
+        .on_error(args.on_error);
+    if env::var("HYPR_RECORDER_TRANSCRIBE").is_ok() {
+        builder = builder.transcriber(Transcriber::new());
+    }
+
+    builder.build().await
+}
+
+async fn start_dbus(
+    control: &ControlServer,
+    initial: State,
+) -> Option<DbusService> {
+    match DbusService::start(control.sender(), initial).await {
+        Ok(dbus) => Some(dbus),
+        Err(e) => {
+            warn!("D-Bus interface unavailable: {}", e);
+            None
+        }
+    }
+}
+
+/// Records once, from registering the keybindings until the recording is
+/// saved or discarded.
+async fn run_session(
+    args: &SessionArgs,
+    session: &mut Session,
+    control: &mut ControlServer,
+    dbus: Option<&DbusService>,
 ) -> Result<()> {
-    println!("Cancelling recording...");
+    let compositor = compositor::detect()?;
 
-    recorder.cancel().await?;
+    let metadata = match &compositor {
+        Some(compositor) => {
+            info!("Detected compositor: {}", compositor.name());
 
-    notification.complete(false)?;
+            let window = match compositor.focused_window().await {
+                Ok(window) => window,
+                Err(e) => {
+                    warn!("Failed to query focused window: {}", e);
+                    None
+                }
+            };
+            if let Some(window) = &window {
+                info!("Focused window: {window}");
+            }
 
-    sleep(Duration::from_secs(1)).await;
+            let monitor = match compositor.focused_monitor().await {
+                Ok(monitor) => monitor,
+                Err(e) => {
+                    warn!("Failed to query focused monitor: {}", e);
+                    None
+                }
+            };
 
-    Ok(())
+            Metadata::new(Some(compositor.name()), window, monitor)
+        }
+        None => {
+            info!("No supported compositor detected");
+            Metadata::new(None, None, None)
+        }
+    };
+
+    let mut key_handler = input::open(args.input, compositor)?;
+
+    if let Err(e) = key_handler.register().await {
+        error!("Failed to register keybindings: {}", e);
+        return Err(e);
+    }
+
+    let (actions, mut requested) = mpsc::channel(8);
+    let (arming, armed) = mpsc::channel(8);
+    let (link, uploaded) = oneshot::channel();
+    let followers = [
+        tokio::spawn(notification::follow(session.subscribe(), uploaded)),
+        tokio::spawn(hooks::follow(session.subscribe())),
+        tokio::spawn(library::follow(session.subscribe())),
+        tokio::spawn(upload::follow(session.subscribe(), link)),
+    ];
+    let keys = session.subscribe();
+    let requests = session.subscribe();
+    let published = session.subscribe();
+
+    let (result, ..) = tokio::join!(
+        session.run(metadata, &mut requested),
+        input::drive(key_handler.as_mut(), keys, actions.clone(), armed),
+        control::serve(control, requests, actions, arming),
+        async {
+            if let Some(dbus) = dbus {
+                dbus.follow(published).await;
+            }
+        },
+    );
+
+    for follower in followers {
+        let _ = follower.await;
+    }
+
+    result
 }
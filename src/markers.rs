@@ -0,0 +1,98 @@
+use std::{
+    env,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use serde::{Serialize, Serializer};
+use tokio::{fs, process::Command, time::timeout};
+use tracing::warn;
+
+use crate::{utils::run_command_async, Result};
+
+/// How long the label prompt may stay open before the marker goes unlabelled.
+const PROMPT_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// A point of interest in a recording.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Marker {
+    /// Position in the recording, excluding time spent paused.
+    #[serde(rename = "seconds", serialize_with = "as_secs")]
+    pub offset: Duration,
+    pub label: Option<String>,
+}
+
+impl Marker {
+    pub fn timestamp(&self) -> String {
+        format_offset(self.offset)
+    }
+}
+
+/// `MM:SS`, or `H:MM:SS` past the hour.
+pub fn format_offset(offset: Duration) -> String {
+    let secs = offset.as_secs();
+    let (hours, minutes, seconds) = (secs / 3600, secs / 60 % 60, secs % 60);
+
+    if hours > 0 {
+        format!("{hours}:{minutes:02}:{seconds:02}")
+    } else {
+        format!("{minutes:02}:{seconds:02}")
+    }
+}
+
+fn as_secs<S: Serializer>(
+    offset: &Duration,
+    serializer: S,
+) -> std::result::Result<S::Ok, S::Error> {
+    serializer.serialize_f64(offset.as_secs_f64())
+}
+
+/// Asks for a label through `HYPR_RECORDER_MARKER_PROMPT`, a shell command
+/// printing the label on stdout, e.g. `fuzzel --dmenu --prompt 'Marker: '`.
+///
+/// Without a prompt configured, or if it fails or is dismissed, the marker
+/// stays unlabelled.
+pub async fn prompt_label() -> Option<String> {
+    let command = env::var("HYPR_RECORDER_MARKER_PROMPT")
+        .ok()
+        .filter(|command| !command.trim().is_empty())?;
+
+    let mut prompt = Command::new("sh");
+    prompt.arg("-c").arg(&command).kill_on_drop(true);
+
+    let output = match timeout(PROMPT_TIMEOUT, run_command_async(prompt)).await
+    {
+        Ok(Ok(output)) if output.is_success() => output,
+        Ok(Ok(_)) => return None,
+        Ok(Err(e)) => {
+            warn!("Failed to run marker prompt: {}", e);
+            return None;
+        }
+        Err(_) => {
+            warn!("Marker prompt timed out");
+            return None;
+        }
+    };
+
+    let label = output.stdout.lines().next()?.trim();
+    (!label.is_empty()).then(|| label.to_string())
+}
+
+/// `<stem>.markers.json` next to `recording`.
+pub fn sidecar_path(recording: &Path) -> PathBuf {
+    let stem = recording
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "capture".into());
+
+    recording.with_file_name(format!("{stem}.markers.json"))
+}
+
+pub async fn write_sidecar(
+    recording: &Path,
+    markers: &[Marker],
+) -> Result<PathBuf> {
+    let path = sidecar_path(recording);
+    fs::write(&path, serde_json::to_vec_pretty(markers)?).await?;
+    Ok(path)
+}
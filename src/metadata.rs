@@ -0,0 +1,135 @@
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+use tokio::fs;
+
+use crate::{
+    compositor::Window,
+    naming::Naming,
+    pipeline::StopOutcome,
+    utils,
+    Result,
+};
+
+/// Encoder settings used for screen recordings.
+pub const VIDEO_ENCODER: &str =
+    "x264enc bitrate=20000 speed-preset=faster tune=zerolatency key-int-max=60";
+pub const AUDIO_ENCODER: &str = "avenc_aac bitrate=128000 compliance=-2";
+pub const MUXER: &str = "mp4mux faststart=true";
+
+/// A screen or window stream handed out by the portal.
+#[derive(Debug, Clone, Serialize)]
+pub struct VideoSource {
+    /// `monitor`, `window`, `synthetic` or `unknown`.
+    pub kind: String,
+    /// PipeWire node, for portal streams.
+    pub node_id: Option<u32>,
+    pub size: Option<(i32, i32)>,
+    pub position: Option<(i32, i32)>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum AudioSource {
+    /// The portal's virtual stream, usually the desktop audio.
+    Portal { node_id: u32 },
+    /// The default input device, recorded when the capture source has no audio.
+    Microphone { device: String },
+    /// A test tone from a synthetic capture source.
+    Synthetic,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Encoder {
+    pub video: &'static str,
+    pub audio: &'static str,
+    pub container: &'static str,
+}
+
+impl Default for Encoder {
+    fn default() -> Self {
+        Self {
+            video: VIDEO_ENCODER,
+            audio: AUDIO_ENCODER,
+            container: MUXER,
+        }
+    }
+}
+
+/// What the recorder knows about a recording it made.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Capture {
+    /// Local time, RFC 3339.
+    pub started_at: String,
+    pub ended_at: Option<String>,
+    /// Recorded time, excluding pauses.
+    pub duration_seconds: f64,
+    pub video_sources: Vec<VideoSource>,
+    pub audio_sources: Vec<AudioSource>,
+    pub encoder: Encoder,
+    /// How draining the pipeline went, once the recording was stopped.
+    pub stop: Option<StopOutcome>,
+    /// Whether the saved file could be demuxed from start to end.
+    pub verified: Option<bool>,
+}
+
+/// The `<stem>.json` sidecar written next to every saved recording.
+#[derive(Debug, Clone, Serialize)]
+pub struct Metadata {
+    pub app_version: &'static str,
+    pub hostname: Option<String>,
+    pub recording: PathBuf,
+    #[serde(flatten)]
+    pub capture: Capture,
+    pub compositor: Option<&'static str>,
+    /// The window and output focused when the recording started.
+    pub focused_window: Option<Window>,
+    pub monitor: Option<String>,
+    pub markers: Option<PathBuf>,
+    pub transcript: Option<PathBuf>,
+}
+
+impl Metadata {
+    /// Starts the metadata of a session; the recording itself is filled in
+    /// once saved.
+    pub fn new(
+        compositor: Option<&'static str>,
+        window: Option<Window>,
+        monitor: Option<String>,
+    ) -> Self {
+        Self {
+            app_version: env!("CARGO_PKG_VERSION"),
+            hostname: utils::hostname(),
+            recording: PathBuf::new(),
+            capture: Capture::default(),
+            compositor,
+            focused_window: window,
+            monitor,
+            markers: None,
+            transcript: None,
+        }
+    }
+
+    /// Values for the tokens of the recording's file name.
+    pub fn naming(&self) -> Naming {
+        Naming {
+            window_class: self
+                .focused_window
+                .as_ref()
+                .map(|window| window.class.clone()),
+            monitor: self.monitor.clone(),
+        }
+    }
+
+    /// Writes the sidecar, replacing any earlier version.
+    pub async fn write(&self) -> Result<PathBuf> {
+        let path = sidecar_path(&self.recording);
+        fs::write(&path, serde_json::to_vec_pretty(self)?).await?;
+        Ok(path)
+    }
+}
+
+/// `<stem>.json` next to `recording`.
+pub fn sidecar_path(recording: &Path) -> PathBuf {
+    recording.with_extension("json")
+}
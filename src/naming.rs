@@ -0,0 +1,297 @@
+use std::{
+    env,
+    fs,
+    path::{Component, Path, PathBuf},
+    time::SystemTime,
+};
+
+use crate::utils;
+
+/// Used unless `HYPR_RECORDER_FILENAME` says otherwise.
+pub const DEFAULT_TEMPLATE: &str = "capture_%Y%m%d_%H%M%S";
+const EXTENSION: &str = "mp4";
+
+/// What a recording's name may refer to besides the time.
+#[derive(Debug, Clone, Default)]
+pub struct Naming {
+    pub window_class: Option<String>,
+    pub monitor: Option<String>,
+}
+
+/// Where recordings are saved: `HYPR_RECORDER_OUTPUT_DIR`, or else the XDG
+/// videos directory. Without a home directory they go to the state
+/// directory, never the working directory, which the library would then
+/// list and prune.
+pub fn recordings_dir() -> PathBuf {
+    env::var_os("HYPR_RECORDER_OUTPUT_DIR")
+        .filter(|dir| !dir.is_empty())
+        .map(|dir| expand_home(Path::new(&dir)))
+        .or_else(videos_dir)
+        .unwrap_or_else(|| utils::state_dir().join("recordings"))
+}
+
+/// `~/Recordings`, where versions before the XDG videos directory saved
+/// recordings, while it still exists and no output directory is configured.
+pub fn legacy_recordings_dir() -> Option<PathBuf> {
+    if env::var_os("HYPR_RECORDER_OUTPUT_DIR")
+        .is_some_and(|dir| !dir.is_empty())
+    {
+        return None;
+    }
+
+    let dir = env::home_dir()?.join("Recordings");
+    let current = recordings_dir();
+    let separate = !dir.starts_with(&current) && !current.starts_with(&dir);
+    (separate && dir.is_dir()).then_some(dir)
+}
+
+/// `$XDG_VIDEOS_DIR`, then the entry in `user-dirs.dirs`, then `~/Videos`.
+fn videos_dir() -> Option<PathBuf> {
+    let home = env::home_dir()?;
+
+    if let Some(dir) = env::var_os("XDG_VIDEOS_DIR")
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute())
+    {
+        return Some(dir);
+    }
+
+    let config = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute())
+        .unwrap_or_else(|| home.join(".config"));
+    let configured = fs::read_to_string(config.join("user-dirs.dirs"))
+        .ok()
+        .and_then(|contents| user_dir(&contents, "XDG_VIDEOS_DIR", &home));
+
+    // xdg-user-dirs points disabled directories at the home directory.
+    Some(
+        configured
+            .filter(|dir| *dir != home)
+            .unwrap_or_else(|| home.join("Videos")),
+    )
+}
+
+/// Reads `KEY="$HOME/dir"` from the contents of `user-dirs.dirs`.
+fn user_dir(contents: &str, key: &str, home: &Path) -> Option<PathBuf> {
+    let value = contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.starts_with('#'))
+        .find_map(|line| {
+            line.strip_prefix(key)?.trim_start().strip_prefix('=')
+        })?
+        .trim()
+        .trim_matches('"');
+
+    let dir = match value.strip_prefix("$HOME") {
+        Some(rest) => home.join(rest.trim_start_matches('/')),
+        None => PathBuf::from(value),
+    };
+
+    dir.is_absolute().then_some(dir)
+}
+
+fn expand_home(path: &Path) -> PathBuf {
+    match (path.strip_prefix("~"), env::home_dir()) {
+        (Ok(rest), Some(home)) => home.join(rest),
+        _ => path.to_path_buf(),
+    }
+}
+
+/// Picks an unused path for a new recording.
+///
+/// The name comes from `HYPR_RECORDER_FILENAME`, a `strftime(3)` template
+/// without extension that may contain `/` for subdirectories, plus the tokens
+/// `{date}`, `{time}`, `{window_class}`, `{monitor}` and `{counter}`.
+pub fn recording_path(naming: &Naming) -> PathBuf {
+    let template = env::var("HYPR_RECORDER_FILENAME")
+        .ok()
+        .filter(|template| !template.trim().is_empty())
+        .unwrap_or_else(|| DEFAULT_TEMPLATE.into());
+
+    let name = render(&template, naming, SystemTime::now());
+    unique_path(&recordings_dir(), &name)
+}
+
+fn render(template: &str, naming: &Naming, time: SystemTime) -> String {
+    let template = template
+        .replace("{date}", "%Y-%m-%d")
+        .replace("{time}", "%H-%M-%S");
+
+    // Tokens are filled in after strftime so their values are never read as
+    // conversions, though `token` leaves no `%` in them anyway.
+    let name = utils::strftime(&template, time)
+        .replace("{window_class}", &token(naming.window_class.as_deref()))
+        .replace("{monitor}", &token(naming.monitor.as_deref()));
+
+    // Keep the recording inside the output directory.
+    let name = Path::new(&name)
+        .components()
+        .filter_map(|component| match component {
+            Component::Normal(part) => Some(part.to_string_lossy()),
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join("/");
+
+    if name.is_empty() {
+        utils::strftime(DEFAULT_TEMPLATE, time)
+    } else {
+        name
+    }
+}
+
+/// A token value that is safe as part of a file name.
+fn token(value: Option<&str>) -> String {
+    let value: String = value
+        .unwrap_or_default()
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || matches!(c, '-' | '_' | '.') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+
+    if value.trim_matches('_').is_empty() {
+        "unknown".into()
+    } else {
+        value
+    }
+}
+
+/// Numbers `{counter}` from 001, or appends `_2`, `_3`, ... when the name is
+/// taken and the template has no counter.
+fn unique_path(dir: &Path, name: &str) -> PathBuf {
+    let path = |name: &str| dir.join(format!("{name}.{EXTENSION}"));
+
+    if name.contains("{counter}") {
+        return (1..)
+            .map(|n| path(&name.replace("{counter}", &format!("{n:03}"))))
+            .find(|candidate| !candidate.exists())
+            .unwrap_or_else(|| path(name));
+    }
+
+    let first = path(name);
+    if !first.exists() {
+        return first;
+    }
+
+    (2..)
+        .map(|n| path(&format!("{name}_{n}")))
+        .find(|candidate| !candidate.exists())
+        .unwrap_or(first)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use tempfile::TempDir;
+
+    use super::*;
+
+    fn time() -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(1_714_572_192)
+    }
+
+    fn naming(window_class: Option<&str>, monitor: Option<&str>) -> Naming {
+        Naming {
+            window_class: window_class.map(Into::into),
+            monitor: monitor.map(Into::into),
+        }
+    }
+
+    #[test]
+    fn tokens_are_filled_in() {
+        let date = utils::strftime("%Y-%m-%d", time());
+        let clock = utils::strftime("%H-%M-%S", time());
+
+        assert_eq!(
+            render(
+                "{date}/{time}_{window_class}_{monitor}",
+                &naming(Some("org.mozilla.firefox"), Some("DP-1")),
+                time(),
+            ),
+            format!("{date}/{clock}_org.mozilla.firefox_DP-1")
+        );
+        assert_eq!(
+            render("%Y_{window_class}", &naming(None, None), time()),
+            format!("{}_unknown", utils::strftime("%Y", time()))
+        );
+    }
+
+    #[test]
+    fn token_values_are_made_safe() {
+        assert_eq!(
+            token(Some("Visual Studio/Code %d")),
+            "Visual_Studio_Code__d"
+        );
+        assert_eq!(token(Some("../..")), ".._..");
+        assert_eq!(token(Some("/%")), "unknown");
+        assert_eq!(token(Some("")), "unknown");
+    }
+
+    #[test]
+    fn names_stay_inside_the_output_directory() {
+        let naming = naming(None, None);
+
+        assert_eq!(render("../../etc/passwd", &naming, time()), "etc/passwd");
+        assert_eq!(render("/tmp/./capture", &naming, time()), "tmp/capture");
+        assert_eq!(
+            render("/..", &naming, time()),
+            utils::strftime(DEFAULT_TEMPLATE, time())
+        );
+    }
+
+    #[test]
+    fn taken_names_get_a_suffix() {
+        let dir = TempDir::new().unwrap();
+
+        let first = unique_path(dir.path(), "capture");
+        assert_eq!(first, dir.path().join("capture.mp4"));
+        fs::write(&first, "").unwrap();
+
+        let second = unique_path(dir.path(), "capture");
+        assert_eq!(second, dir.path().join("capture_2.mp4"));
+        fs::write(&second, "").unwrap();
+
+        assert_eq!(
+            unique_path(dir.path(), "capture"),
+            dir.path().join("capture_3.mp4")
+        );
+    }
+
+    #[test]
+    fn counters_count_from_one() {
+        let dir = TempDir::new().unwrap();
+
+        let first = unique_path(dir.path(), "take_{counter}");
+        assert_eq!(first, dir.path().join("take_001.mp4"));
+        fs::write(&first, "").unwrap();
+
+        assert_eq!(
+            unique_path(dir.path(), "take_{counter}"),
+            dir.path().join("take_002.mp4")
+        );
+    }
+
+    #[test]
+    fn user_dirs_entries_are_read() {
+        let home = Path::new("/home/user");
+        let contents = "# comment\nXDG_VIDEOS_DIR=\"$HOME/Movies\"\n";
+
+        assert_eq!(
+            user_dir(contents, "XDG_VIDEOS_DIR", home),
+            Some(PathBuf::from("/home/user/Movies"))
+        );
+        assert_eq!(user_dir(contents, "XDG_MUSIC_DIR", home), None);
+        assert_eq!(
+            user_dir("XDG_VIDEOS_DIR=\"relative\"", "XDG_VIDEOS_DIR", home),
+            None
+        );
+    }
+}
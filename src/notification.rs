@@ -1,18 +1,39 @@
-use std::time::Duration;
+use std::{sync::Arc, time::Duration};
 
-use crate::{utils::run, Error, Result};
+use tokio::{
+    sync::{broadcast, oneshot},
+    time::sleep,
+};
+use tracing::{debug, warn};
+
+use crate::{
+    command::{self, CommandRunner},
+    session::{self, Event},
+    Error,
+    Result,
+};
 
 pub struct Notification {
+    runner: Arc<dyn CommandRunner>,
     is_active: bool,
 }
 
 impl Notification {
     pub fn show() -> Result<Self> {
-        println!("Showing recording notification via swayosd");
+        Self::show_with(command::system())
+    }
 
-        Self::show_progress(0, 0)?;
+    /// Like [`Notification::show`], running swayosd-client through `runner`.
+    pub fn show_with(runner: Arc<dyn CommandRunner>) -> Result<Self> {
+        debug!("Showing recording notification via swayosd");
+
+        let notification = Self {
+            runner,
+            is_active: true,
+        };
+        notification.show_progress(0, 0)?;
 
-        Ok(Self { is_active: true })
+        Ok(notification)
     }
 
     pub fn update(&mut self, elapsed: Duration) -> Result<()> {
@@ -24,7 +45,7 @@ impl Notification {
         let progress_percent =
             (elapsed_secs as f32 / 60.0 * 100.0).min(100.0) as u32;
 
-        Self::show_progress(progress_percent, elapsed_secs)?;
+        self.show_progress(progress_percent, elapsed_secs)?;
         Ok(())
     }
 
@@ -37,50 +58,261 @@ impl Notification {
             ("Recording Cancelled", "dialog-warning")
         };
 
-        println!("Showing completion notification: saved={}", saved);
+        debug!("Showing completion notification: saved={}", saved);
 
-        let output = run!(
-            "swayosd-client",
-            "--custom-message",
-            message,
-            "--custom-icon",
-            icon
-        )?;
-
-        if output.is_failure() {
-            return Err(Error::Notification(format!(
-                "swayosd-client failed with status {}: {}",
-                output.status,
-                output.stderr.trim()
-            )));
-        }
+        show_message(self.runner.as_ref(), message, icon)
+    }
 
-        Ok(())
+    /// Completes a saved recording whose link was copied to the clipboard.
+    pub fn complete_uploaded(&mut self, url: &str) -> Result<()> {
+        self.is_active = false;
+
+        debug!("Showing completion notification: uploaded");
+
+        show_message(
+            self.runner.as_ref(),
+            &format!("Recording Saved, link copied: {url}"),
+            "emblem-shared",
+        )
+    }
+
+    /// Shows a one-off error, independently of any recording in progress.
+    pub fn error(message: &str) -> Result<()> {
+        Self::error_with(command::system(), message)
+    }
+
+    /// Like [`Notification::error`], running swayosd-client through `runner`.
+    pub fn error_with(
+        runner: Arc<dyn CommandRunner>,
+        message: &str,
+    ) -> Result<()> {
+        show_message(runner.as_ref(), message, "dialog-error")
     }
 
-    fn show_progress(percent: u32, elapsed_secs: u64) -> Result<()> {
+    fn show_progress(&self, percent: u32, elapsed_secs: u64) -> Result<()> {
         let message = format!("Recording: {elapsed_secs}s / 60s");
 
-        let progress = percent.to_string();
+        swayosd(
+            self.runner.as_ref(),
+            &[
+                "--custom-progress",
+                &percent.to_string(),
+                "--custom-progress-text",
+                &message,
+                "--custom-icon",
+                "audio-input-microphone",
+            ],
+        )
+    }
+}
+
+/// Shows a session's progress and outcome until it ends. A saved
+/// recording's completion waits for `uploaded`, the link to it if it was
+/// uploaded.
+pub async fn follow(
+    mut events: broadcast::Receiver<Event>,
+    uploaded: oneshot::Receiver<Option<String>>,
+) {
+    let mut notification = None;
+    let mut saved = false;
+    let mut uploaded = Some(uploaded);
+
+    while let Some(event) = session::next_event(&mut events).await {
+        let result = match &event {
+            Event::Starting => Notification::show().map(|shown| {
+                notification = Some(shown);
+            }),
+            Event::Progress(elapsed) => notification
+                .as_mut()
+                .map_or(Ok(()), |notification| notification.update(*elapsed)),
+            Event::Problem {
+                message,
+                fatal: true,
+            } => Notification::error(&format!("Recording failed: {message}")),
+            Event::Saved(_) => {
+                saved = true;
+                Ok(())
+            }
+            Event::Cancelled(_) => {
+                let result = complete(&mut notification, false);
+                sleep(Duration::from_secs(1)).await;
+                result
+            }
+            Event::Done if saved => {
+                let link = match uploaded.take() {
+                    Some(uploaded) => uploaded.await.ok().flatten(),
+                    None => None,
+                };
+                let result = match (notification.as_mut(), link) {
+                    (Some(notification), Some(url)) => {
+                        notification.complete_uploaded(&url)
+                    }
+                    _ => complete(&mut notification, true),
+                };
+                sleep(Duration::from_secs(2)).await;
+                result
+            }
+            Event::Failed { .. } => complete(&mut notification, false),
+            _ => Ok(()),
+        };
+
+        if let Err(e) = result {
+            warn!("Failed to update notification: {}", e);
+        }
+        if event.ends_session() {
+            break;
+        }
+    }
+}
+
+fn complete(
+    notification: &mut Option<Notification>,
+    saved: bool,
+) -> Result<()> {
+    match notification {
+        Some(notification) => notification.complete(saved),
+        None => Ok(()),
+    }
+}
+
+fn show_message(
+    runner: &dyn CommandRunner,
+    message: &str,
+    icon: &str,
+) -> Result<()> {
+    swayosd(
+        runner,
+        &["--custom-message", message, "--custom-icon", icon],
+    )
+}
+
+fn swayosd(runner: &dyn CommandRunner, args: &[&str]) -> Result<()> {
+    let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+    let output = runner.run("swayosd-client", &args)?;
+
+    if output.is_failure() {
+        return Err(Error::Notification(format!(
+            "swayosd-client failed with status {}: {}",
+            output.status,
+            output.stderr.trim()
+        )));
+    }
 
-        let output = run!(
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{command::ScriptedRunner, utils::Output};
+
+    /// The command line showing `percent` with `message`.
+    fn progress<'a>(percent: &'a str, message: &'a str) -> [&'a str; 7] {
+        [
             "swayosd-client",
             "--custom-progress",
-            progress,
+            percent,
             "--custom-progress-text",
-            &message,
+            message,
             "--custom-icon",
-            "audio-input-microphone"
-        )?;
-
-        if output.is_failure() {
-            return Err(Error::Notification(format!(
-                "swayosd-client failed with status {}: {}",
-                output.status,
-                output.stderr.trim()
-            )));
-        }
+            "audio-input-microphone",
+        ]
+    }
 
-        Ok(())
+    #[test]
+    fn progress_counts_up_to_a_minute() {
+        let runner = Arc::new(
+            ScriptedRunner::new()
+                .expect_success(&progress("0", "Recording: 0s / 60s"), "")
+                .expect_success(&progress("50", "Recording: 30s / 60s"), "")
+                .expect_success(&progress("100", "Recording: 90s / 60s"), ""),
+        );
+
+        let mut notification = Notification::show_with(runner.clone()).unwrap();
+        notification.update(Duration::from_secs(30)).unwrap();
+        notification.update(Duration::from_secs(90)).unwrap();
+
+        runner.assert_done();
+    }
+
+    #[test]
+    fn completion_ends_the_progress() {
+        let runner = Arc::new(
+            ScriptedRunner::new()
+                .expect_success(&progress("0", "Recording: 0s / 60s"), "")
+                .expect_success(
+                    &[
+                        "swayosd-client",
+                        "--custom-message",
+                        "Recording Cancelled",
+                        "--custom-icon",
+                        "dialog-warning",
+                    ],
+                    "",
+                ),
+        );
+
+        let mut notification = Notification::show_with(runner.clone()).unwrap();
+        notification.complete(false).unwrap();
+        // Anything after completion would be an unexpected command.
+        notification.update(Duration::from_secs(5)).unwrap();
+
+        runner.assert_done();
+    }
+
+    #[test]
+    fn uploaded_recordings_show_their_link() {
+        let runner = Arc::new(
+            ScriptedRunner::new()
+                .expect_success(&progress("0", "Recording: 0s / 60s"), "")
+                .expect_success(
+                    &[
+                        "swayosd-client",
+                        "--custom-message",
+                        "Recording Saved, link copied: https://example.com/a",
+                        "--custom-icon",
+                        "emblem-shared",
+                    ],
+                    "",
+                ),
+        );
+
+        let mut notification = Notification::show_with(runner.clone()).unwrap();
+        notification
+            .complete_uploaded("https://example.com/a")
+            .unwrap();
+
+        runner.assert_done();
+    }
+
+    #[test]
+    fn errors_fail_with_swayosd() {
+        let runner = Arc::new(ScriptedRunner::new().expect(
+            &[
+                "swayosd-client",
+                "--custom-message",
+                "Recording failed: no portal",
+                "--custom-icon",
+                "dialog-error",
+            ],
+            Output {
+                stdout: String::new(),
+                stderr: "no display\n".into(),
+                status: 1,
+            },
+        ));
+
+        let error = Notification::error_with(
+            runner.clone(),
+            "Recording failed: no portal",
+        )
+        .unwrap_err();
+
+        assert!(matches!(
+            error,
+            Error::Notification(message)
+                if message == "swayosd-client failed with status 1: no display"
+        ));
+        runner.assert_done();
     }
 }
@@ -0,0 +1,450 @@
+use std::{
+    env,
+    fmt,
+    path::Path,
+    process,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+use gstreamer::{self as gst, prelude::*, ClockTime};
+use serde::Serialize;
+use tracing::warn;
+
+use crate::{
+    metadata::{AUDIO_ENCODER, MUXER, VIDEO_ENCODER},
+    Error,
+    Result,
+};
+
+/// Elements the capture and fallback muxing pipelines are built from, with
+/// where to get them. Each capture source adds the ones its streams start
+/// with.
+pub const ELEMENTS: &[(&str, &str)] = &[
+    ("queue", "GStreamer core (gstreamer)"),
+    ("filesrc", "GStreamer core (gstreamer)"),
+    ("filesink", "GStreamer core (gstreamer)"),
+    ("videoconvert", "gst-plugins-base"),
+    ("audioconvert", "gst-plugins-base"),
+    ("audioresample", "gst-plugins-base"),
+    ("x264enc", "gst-plugins-ugly"),
+    ("avenc_aac", "gst-libav"),
+    ("h264parse", "gst-plugins-bad"),
+    ("mp4mux", "gst-plugins-good"),
+    ("qtdemux", "gst-plugins-good"),
+    ("wavparse", "gst-plugins-good"),
+];
+
+/// Stand-ins for the portal streams during the preflight and dry runs.
+const TEST_ELEMENTS: &[(&str, &str)] = &[
+    ("videotestsrc", "gst-plugins-base"),
+    ("audiotestsrc", "gst-plugins-base"),
+    ("fakesink", "GStreamer core (gstreamer)"),
+];
+
+/// Buffers each test stream sends during the preflight.
+const PREFLIGHT_BUFFERS: u32 = 5;
+/// How long the preflight's test streams may take to go through.
+const PREFLIGHT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long the dry run records before it is stopped.
+const DRY_RUN_LENGTH: Duration = Duration::from_millis(300);
+/// How long stopping and verifying the dry run may take.
+const DRY_RUN_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How stopping a recording went.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "outcome", rename_all = "snake_case")]
+pub enum StopOutcome {
+    /// EOS made it through the muxer to the file.
+    Clean,
+    /// The deadline passed first; the muxer may not have written its index.
+    TimedOut,
+    /// An element failed while draining.
+    Errored { message: String },
+}
+
+impl fmt::Display for StopOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Clean => write!(f, "clean"),
+            Self::TimedOut => write!(f, "timed out"),
+            Self::Errored { message } => write!(f, "errored: {message}"),
+        }
+    }
+}
+
+/// Launch description of a recording: `video` and, if any, `audio` are
+/// encoded and muxed into `sink`.
+pub fn description(video: &str, audio: Option<&str>, sink: &str) -> String {
+    let mut description = format!(
+        "{video} ! queue ! videoconvert ! queue ! {VIDEO_ENCODER} ! \
+         h264parse ! queue ! mux. "
+    );
+    if let Some(audio) = audio {
+        description.push_str(&format!(
+            "{audio} ! queue ! audioconvert ! audioresample ! \
+             {AUDIO_ENCODER} ! queue ! mux. "
+        ));
+    }
+    description.push_str(&format!("{MUXER} name=mux ! {sink}"));
+
+    description
+}
+
+/// [`ELEMENTS`] and the `source` elements missing from the registry, with
+/// their packages.
+pub fn missing_elements(
+    source: &[(&'static str, &'static str)],
+) -> Vec<(&'static str, &'static str)> {
+    source
+        .iter()
+        .chain(ELEMENTS)
+        .copied()
+        .filter(|(element, _)| gst::ElementFactory::find(element).is_none())
+        .collect()
+}
+
+/// Makes sure a recording can be built before the portal is asked for
+/// anything: every element, including the `source` ones, must be installed
+/// and a few test buffers must make it through the template, so caps
+/// negotiate. Quick enough for every launch; `doctor` also does a
+/// [`dry_run`], which writes and verifies a file.
+pub fn preflight(source: &[(&'static str, &'static str)]) -> Result<()> {
+    let mut missing = missing_elements(source);
+    missing.extend(
+        TEST_ELEMENTS.iter().copied().filter(|(element, _)| {
+            gst::ElementFactory::find(element).is_none()
+        }),
+    );
+    if !missing.is_empty() {
+        return Err(Error::MissingElements(
+            missing
+                .iter()
+                .map(|(element, package)| format!("{element} ({package})"))
+                .collect(),
+        ));
+    }
+
+    // The muxer only writes with faststart once it sees EOS, so rather than
+    // prerolling, the test streams run to their end.
+    let pipeline = launch(&description(
+        &format!("videotestsrc num-buffers={PREFLIGHT_BUFFERS}"),
+        Some(&format!("audiotestsrc num-buffers={PREFLIGHT_BUFFERS}")),
+        "fakesink",
+    ))?;
+    let Some(bus) = pipeline.bus() else {
+        return Err(Error::Preflight("pipeline has no bus".into()));
+    };
+
+    pipeline.set_state(gst::State::Playing)?;
+    let outcome = wait_for_eos(&bus, PREFLIGHT_TIMEOUT);
+    let _ = pipeline.set_state(gst::State::Null);
+
+    match outcome {
+        StopOutcome::Clean => Ok(()),
+        outcome => Err(Error::Preflight(format!(
+            "test streams did not go through the pipeline: {outcome}"
+        ))),
+    }
+}
+
+/// The first of [`TEST_ELEMENTS`] that is not installed, if any.
+pub fn missing_test_element() -> Option<&'static str> {
+    TEST_ELEMENTS
+        .iter()
+        .map(|(element, _)| *element)
+        .find(|element| gst::ElementFactory::find(element).is_none())
+}
+
+/// Records a moment of test streams with the recording template, then
+/// stops and verifies the file the same way real recordings are.
+pub fn dry_run() -> Result<()> {
+    let output = env::temp_dir()
+        .join(format!("hypr-recorder-preflight-{}.mp4", process::id()));
+    let result = record_test(&output);
+    let _ = std::fs::remove_file(&output);
+
+    result
+}
+
+fn record_test(output: &Path) -> Result<()> {
+    let description = description(
+        "videotestsrc is-live=true",
+        Some("audiotestsrc is-live=true"),
+        &format!("filesink location=\"{}\"", escape(output)),
+    );
+    let pipeline = launch(&description)?;
+
+    pipeline.set_state(gst::State::Playing)?;
+    thread::sleep(DRY_RUN_LENGTH);
+
+    match drain(&pipeline, DRY_RUN_TIMEOUT) {
+        StopOutcome::Clean => (),
+        outcome => {
+            return Err(Error::Preflight(format!(
+                "test recording did not stop cleanly: {outcome}"
+            )))
+        }
+    }
+
+    verify(output, DRY_RUN_TIMEOUT)?;
+    Ok(())
+}
+
+/// Sends EOS and waits until it has reached the sinks, an element fails or
+/// `deadline` passes, then shuts the pipeline down.
+pub fn drain(pipeline: &gst::Pipeline, deadline: Duration) -> StopOutcome {
+    let Some(bus) = pipeline.bus() else {
+        return StopOutcome::Errored {
+            message: "pipeline has no bus".into(),
+        };
+    };
+
+    // Sending EOS blocks for as long as a source is stuck pushing, holding
+    // the pipeline's state lock all the while.
+    let sender = {
+        let pipeline = pipeline.clone();
+        thread::spawn(move || pipeline.send_event(gst::event::Eos::new()))
+    };
+
+    let outcome = wait_for_eos(&bus, deadline);
+    if outcome != StopOutcome::Clean {
+        // Flushing fails the stuck push, which lets the sender and the
+        // state change below through.
+        for source in pipeline.iterate_sources().into_iter().flatten() {
+            for pad in source.src_pads() {
+                pad.push_event(gst::event::FlushStart::new());
+            }
+        }
+    }
+    let _ = sender.join();
+
+    if let Err(e) = pipeline.set_state(gst::State::Null) {
+        warn!("Failed to shut down pipeline: {}", e);
+    }
+
+    outcome
+}
+
+/// Demuxes a finished recording from start to end, returning its duration.
+pub fn verify(path: &Path, deadline: Duration) -> Result<Duration> {
+    let pipeline = launch("filesrc name=src ! qtdemux name=demux")?;
+    let (Some(src), Some(demux)) =
+        (pipeline.by_name("src"), pipeline.by_name("demux"))
+    else {
+        return Err(Error::Verification("no demuxer".into()));
+    };
+    src.set_property("location", path.to_string_lossy().as_ref());
+
+    let streams = Arc::new(AtomicUsize::new(0));
+    let weak = pipeline.downgrade();
+    let linked = Arc::clone(&streams);
+    demux.connect_pad_added(move |_, pad| {
+        let Some(pipeline) = weak.upgrade() else {
+            return;
+        };
+        let Ok(sink) = gst::ElementFactory::make("fakesink")
+            .property("sync", false)
+            .build()
+        else {
+            return;
+        };
+
+        if pipeline.add(&sink).is_ok()
+            && sink
+                .static_pad("sink")
+                .is_some_and(|sink_pad| pad.link(&sink_pad).is_ok())
+            && sink.sync_state_with_parent().is_ok()
+        {
+            linked.fetch_add(1, Ordering::Relaxed);
+        }
+    });
+
+    pipeline.set_state(gst::State::Playing)?;
+
+    let outcome = match pipeline.bus() {
+        Some(bus) => wait_for_eos(&bus, deadline),
+        None => StopOutcome::Errored {
+            message: "pipeline has no bus".into(),
+        },
+    };
+    let duration = pipeline.query_duration::<ClockTime>();
+    pipeline.set_state(gst::State::Null)?;
+
+    match outcome {
+        StopOutcome::Clean => (),
+        outcome => return Err(Error::Verification(outcome.to_string())),
+    }
+    if streams.load(Ordering::Relaxed) == 0 {
+        return Err(Error::Verification("no streams".into()));
+    }
+
+    Ok(duration
+        .map(|duration| Duration::from_nanos(duration.nseconds()))
+        .unwrap_or_default())
+}
+
+/// `path` as the inside of a quoted property value in a launch description.
+pub(crate) fn escape(path: &Path) -> String {
+    path.to_string_lossy()
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+}
+
+fn launch(description: &str) -> Result<gst::Pipeline> {
+    gst::parse::launch(description)?
+        .downcast::<gst::Pipeline>()
+        .map_err(|_| Error::Preflight(format!("not a pipeline: {description}")))
+}
+
+/// Waits for EOS or an error on `bus`, for at most `deadline`.
+pub(crate) fn wait_for_eos(bus: &gst::Bus, deadline: Duration) -> StopOutcome {
+    let message = bus.timed_pop_filtered(
+        ClockTime::from_nseconds(deadline.as_nanos() as u64),
+        &[gst::MessageType::Eos, gst::MessageType::Error],
+    );
+
+    match message.as_ref().map(|message| message.view()) {
+        Some(gst::MessageView::Eos(_)) => StopOutcome::Clean,
+        Some(gst::MessageView::Error(err)) => StopOutcome::Errored {
+            message: format!(
+                "{} from {}{}",
+                err.error(),
+                err.src()
+                    .map(|src| src.path_string().to_string())
+                    .unwrap_or_else(|| "the pipeline".into()),
+                err.debug()
+                    .map(|debug| format!(" ({debug})"))
+                    .unwrap_or_default()
+            ),
+        },
+        _ => StopOutcome::TimedOut,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, time::Instant};
+
+    use tempfile::TempDir;
+
+    use super::*;
+
+    /// Records test streams into `output` for `length`, then drains.
+    fn record(output: &Path, length: Duration) -> StopOutcome {
+        gst::init().unwrap();
+        let pipeline = launch(&description(
+            "videotestsrc is-live=true",
+            Some("audiotestsrc is-live=true"),
+            &format!("filesink location=\"{}\"", escape(output)),
+        ))
+        .unwrap();
+
+        pipeline.set_state(gst::State::Playing).unwrap();
+        thread::sleep(length);
+        drain(&pipeline, Duration::from_secs(10))
+    }
+
+    #[test]
+    #[ignore = "needs the GStreamer test source, encoder and muxer plugins"]
+    fn preflight_runs_test_streams_through_the_template() {
+        gst::init().unwrap();
+
+        preflight(&[]).unwrap();
+    }
+
+    #[test]
+    fn output_paths_survive_the_launch_syntax() {
+        gst::init().unwrap();
+        let path = Path::new("/tmp/a \"quoted\" \\ name.mp4");
+
+        let sink = gst::parse::launch(&format!(
+            "filesink location=\"{}\"",
+            escape(path)
+        ))
+        .unwrap();
+
+        assert_eq!(sink.property::<String>("location"), path.to_str().unwrap());
+    }
+
+    #[test]
+    fn preflight_names_missing_elements() {
+        gst::init().unwrap();
+
+        match preflight(&[("hypr-recorder-missing", "nowhere")]) {
+            Err(Error::MissingElements(missing)) => assert!(missing
+                .contains(&"hypr-recorder-missing (nowhere)".to_string())),
+            other => panic!("unexpected preflight result: {other:?}"),
+        }
+    }
+
+    #[test]
+    #[ignore = "needs the GStreamer test source, encoder and muxer plugins"]
+    fn drain_finishes_a_live_recording() {
+        let dir = TempDir::new().unwrap();
+        let output = dir.path().join("drained.mp4");
+
+        assert_eq!(
+            record(&output, Duration::from_millis(500)),
+            StopOutcome::Clean
+        );
+
+        let duration = verify(&output, Duration::from_secs(10)).unwrap();
+        assert!(duration > Duration::ZERO);
+    }
+
+    #[test]
+    #[ignore = "needs the GStreamer test source, encoder and muxer plugins"]
+    fn verify_rejects_a_truncated_recording() {
+        let dir = TempDir::new().unwrap();
+        let output = dir.path().join("truncated.mp4");
+        assert_eq!(
+            record(&output, Duration::from_millis(500)),
+            StopOutcome::Clean
+        );
+
+        let bytes = fs::read(&output).unwrap();
+        fs::write(&output, &bytes[..bytes.len() / 2]).unwrap();
+
+        assert!(verify(&output, Duration::from_secs(10)).is_err());
+    }
+
+    #[test]
+    #[ignore = "needs videotestsrc from gst-plugins-base"]
+    fn drain_times_out_when_eos_never_arrives() {
+        gst::init().unwrap();
+        let pipeline =
+            launch("videotestsrc is-live=true ! fakesink name=sink").unwrap();
+
+        // Swallow EOS on its way into the sink, as a stuck muxer would.
+        let pad = pipeline
+            .by_name("sink")
+            .unwrap()
+            .static_pad("sink")
+            .unwrap();
+        pad.add_probe(
+            gst::PadProbeType::EVENT_DOWNSTREAM,
+            |_, info| match &info.data {
+                Some(gst::PadProbeData::Event(event))
+                    if event.type_() == gst::EventType::Eos =>
+                {
+                    gst::PadProbeReturn::Drop
+                }
+                _ => gst::PadProbeReturn::Ok,
+            },
+        );
+        pipeline.set_state(gst::State::Playing).unwrap();
+
+        let started = Instant::now();
+        assert_eq!(
+            drain(&pipeline, Duration::from_millis(300)),
+            StopOutcome::TimedOut
+        );
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+}
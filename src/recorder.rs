@@ -1,96 +1,164 @@
 use std::{
-    env,
-    os::fd::{AsRawFd, FromRawFd, IntoRawFd, OwnedFd, RawFd},
     path::{Path, PathBuf},
-    sync::{
-        atomic::{AtomicBool, Ordering},
-        Arc,
-    },
-    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+    time::{Duration, Instant, SystemTime},
 };
 
-use ashpd::{
-    desktop::{
-        screencast::{CursorMode, Screencast, SourceType, Stream},
-        PersistMode,
-        Session,
-    },
-    Error as AshpdError,
-    PortalError as AshpdPortalError,
+#[cfg(feature = "cli")]
+use clap::ValueEnum;
+use gstreamer::{self as gst, prelude::*};
+use tokio::{
+    fs,
+    sync::mpsc::{self, UnboundedReceiver},
+    task,
+};
+use tracing::{error, info, instrument, warn};
+
+#[cfg(feature = "cpal")]
+use crate::audio::AudioRecorder;
+use crate::{
+    capture::{CaptureSource, Streams},
+    chapters,
+    markers::{self, Marker},
+    metadata::{AudioSource, Capture},
+    naming::{self, Naming},
+    pipeline::{self, StopOutcome},
+    utils,
+    Error,
+    Result,
 };
-use gstreamer::{self as gst, prelude::*, ClockTime};
-use tokio::{fs, task::JoinHandle, time::sleep};
-
-use crate::{audio::AudioRecorder, Error, Result};
 
-const RECORDING_LIMIT_SECS: u64 = 60;
+/// How long stopping may take unless `HYPR_RECORDER_STOP_TIMEOUT` says
+/// otherwise.
+const DEFAULT_STOP_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long muxing the fallback audio into a recording may take.
+const MUX_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// What to do with a recording whose pipeline failed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(ValueEnum))]
+pub enum OnError {
+    /// Finalise and keep whatever was recorded up to the error
+    Salvage,
+    /// Discard the recording
+    Cancel,
+}
 
 pub struct Recorder {
     pipeline: Option<gst::Pipeline>,
-    session: Option<Session<'static, Screencast<'static>>>,
-    remote_fd: Option<OwnedFd>,
+    source: Box<dyn CaptureSource>,
     recording_path: Option<PathBuf>,
     fallback_audio: Option<FallbackAudio>,
-    is_recording: Arc<AtomicBool>,
+    is_recording: bool,
     start_time: Option<Instant>,
-    timeout_task: Option<JoinHandle<()>>,
+    paused_at: Option<Instant>,
+    paused_total: Duration,
+    markers: Vec<Marker>,
+    capture: Option<Capture>,
+    problems: Option<UnboundedReceiver<Error>>,
+    failed: bool,
 }
 
 impl Recorder {
-    pub async fn new() -> Result<Self> {
+    /// Records what `source` provides.
+    pub async fn new(source: Box<dyn CaptureSource>) -> Result<Self> {
         gst::init()?;
+        // Missing plugins would otherwise only show after the portal dialog.
+        let elements = source.elements();
+        task::spawn_blocking(move || pipeline::preflight(elements))
+            .await
+            .unwrap_or_else(|e| Err(Error::Preflight(e.to_string())))?;
 
         Ok(Self {
             pipeline: None,
-            session: None,
-            remote_fd: None,
+            source,
             recording_path: None,
             fallback_audio: None,
-            is_recording: Arc::new(AtomicBool::new(false)),
+            is_recording: false,
             start_time: None,
-            timeout_task: None,
+            paused_at: None,
+            paused_total: Duration::ZERO,
+            markers: Vec::new(),
+            capture: None,
+            problems: None,
+            failed: false,
         })
     }
 
-    pub async fn start(&mut self) -> Result<()> {
-        if self.is_recording.load(Ordering::Relaxed) {
+    #[instrument(skip_all, fields(source = self.source.name()))]
+    pub async fn start(&mut self, naming: &Naming) -> Result<()> {
+        if self.is_recording {
             return Ok(());
         }
 
-        let output_path = Self::recording_path()?;
+        let output_path = naming::recording_path(naming);
         if let Some(parent) = output_path.parent() {
             fs::create_dir_all(parent).await?;
         }
 
-        let resources = Self::build_pipeline(&output_path).await?;
-        let mut fallback_audio = None;
-        if !resources.audio_attached {
-            let mut audio_recorder = AudioRecorder::new()?;
-            audio_recorder.start().await?;
-            fallback_audio = Some(FallbackAudio::new(
-                audio_recorder,
-                output_path.with_extension("wav"),
-            ));
+        info!("Opening {} capture source", self.source.name());
+        let streams = self.source.open().await?;
+        if let Err(e) = self.start_pipeline(streams, output_path).await {
+            self.source.close().await;
+            return Err(e);
         }
+        self.is_recording = true;
+
+        Ok(())
+    }
 
-        let pipeline = resources.pipeline;
+    /// Starts recording `streams` into `output_path`, with the microphone
+    /// standing in for missing audio.
+    #[instrument(
+        name = "pipeline",
+        skip_all,
+        fields(output = %output_path.display())
+    )]
+    async fn start_pipeline(
+        &mut self,
+        streams: Streams,
+        output_path: PathBuf,
+    ) -> Result<()> {
+        let (audio, audio_sources) = match streams.audio {
+            Some((audio, source)) => (Some(audio), vec![source]),
+            None => (None, Vec::new()),
+        };
+
+        let sink =
+            format!("filesink location=\"{}\"", pipeline::escape(&output_path));
+        let description =
+            pipeline::description(&streams.video, audio.as_deref(), &sink);
+        let pipeline = gst::parse::launch(&description)?
+            .downcast::<gst::Pipeline>()
+            .map_err(|_| {
+                Error::ScreenCapture(
+                    "Failed to create GStreamer pipeline".into(),
+                )
+            })?;
+
+        let (fallback_audio, audio_sources) = if audio_sources.is_empty() {
+            start_fallback_audio(&output_path).await?
+        } else {
+            (None, audio_sources)
+        };
+
+        self.problems = Some(watch_bus(&pipeline));
+        self.failed = false;
         pipeline.set_state(gst::State::Playing)?;
 
         self.pipeline = Some(pipeline);
-        self.session = Some(resources.session);
-        self.remote_fd = Some(resources.remote_fd);
         self.recording_path = Some(output_path);
         self.fallback_audio = fallback_audio;
         self.start_time = Some(Instant::now());
-        self.is_recording.store(true, Ordering::Relaxed);
-
-        let is_recording_flag = Arc::clone(&self.is_recording);
-        self.timeout_task = Some(tokio::spawn(async move {
-            sleep(Duration::from_secs(RECORDING_LIMIT_SECS)).await;
-            if is_recording_flag.swap(false, Ordering::Relaxed) {
-                println!("Recording stopped due to 1-minute timeout");
-            }
-        }));
+        self.paused_at = None;
+        self.paused_total = Duration::ZERO;
+        self.markers.clear();
+        self.capture = Some(Capture {
+            started_at: utils::rfc3339(SystemTime::now()),
+            video_sources: streams.video_sources,
+            audio_sources,
+            ..Capture::default()
+        });
 
         Ok(())
     }
@@ -102,7 +170,7 @@ impl Recorder {
     pub async fn cancel(&mut self) -> Result<()> {
         if let Some(path) = self.finish(true).await? {
             if let Err(err) = fs::remove_file(&path).await {
-                eprintln!(
+                warn!(
                     "Failed to remove cancelled recording {}: {err}",
                     path.display()
                 );
@@ -112,42 +180,138 @@ impl Recorder {
         Ok(())
     }
 
+    pub fn pause(&mut self) -> Result<()> {
+        if !self.is_recording() || self.is_paused() {
+            return Ok(());
+        }
+
+        if let Some(pipeline) = &self.pipeline {
+            pipeline.set_state(gst::State::Paused)?;
+        }
+
+        if let Some(fallback) = self.fallback_audio.as_mut() {
+            fallback.pause();
+        }
+
+        self.paused_at = Some(Instant::now());
+
+        Ok(())
+    }
+
+    pub fn resume(&mut self) -> Result<()> {
+        let Some(paused_at) = self.paused_at.take() else {
+            return Ok(());
+        };
+
+        self.paused_total += paused_at.elapsed();
+
+        if let Some(pipeline) = &self.pipeline {
+            pipeline.set_state(gst::State::Playing)?;
+        }
+
+        if let Some(fallback) = self.fallback_audio.as_mut() {
+            fallback.resume();
+        }
+
+        Ok(())
+    }
+
+    /// Waits for the next error or warning the pipeline reports while
+    /// recording. After an error, only stopping or cancelling is left.
+    pub async fn problem(&mut self) -> Option<Error> {
+        let problem = self.problems.as_mut()?.recv().await?;
+        if !matches!(problem, Error::PipelineWarning { .. }) {
+            self.failed = true;
+        }
+
+        Some(problem)
+    }
+
+    /// Records a marker at `offset`, as returned by [`Recorder::elapsed`].
+    pub fn add_marker(&mut self, offset: Duration, label: Option<String>) {
+        self.markers.push(Marker { offset, label });
+    }
+
+    /// Names the marker at `index` in [`Recorder::markers`], once its label
+    /// prompt has been answered.
+    pub fn label_marker(&mut self, index: usize, label: String) {
+        if let Some(marker) = self.markers.get_mut(index) {
+            marker.label = Some(label);
+        }
+    }
+
+    /// Markers of the current, or last finished, recording.
+    pub fn markers(&self) -> &[Marker] {
+        &self.markers
+    }
+
+    /// Sources and timing of the current, or last finished, recording.
+    pub fn capture(&self) -> Option<&Capture> {
+        self.capture.as_ref()
+    }
+
     pub fn is_recording(&self) -> bool {
-        self.is_recording.load(Ordering::Relaxed)
+        self.is_recording
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused_at.is_some()
     }
 
+    /// Recorded time so far, excluding any time spent paused.
     pub fn elapsed(&self) -> Option<Duration> {
-        self.start_time.map(|start| start.elapsed())
+        self.start_time.map(|start| {
+            let paused = self.paused_total
+                + self.paused_at.map(|at| at.elapsed()).unwrap_or_default();
+            start.elapsed().saturating_sub(paused)
+        })
     }
 
+    #[instrument(name = "stop", skip(self))]
     async fn finish(&mut self, discard: bool) -> Result<Option<PathBuf>> {
-        if let Some(handle) = self.timeout_task.take() {
-            handle.abort();
-        }
+        // A paused pipeline would never let EOS reach the muxer.
+        self.resume()?;
+
+        let was_recording = std::mem::take(&mut self.is_recording);
 
-        let was_recording = self.is_recording.swap(false, Ordering::Relaxed);
+        let duration = self.elapsed().unwrap_or_default();
+        if let Some(capture) = self.capture.as_mut() {
+            capture.ended_at = Some(utils::rfc3339(SystemTime::now()));
+            capture.duration_seconds = duration.as_secs_f64();
+        }
 
         if let Some(pipeline) = self.pipeline.take() {
-            pipeline.send_event(gst::event::Eos::new());
+            // The bus is read directly from here on.
             if let Some(bus) = pipeline.bus() {
-                let timeout = Some(ClockTime::from_mseconds(100));
-                while let Some(msg) = bus.timed_pop(timeout) {
-                    match msg.view() {
-                        gst::MessageView::Eos(_)
-                        | gst::MessageView::Error(_) => break,
-                        _ => (),
-                    }
-                }
+                bus.unset_sync_handler();
+            }
+            self.problems = None;
+
+            if std::mem::take(&mut self.failed) {
+                end_mux_inputs(&pipeline);
             }
-            pipeline.set_state(gst::State::Null)?;
-        }
 
-        if let Some(session) = self.session.take() {
-            let _ = session.close().await;
+            let deadline = stop_timeout();
+            let outcome = task::spawn_blocking(move || {
+                pipeline::drain(&pipeline, deadline)
+            })
+            .await
+            .unwrap_or_else(|e| StopOutcome::Errored {
+                message: e.to_string(),
+            });
+
+            match &outcome {
+                StopOutcome::Clean => info!("Pipeline drained"),
+                outcome => warn!("Pipeline did not drain: {outcome}"),
+            }
+            if let Some(capture) = self.capture.as_mut() {
+                capture.stop = Some(outcome);
+            }
         }
 
-        self.remote_fd = None;
+        self.source.close().await;
         self.start_time = None;
+        self.paused_total = Duration::ZERO;
 
         let fallback_audio_path = match self.fallback_audio.take() {
             Some(fallback) => fallback.stop(!discard)?,
@@ -180,11 +344,11 @@ impl Recorder {
                         let _ = fs::remove_file(&audio_path).await;
                     }
                     Err(err) => {
-                        eprintln!(
+                        warn!(
                             "Failed to merge fallback audio into recording {}: {err}",
                             path.display()
                         );
-                        eprintln!(
+                        warn!(
                             "Audio fallback saved separately at {}",
                             audio_path.display()
                         );
@@ -193,7 +357,26 @@ impl Recorder {
             }
 
             match fs::metadata(&path).await {
-                Ok(metadata) if metadata.len() > 0 => Ok(Some(path)),
+                Ok(metadata) if metadata.len() > 0 => {
+                    embed_chapters(&path, &self.markers).await;
+                    let verified = verify(&path).await;
+                    if let Some(capture) = self.capture.as_mut() {
+                        capture.verified = Some(verified);
+                    }
+
+                    if !self.markers.is_empty() {
+                        match markers::write_sidecar(&path, &self.markers).await
+                        {
+                            Ok(sidecar) => {
+                                info!("Markers saved to: {}", sidecar.display())
+                            }
+                            Err(e) => {
+                                warn!("Failed to write markers: {}", e)
+                            }
+                        }
+                    }
+                    Ok(Some(path))
+                }
                 Ok(_) | Err(_) => {
                     let _ = fs::remove_file(&path).await;
                     Ok(None)
@@ -207,19 +390,7 @@ impl Recorder {
         }
     }
 
-    fn recording_path() -> Result<PathBuf> {
-        let timestamp =
-            SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis();
-        let filename = format!("capture_{timestamp}.mp4");
-
-        let base_dir = env::home_dir()
-            .map(|dir| dir.join("Recordings"))
-            .or_else(|| env::current_dir().ok())
-            .unwrap_or_else(|| PathBuf::from("/tmp"));
-
-        Ok(base_dir.join(filename))
-    }
-
+    #[instrument(name = "mux", skip_all, fields(video = %video_path.display()))]
     async fn mux_with_fallback_audio(
         video_path: &Path,
         audio_path: &Path,
@@ -237,54 +408,20 @@ impl Recorder {
 
         fs::rename(video_path, &temp_video).await?;
 
-        let video_location = Self::escape_for_gst(&temp_video);
-        let audio_location = Self::escape_for_gst(audio_path);
-        let output_location = Self::escape_for_gst(video_path);
-
-        let mux_result = (|| -> Result<()> {
-            let description = format!(
-                "filesrc location=\"{video_location}\" ! qtdemux name=demux \
-                 demux.video_0 ! queue ! h264parse ! queue ! mux. \
-                 filesrc location=\"{audio_location}\" ! wavparse ! audioconvert ! audioresample ! \
-                 avenc_aac bitrate=128000 ! queue ! mux. \
-                 mp4mux name=mux faststart=true ! filesink location=\"{output_location}\""
-            );
-
-            let element = gst::parse::launch(&description)?;
-            let pipeline =
-                element.downcast::<gst::Pipeline>().map_err(|_| {
-                    Error::ScreenCapture(
-                        "Failed to create audio muxing pipeline".into(),
-                    )
-                })?;
-
-            pipeline.set_state(gst::State::Playing)?;
-
-            let bus = pipeline.bus().ok_or_else(|| {
-                Error::ScreenCapture(
-                    "Failed to retrieve GStreamer bus for audio muxing".into(),
-                )
-            })?;
-
-            let mut result = Ok(());
-            while let Some(msg) = bus.timed_pop(ClockTime::from_mseconds(500)) {
-                match msg.view() {
-                    gst::MessageView::Eos(_) => break,
-                    gst::MessageView::Error(err) => {
-                        result = Err(Error::ScreenCapture(format!(
-                            "Audio muxing error: {} (debug {:?})",
-                            err.error(),
-                            err.debug()
-                        )));
-                        break;
-                    }
-                    _ => (),
-                }
-            }
-
-            pipeline.set_state(gst::State::Null)?;
-            result
-        })();
+        let video_location = pipeline::escape(&temp_video);
+        let audio_location = pipeline::escape(audio_path);
+        let output_location = pipeline::escape(video_path);
+
+        let description = format!(
+            "filesrc location=\"{video_location}\" ! qtdemux name=demux \
+             demux.video_0 ! queue ! h264parse ! queue ! mux. \
+             filesrc location=\"{audio_location}\" ! wavparse ! audioconvert ! audioresample ! \
+             avenc_aac bitrate=128000 ! queue ! mux. \
+             mp4mux name=mux faststart=true ! filesink location=\"{output_location}\""
+        );
+        let mux_result = task::spawn_blocking(move || remux(&description))
+            .await
+            .unwrap_or_else(|e| Err(Error::ScreenCapture(e.to_string())));
 
         match mux_result {
             Ok(()) => {
@@ -297,204 +434,202 @@ impl Recorder {
             }
         }
     }
+}
 
-    fn escape_for_gst(path: &Path) -> String {
-        let mut value = path.to_string_lossy().to_string();
-        value = value.replace('\\', "\\\\");
-        value = value.replace('"', "\\\"");
-        value
+/// Runs a file-to-file pipeline until it has written everything, an
+/// element fails or [`MUX_TIMEOUT`] passes.
+fn remux(description: &str) -> Result<()> {
+    let pipeline = gst::parse::launch(description)?
+        .downcast::<gst::Pipeline>()
+        .map_err(|_| {
+            Error::ScreenCapture(
+                "Failed to create audio muxing pipeline".into(),
+            )
+        })?;
+    let bus = pipeline.bus().ok_or_else(|| {
+        Error::ScreenCapture(
+            "Failed to retrieve GStreamer bus for audio muxing".into(),
+        )
+    })?;
+
+    pipeline.set_state(gst::State::Playing)?;
+    let outcome = pipeline::wait_for_eos(&bus, MUX_TIMEOUT);
+    pipeline.set_state(gst::State::Null)?;
+
+    match outcome {
+        StopOutcome::Clean => Ok(()),
+        outcome => Err(Error::ScreenCapture(format!("Audio muxing {outcome}"))),
     }
+}
 
-    async fn build_pipeline(path: &Path) -> Result<PipelineResources> {
-        let screencast = Screencast::new().await?;
-        let session = screencast.create_session().await?;
-
-        let available_types = screencast.available_source_types().await?;
+/// `HYPR_RECORDER_STOP_TIMEOUT`, in seconds.
+fn stop_timeout() -> Duration {
+    std::env::var("HYPR_RECORDER_STOP_TIMEOUT")
+        .ok()
+        .and_then(|secs| secs.trim().parse::<f64>().ok())
+        .filter(|secs| secs.is_finite() && *secs > 0.0)
+        .map(Duration::from_secs_f64)
+        .unwrap_or(DEFAULT_STOP_TIMEOUT)
+}
 
-        let mut requested_types = SourceType::Monitor | SourceType::Window;
-        let audio_supported = available_types.contains(SourceType::Virtual);
-        if audio_supported {
-            requested_types |= SourceType::Virtual;
-        }
+/// Re-demuxes a saved recording, reporting whether it is complete.
+#[instrument(skip_all, fields(path = %path.display()))]
+/// Adds `markers` to the recording at `path` as chapters. They are in the
+/// markers sidecar regardless, so a failure is only reported.
+async fn embed_chapters(path: &Path, markers: &[Marker]) {
+    if markers.is_empty() {
+        return;
+    }
 
-        requested_types &= available_types;
+    let count = markers.len();
+    let file = path.to_path_buf();
+    let markers = markers.to_vec();
+    let embedded =
+        task::spawn_blocking(move || chapters::embed(&file, &markers))
+            .await
+            .unwrap_or_else(|e| Err(Error::Chapters(e.to_string())));
+
+    match embedded {
+        Ok(()) => info!("Embedded {count} chapter(s)"),
+        Err(e) => warn!("{}", e),
+    }
+}
 
-        if !requested_types.contains(SourceType::Monitor)
-            && !requested_types.contains(SourceType::Window)
-        {
-            return Err(Error::ScreenCapture(
-                "Portal did not advertise any monitor or window sources to capture"
-                    .into(),
-            ));
+async fn verify(path: &Path) -> bool {
+    let file = path.to_path_buf();
+    let deadline = stop_timeout();
+    let verified =
+        task::spawn_blocking(move || pipeline::verify(&file, deadline))
+            .await
+            .unwrap_or_else(|e| Err(Error::Verification(e.to_string())));
+
+    match verified {
+        Ok(duration) => {
+            info!("Verified recording: {:.1}s", duration.as_secs_f64());
+            true
         }
+        Err(e) => {
+            error!("{} may be damaged: {}", path.display(), e);
+            false
+        }
+    }
+}
 
-        screencast
-            .select_sources(
-                &session,
-                CursorMode::Embedded,
-                requested_types,
-                true,
-                None,
-                PersistMode::DoNot,
-            )
-            .await?
-            .response()?;
-
-        let start_request = match screencast.start(&session, None).await {
-            Ok(request) => request,
-            Err(err) => {
-                if let AshpdError::Portal(AshpdPortalError::Failed(message)) =
-                    &err
-                {
-                    if message.contains("No streams available") {
-                        return Err(Error::ScreenCapture(
-                            "Portal did not return any streams. Please ensure you selected a source and that audio capture is enabled in your compositor portal configuration."
-                                .into(),
-                        ));
-                    }
-                }
+/// Forwards errors and warnings posted while recording, as typed errors, to
+/// the returned channel. Other messages stay on the bus.
+fn watch_bus(pipeline: &gst::Pipeline) -> UnboundedReceiver<Error> {
+    let (sender, problems) = mpsc::unbounded_channel();
 
-                return Err(err.into());
+    if let Some(bus) = pipeline.bus() {
+        bus.set_sync_handler(move |_, message| match bus_problem(message) {
+            Some(problem) => {
+                let _ = sender.send(problem);
+                gst::BusSyncReply::Drop
             }
-        };
-
-        let streams = match start_request.response() {
-            Ok(streams) => streams,
-            Err(err) => {
-                if let AshpdError::Portal(AshpdPortalError::Failed(message)) =
-                    &err
-                {
-                    if message.contains("No streams available") {
-                        return Err(Error::ScreenCapture(
-                            "Portal did not return any streams. Please ensure you selected a source and that audio capture is enabled in your compositor portal configuration."
-                                .into(),
-                        ));
-                    }
-                }
+            None => gst::BusSyncReply::Pass,
+        });
+    }
 
-                return Err(err.into());
-            }
-        };
+    problems
+}
 
-        println!("Portal returned {} stream(s)", streams.streams().len());
-        for stream in streams.streams() {
-            println!(
-                "- node {} type {:?} id {:?}",
-                stream.pipe_wire_node_id(),
-                stream.source_type(),
-                stream.id()
+fn bus_problem(message: &gst::Message) -> Option<Error> {
+    let source = message.src();
+    let element = source
+        .map(|src| src.name().to_string())
+        .unwrap_or_else(|| "pipeline".into());
+    // Only the capture sources have no sink pads.
+    let from_capture = source
+        .and_then(|src| src.downcast_ref::<gst::Element>())
+        .is_some_and(|element| element.sink_pads().is_empty());
+
+    match message.view() {
+        gst::MessageView::Error(err) => {
+            warn!(
+                "GStreamer error from {element}: {} (debug {:?})",
+                err.error(),
+                err.debug()
             );
-        }
-
-        let remote_fd = screencast.open_pipe_wire_remote(&session).await?;
-
-        let (video_stream, audio_stream) =
-            Self::split_streams(streams.streams())?;
-
-        let remote = unsafe { OwnedFd::from_raw_fd(remote_fd.into_raw_fd()) };
-        let audio_attached = audio_stream.is_some();
-        let pipeline =
-            Self::create_pipeline(&remote, video_stream, audio_stream, path)?;
-
-        Ok(PipelineResources {
-            pipeline,
-            session,
-            remote_fd: remote,
-            audio_attached,
-        })
-    }
-
-    fn split_streams(streams: &[Stream]) -> Result<(Stream, Option<Stream>)> {
-        let mut video: Option<Stream> = None;
-        let mut audio: Option<Stream> = None;
 
-        for stream in streams.iter().cloned() {
-            match stream.source_type() {
-                Some(SourceType::Monitor) | Some(SourceType::Window) => {
-                    video = Some(stream)
-                }
-                Some(SourceType::Virtual) => audio = Some(stream),
-                None => {
-                    if stream
-                        .id()
-                        .map(|id| id.contains("audio"))
-                        .unwrap_or(false)
-                    {
-                        audio = Some(stream);
-                    } else {
-                        video = Some(stream);
-                    }
-                }
-            }
+            let message = err.error().to_string();
+            Some(if from_capture {
+                Error::CaptureSource { element, message }
+            } else {
+                Error::Pipeline { element, message }
+            })
         }
+        gst::MessageView::Warning(warning) => {
+            warn!(
+                "GStreamer warning from {element}: {} (debug {:?})",
+                warning.error(),
+                warning.debug()
+            );
 
-        match (video, audio) {
-            (Some(v), maybe_audio) => {
-                if maybe_audio.is_none() {
-                    eprintln!(
-                        "Portal did not supply an audio stream. Continuing with video-only recording."
-                    );
-                    eprintln!(
-                        "If you expected audio, ensure xdg-desktop-portal-hyprland and PipeWire are configured for audio capture."
-                    );
-                }
-                Ok((v, maybe_audio))
-            }
-            _ => Err(Error::ScreenCapture(
-                "Portal did not provide a video stream".into(),
-            )),
+            Some(Error::PipelineWarning {
+                element,
+                message: warning.error().to_string(),
+            })
         }
+        _ => None,
     }
+}
 
-    fn create_pipeline(
-        remote_fd: &OwnedFd,
-        video_stream: Stream,
-        audio_stream: Option<Stream>,
-        output_path: &Path,
-    ) -> Result<gst::Pipeline> {
-        let video_fd = Self::dup_fd(remote_fd.as_raw_fd())?;
-        let video_path = video_stream.pipe_wire_node_id();
-        let location = output_path.display();
-
-        let pipeline_description = if let Some(audio_stream) = audio_stream {
-            let audio_fd = Self::dup_fd(remote_fd.as_raw_fd())?;
-            let audio_path = audio_stream.pipe_wire_node_id();
-            format!(
-                "pipewiresrc fd={video_fd} path={video_path} do-timestamp=true ! queue ! videoconvert ! queue ! \
-                 x264enc bitrate=20000 speed-preset=faster tune=zerolatency key-int-max=60 ! h264parse ! queue ! mux. \
-                 pipewiresrc fd={audio_fd} path={audio_path} do-timestamp=true ! queue ! audioconvert ! audioresample ! \
-                 avenc_aac bitrate=128000 compliance=-2 ! queue ! mux. mp4mux name=mux faststart=true ! filesink location=\"{location}\""
-            )
-        } else {
-            format!(
-                "pipewiresrc fd={video_fd} path={video_path} do-timestamp=true ! queue ! videoconvert ! queue ! \
-                 x264enc bitrate=20000 speed-preset=faster tune=zerolatency key-int-max=60 ! h264parse ! queue ! mp4mux name=mux faststart=true ! filesink location=\"{location}\""
-            )
-        };
+/// After an error, EOS sent to the sources may never make it through;
+/// ending the muxer inputs directly still lets it finalise the file.
+fn end_mux_inputs(pipeline: &gst::Pipeline) {
+    let Some(mux) = pipeline.by_name("mux") else {
+        return;
+    };
 
-        let element = gst::parse::launch(&pipeline_description)?;
-        element.downcast::<gst::Pipeline>().map_err(|_| {
-            Error::ScreenCapture("Failed to create GStreamer pipeline".into())
-        })
+    for pad in mux.sink_pads() {
+        pad.send_event(gst::event::Eos::new());
     }
+}
 
-    fn dup_fd(fd: RawFd) -> Result<i32> {
-        let duplicated = unsafe { libc::dup(fd) };
-        if duplicated < 0 {
-            return Err(Error::Io(std::io::Error::last_os_error()));
-        }
-        Ok(duplicated)
-    }
+/// Records the microphone next to `output_path` for a source without
+/// audio.
+#[cfg(feature = "cpal")]
+async fn start_fallback_audio(
+    output_path: &Path,
+) -> Result<(Option<FallbackAudio>, Vec<AudioSource>)> {
+    let mut recorder = AudioRecorder::new()?;
+    recorder.start().await?;
+    let source = AudioSource::Microphone {
+        device: recorder.device_name(),
+    };
+
+    Ok((
+        Some(FallbackAudio {
+            recorder,
+            path: output_path.with_extension("wav"),
+        }),
+        vec![source],
+    ))
 }
 
+/// Without microphone support, sources without audio record silently.
+#[cfg(not(feature = "cpal"))]
+async fn start_fallback_audio(
+    _output_path: &Path,
+) -> Result<(Option<FallbackAudio>, Vec<AudioSource>)> {
+    info!("Recording without audio: built without microphone support");
+    Ok((None, Vec::new()))
+}
+
+#[cfg(feature = "cpal")]
 struct FallbackAudio {
     recorder: AudioRecorder,
     path: PathBuf,
 }
 
+#[cfg(feature = "cpal")]
 impl FallbackAudio {
-    fn new(recorder: AudioRecorder, path: PathBuf) -> Self {
-        Self { recorder, path }
+    fn pause(&mut self) {
+        self.recorder.pause();
+    }
+
+    fn resume(&mut self) {
+        self.recorder.resume();
     }
 
     fn stop(mut self, keep: bool) -> Result<Option<PathBuf>> {
@@ -509,9 +644,134 @@ impl FallbackAudio {
     }
 }
 
-struct PipelineResources {
-    pipeline: gst::Pipeline,
-    session: Session<'static, Screencast<'static>>,
-    remote_fd: OwnedFd,
-    audio_attached: bool,
+/// Never exists without microphone support.
+#[cfg(not(feature = "cpal"))]
+enum FallbackAudio {}
+
+#[cfg(not(feature = "cpal"))]
+impl FallbackAudio {
+    fn pause(&mut self) {
+        match *self {}
+    }
+
+    fn resume(&mut self) {
+        match *self {}
+    }
+
+    fn stop(self, _keep: bool) -> Result<Option<PathBuf>> {
+        match self {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, sync::OnceLock};
+
+    use tempfile::TempDir;
+    use tokio::sync::Mutex;
+
+    use super::*;
+    use crate::capture::SyntheticSource;
+
+    /// Recordings share the output directory, so they take turns.
+    static RECORDING: Mutex<()> = Mutex::const_new(());
+
+    /// Points recordings at a directory of their own, once per run.
+    fn output_dir() -> &'static Path {
+        static DIR: OnceLock<TempDir> = OnceLock::new();
+
+        DIR.get_or_init(|| {
+            let dir = TempDir::new().unwrap();
+            env::set_var("HYPR_RECORDER_OUTPUT_DIR", dir.path());
+            dir
+        })
+        .path()
+    }
+
+    /// A recorder of test patterns.
+    async fn recorder() -> Recorder {
+        output_dir();
+
+        Recorder::new(Box::new(SyntheticSource::new(true)))
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn synthetic_source_does_not_need_pipewire() {
+        output_dir();
+
+        let result = Recorder::new(Box::new(SyntheticSource::new(true))).await;
+        if let Err(Error::MissingElements(missing)) = &result {
+            assert!(!missing.iter().any(|m| m.starts_with("pipewiresrc")));
+        } else {
+            result.unwrap();
+        }
+    }
+
+    #[tokio::test]
+    #[ignore = "needs the GStreamer encoder and muxer plugins"]
+    async fn stop_keeps_a_verified_recording() {
+        let _turn = RECORDING.lock().await;
+        let mut recorder = recorder().await;
+
+        recorder.start(&Naming::default()).await.unwrap();
+        assert!(recorder.is_recording());
+        tokio::time::sleep(Duration::from_millis(500)).await;
+
+        let path = recorder.stop().await.unwrap().unwrap();
+        assert!(path.starts_with(output_dir()));
+        assert!(!recorder.is_recording());
+        let capture = recorder.capture().unwrap();
+        assert_eq!(capture.stop, Some(StopOutcome::Clean));
+        assert_eq!(capture.verified, Some(true));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    #[ignore = "needs the GStreamer encoder and muxer plugins"]
+    async fn markers_become_chapters() {
+        let _turn = RECORDING.lock().await;
+        let mut recorder = recorder().await;
+
+        recorder.start(&Naming::default()).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        recorder.add_marker(Duration::from_millis(300), Some("Intro".into()));
+        recorder.add_marker(Duration::from_millis(600), None);
+        tokio::time::sleep(Duration::from_millis(500)).await;
+
+        let path = recorder.stop().await.unwrap().unwrap();
+        assert_eq!(
+            chapters::read(&path).unwrap(),
+            [
+                (Duration::from_millis(300), "Intro".to_string()),
+                (Duration::from_millis(600), "Marker 2".to_string()),
+            ]
+        );
+        assert_eq!(recorder.capture().unwrap().verified, Some(true));
+        std::fs::remove_file(markers::sidecar_path(&path)).unwrap();
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    #[ignore = "needs the GStreamer encoder and muxer plugins"]
+    async fn cancel_removes_the_recording() {
+        let _turn = RECORDING.lock().await;
+        let mut recorder = recorder().await;
+
+        recorder.start(&Naming::default()).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(500)).await;
+
+        recorder.cancel().await.unwrap();
+        assert!(!recorder.is_recording());
+        assert_eq!(std::fs::read_dir(output_dir()).unwrap().count(), 0);
+    }
+
+    #[tokio::test]
+    #[ignore = "needs the GStreamer encoder and muxer plugins"]
+    async fn stop_without_start_keeps_nothing() {
+        let mut recorder = recorder().await;
+
+        assert_eq!(recorder.stop().await.unwrap(), None);
+    }
 }
@@ -0,0 +1,354 @@
+use std::{
+    env,
+    path::{Path, PathBuf},
+    process::Command,
+    time::Duration,
+};
+
+use rusqlite::{params, Connection};
+use serde::Serialize;
+use tracing::warn;
+
+use crate::{
+    library,
+    transcriber::{self, Cue},
+    utils,
+    Error,
+    Result,
+};
+
+const INDEX_NAME: &str = "transcripts.sqlite3";
+
+/// Player used by `play` unless `HYPR_RECORDER_PLAYER` says otherwise.
+const DEFAULT_PLAYER: &str = "mpv --start={seconds} {path}";
+
+/// A transcript cue matching a search.
+#[derive(Debug, Clone, Serialize)]
+pub struct Hit {
+    /// Recording id, as used by the library commands.
+    pub id: String,
+    pub path: PathBuf,
+    #[serde(rename = "seconds", serialize_with = "as_secs")]
+    pub offset: Duration,
+    /// The matching text, with the matched terms in `[brackets]`.
+    pub snippet: String,
+}
+
+fn as_secs<S: serde::Serializer>(
+    offset: &Duration,
+    serializer: S,
+) -> std::result::Result<S::Ok, S::Error> {
+    serializer.serialize_f64(offset.as_secs_f64())
+}
+
+/// SQLite FTS5 index of transcript cues, kept in the state directory.
+pub struct Index {
+    connection: Connection,
+}
+
+impl Index {
+    pub fn open() -> Result<Self> {
+        let dir = utils::state_dir();
+        std::fs::create_dir_all(&dir)?;
+
+        Self::with_connection(Connection::open(dir.join(INDEX_NAME))?)
+    }
+
+    /// An index in `connection`, created if it is not there yet.
+    fn with_connection(connection: Connection) -> Result<Self> {
+        connection.execute_batch(
+            "CREATE VIRTUAL TABLE IF NOT EXISTS cues USING fts5(
+                 path UNINDEXED,
+                 start UNINDEXED,
+                 text,
+                 tokenize = 'unicode61 remove_diacritics 2'
+             );",
+        )?;
+
+        Ok(Self { connection })
+    }
+
+    /// Replaces whatever was indexed for `recording` with `cues`.
+    pub fn add(&mut self, recording: &Path, cues: &[Cue]) -> Result<()> {
+        let path = recording.to_string_lossy();
+        let transaction = self.connection.transaction()?;
+
+        transaction.execute("DELETE FROM cues WHERE path = ?1", [&path])?;
+        {
+            let mut insert = transaction.prepare(
+                "INSERT INTO cues (path, start, text) VALUES (?1, ?2, ?3)",
+            )?;
+            for cue in cues {
+                insert.execute(params![
+                    path,
+                    cue.start.as_secs_f64(),
+                    cue.text
+                ])?;
+            }
+        }
+
+        transaction.commit()?;
+        Ok(())
+    }
+
+    /// Indexes the subtitles next to `recording`, returning whether there
+    /// were any.
+    fn add_subtitles(&mut self, recording: &Path) -> Result<bool> {
+        let Some(cues) = subtitles(recording) else {
+            return Ok(false);
+        };
+
+        self.add(recording, &cues)?;
+        Ok(true)
+    }
+
+    /// Empties the index and indexes `recordings` again, returning how many
+    /// had subtitles.
+    fn rebuild_from<'a>(
+        &mut self,
+        recordings: impl IntoIterator<Item = &'a Path>,
+    ) -> Result<usize> {
+        self.connection.execute("DELETE FROM cues", [])?;
+
+        let mut indexed = 0;
+        for recording in recordings {
+            if self.add_subtitles(recording)? {
+                indexed += 1;
+            }
+        }
+
+        Ok(indexed)
+    }
+
+    pub fn remove(&self, recording: &Path) -> Result<()> {
+        self.connection.execute(
+            "DELETE FROM cues WHERE path = ?1",
+            [recording.to_string_lossy()],
+        )?;
+        Ok(())
+    }
+
+    /// Best matches first. Every word of `query` must appear in a cue.
+    pub fn search(&self, query: &str, limit: usize) -> Result<Vec<Hit>> {
+        let Some(query) = match_expression(query) else {
+            return Ok(Vec::new());
+        };
+
+        let mut statement = self.connection.prepare(
+            "SELECT path, start, snippet(cues, 2, '[', ']', '…', 16)
+             FROM cues WHERE cues MATCH ?1 ORDER BY rank LIMIT ?2",
+        )?;
+
+        let rows =
+            statement.query_map(params![query, limit as i64], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, f64>(1)?,
+                    row.get::<_, String>(2)?,
+                ))
+            })?;
+
+        let mut hits = Vec::new();
+        for row in rows {
+            let (path, start, snippet) = row?;
+            let path = PathBuf::from(path);
+
+            hits.push(Hit {
+                id: library::id_of(&path),
+                path,
+                offset: Duration::from_secs_f64(start.max(0.0)),
+                snippet,
+            });
+        }
+
+        Ok(hits)
+    }
+}
+
+/// Quotes each word so FTS5 syntax in the query is matched literally.
+fn match_expression(query: &str) -> Option<String> {
+    let terms: Vec<_> = query
+        .split_whitespace()
+        .map(|word| format!("\"{}\"", word.replace('"', "\"\"")))
+        .collect();
+
+    (!terms.is_empty()).then(|| terms.join(" "))
+}
+
+/// Rebuilds the index from the subtitles next to every saved recording,
+/// returning how many recordings were indexed.
+pub fn rebuild() -> Result<usize> {
+    let recordings = library::list()?;
+
+    Index::open()?.rebuild_from(
+        recordings.iter().map(|recording| recording.path.as_path()),
+    )
+}
+
+/// Indexes a freshly transcribed recording from the subtitles next to it.
+/// Failures are only reported, as the transcript itself is already safe on
+/// disk.
+pub fn index_transcript(recording: &Path) {
+    match Index::open().and_then(|mut index| index.add_subtitles(recording)) {
+        Ok(true) => (),
+        Ok(false) => warn!("Failed to index transcript: no subtitles"),
+        Err(e) => warn!("Failed to index transcript: {}", e),
+    }
+}
+
+/// The cues of Whisper's `.srt` next to `recording`.
+fn subtitles(recording: &Path) -> Option<Vec<Cue>> {
+    let contents =
+        std::fs::read_to_string(recording.with_extension("srt")).ok()?;
+    Some(transcriber::parse_srt(&contents))
+}
+
+/// Opens `path` at `offset` with `HYPR_RECORDER_PLAYER`, a command whose
+/// `{path}` and `{seconds}` arguments are substituted.
+pub fn play(path: &Path, offset: Duration) -> Result<()> {
+    let template = env::var("HYPR_RECORDER_PLAYER")
+        .ok()
+        .filter(|player| !player.trim().is_empty())
+        .unwrap_or_else(|| DEFAULT_PLAYER.into());
+
+    let seconds = format!("{:.1}", offset.as_secs_f64());
+    let mut words = template.split_whitespace().map(|word| {
+        word.replace("{path}", &path.to_string_lossy())
+            .replace("{seconds}", &seconds)
+    });
+
+    let program = words
+        .next()
+        .ok_or_else(|| Error::Player("HYPR_RECORDER_PLAYER is empty".into()))?;
+    let status = Command::new(&program)
+        .args(words)
+        .status()
+        .map_err(|e| Error::Player(format!("failed to run {program}: {e}")))?;
+
+    if !status.success() {
+        return Err(Error::Player(format!("{program} exited with {status}")));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use tempfile::TempDir;
+
+    use super::*;
+
+    fn index() -> Index {
+        Index::with_connection(Connection::open_in_memory().unwrap()).unwrap()
+    }
+
+    fn cue(seconds: u64, text: &str) -> Cue {
+        Cue {
+            start: Duration::from_secs(seconds),
+            end: Duration::from_secs(seconds + 2),
+            text: text.to_string(),
+        }
+    }
+
+    fn paths(hits: &[Hit]) -> Vec<&Path> {
+        hits.iter().map(|hit| hit.path.as_path()).collect()
+    }
+
+    #[test]
+    fn words_are_matched_literally() {
+        assert_eq!(
+            match_expression("  quick   fox "),
+            Some(r#""quick" "fox""#.to_string())
+        );
+        assert_eq!(
+            match_expression(r#"say "hi" OR NOT*"#),
+            Some(r#""say" """hi""" "OR" "NOT*""#.to_string())
+        );
+        assert_eq!(match_expression(" \t "), None);
+    }
+
+    #[test]
+    fn every_word_must_appear_in_a_cue() {
+        let mut index = index();
+        let first = Path::new("/videos/capture_1.mp4");
+        let second = Path::new("/videos/capture_2.mp4");
+        index
+            .add(first, &[cue(0, "The quick brown fox"), cue(5, "jumps")])
+            .unwrap();
+        index.add(second, &[cue(3, "A quick detour")]).unwrap();
+
+        let hits = index.search("fox quick", 10).unwrap();
+        assert_eq!(paths(&hits), [first]);
+        assert_eq!(hits[0].offset, Duration::ZERO);
+        assert_eq!(hits[0].snippet, "The [quick] brown [fox]");
+
+        let hits = index.search("quick", 10).unwrap();
+        assert_eq!(hits.len(), 2);
+        assert!(index.search("   ", 10).unwrap().is_empty());
+    }
+
+    #[test]
+    fn query_syntax_is_not_interpreted() {
+        let mut index = index();
+        index
+            .add(Path::new("/videos/a.mp4"), &[cue(0, "fox AND hound")])
+            .unwrap();
+
+        assert_eq!(index.search("AND", 10).unwrap().len(), 1);
+        assert!(index.search("fox OR", 10).unwrap().is_empty());
+        assert!(index.search("\"unbalanced", 10).unwrap().is_empty());
+    }
+
+    #[test]
+    fn accents_are_ignored() {
+        let mut index = index();
+        index
+            .add(Path::new("/videos/a.mp4"), &[cue(7, "Un café crème")])
+            .unwrap();
+
+        let hits = index.search("cafe creme", 10).unwrap();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].offset, Duration::from_secs(7));
+    }
+
+    #[test]
+    fn adding_again_replaces_and_removing_forgets() {
+        let mut index = index();
+        let path = Path::new("/videos/a.mp4");
+        index.add(path, &[cue(0, "first take")]).unwrap();
+        index.add(path, &[cue(0, "second take")]).unwrap();
+
+        assert!(index.search("first", 10).unwrap().is_empty());
+        assert_eq!(index.search("take", 10).unwrap().len(), 1);
+
+        index.remove(path).unwrap();
+        assert!(index.search("take", 10).unwrap().is_empty());
+    }
+
+    #[test]
+    fn rebuilding_indexes_the_subtitles_next_to_recordings() {
+        let dir = TempDir::new().unwrap();
+        let transcribed = dir.path().join("capture_1.mp4");
+        let silent = dir.path().join("capture_2.mp4");
+        fs::write(
+            transcribed.with_extension("srt"),
+            "1\n00:00:04,000 --> 00:00:06,500\nHello there\n",
+        )
+        .unwrap();
+        let mut index = index();
+        index
+            .add(Path::new("/gone/capture_0.mp4"), &[cue(0, "Hello again")])
+            .unwrap();
+
+        let indexed = index
+            .rebuild_from([transcribed.as_path(), silent.as_path()])
+            .unwrap();
+
+        assert_eq!(indexed, 1);
+        let hits = index.search("hello", 10).unwrap();
+        assert_eq!(paths(&hits), [transcribed.as_path()]);
+        assert_eq!(hits[0].offset, Duration::from_secs(4));
+    }
+}
@@ -0,0 +1,693 @@
+use std::{fmt, future, time::Duration};
+
+use tokio::{
+    sync::{
+        broadcast::{self, error::RecvError},
+        mpsc,
+    },
+    task::{JoinError, JoinSet},
+    time::{interval, sleep, timeout},
+};
+use tracing::{error, info, instrument, warn};
+
+#[cfg(feature = "whisper")]
+use crate::transcriber::Transcriber;
+use crate::{
+    capture::{self, CaptureSource, Source},
+    input::Action,
+    markers::{self, Marker},
+    metadata::Metadata,
+    recorder::{OnError, Recorder},
+    Error,
+    Result,
+};
+
+/// Events a slow subscriber may fall behind by before it misses some.
+const EVENT_CAPACITY: usize = 64;
+/// Recordings are saved once they reach this length.
+const RECORDING_LIMIT: Duration = Duration::from_secs(60);
+/// How often [`Event::Progress`] is sent while recording.
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(100);
+/// How long saving waits for marker label prompts that are still open.
+const LABEL_GRACE: Duration = Duration::from_secs(10);
+
+/// Where a session is in its lifecycle.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum State {
+    #[default]
+    Idle,
+    Starting,
+    Recording,
+    Paused,
+    Stopping,
+    Transcribing,
+    Done,
+    Failed,
+}
+
+impl State {
+    /// The state `event` moves a session in this state to, or `None` if the
+    /// event cannot happen in this state.
+    pub fn next(self, event: &Event) -> Option<Self> {
+        use State::*;
+
+        let next = match (self, event) {
+            (Idle | Done | Failed, Event::Starting) => Starting,
+            (Starting, Event::Started) => Recording,
+            (Recording, Event::Paused) => Paused,
+            (Paused, Event::Resumed) => Recording,
+            (
+                Recording | Paused,
+                Event::Progress(_) | Event::Marker(_) | Event::Problem { .. },
+            ) => self,
+            (Recording | Paused, Event::Stopping) => Stopping,
+            (Stopping, Event::Saved(_) | Event::Cancelled(_)) => Stopping,
+            (Stopping, Event::Transcribing) => Transcribing,
+            (
+                Transcribing,
+                Event::Transcribed(_) | Event::Problem { fatal: false, .. },
+            ) => Transcribing,
+            (Stopping | Transcribing, Event::Done) => Done,
+            (
+                Starting | Recording | Paused | Stopping | Transcribing,
+                Event::Failed { .. },
+            ) => Failed,
+            _ => return None,
+        };
+
+        Some(next)
+    }
+
+    /// Whether a recording is under way, from starting until it is saved
+    /// and transcribed or discarded.
+    pub fn is_active(self) -> bool {
+        !matches!(self, Self::Idle | Self::Done | Self::Failed)
+    }
+}
+
+impl fmt::Display for State {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Idle => "idle",
+            Self::Starting => "starting",
+            Self::Recording => "recording",
+            Self::Paused => "paused",
+            Self::Stopping => "stopping",
+            Self::Transcribing => "transcribing",
+            Self::Done => "done",
+            Self::Failed => "failed",
+        };
+
+        f.write_str(name)
+    }
+}
+
+/// Something that happened to a session's recording. Events about the
+/// finished recording carry its metadata as written to the sidecar.
+#[derive(Debug, Clone)]
+pub enum Event {
+    Starting,
+    Started,
+    /// Recorded time so far, excluding pauses; sent periodically.
+    Progress(Duration),
+    Paused,
+    Resumed,
+    /// A marker was placed. Its label, if a prompt is configured, is only
+    /// attached to the recorder's marker once the prompt is answered.
+    Marker(Marker),
+    /// The pipeline reported a problem. Fatal ones end the recording,
+    /// which is then salvaged or cancelled as configured. A failed
+    /// transcription is reported as a problem that is not fatal, since the
+    /// recording is already saved.
+    Problem {
+        message: String,
+        fatal: bool,
+    },
+    Stopping,
+    Saved(Box<Metadata>),
+    Cancelled(Box<Metadata>),
+    Transcribing,
+    Transcribed(Box<Metadata>),
+    /// The session ended successfully.
+    Done,
+    /// The session ended with an error; the recording may be missing or
+    /// incomplete.
+    Failed {
+        message: String,
+        metadata: Box<Metadata>,
+    },
+}
+
+impl Event {
+    /// Whether this is the last event of a session.
+    pub fn ends_session(&self) -> bool {
+        matches!(self, Self::Done | Self::Failed { .. })
+    }
+}
+
+/// A subscriber's view of a session, kept current with [`Status::apply`].
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Status {
+    pub state: State,
+    pub elapsed: Duration,
+}
+
+impl Status {
+    pub fn apply(&mut self, event: &Event) {
+        if let Some(state) = self.state.next(event) {
+            self.state = state;
+        }
+
+        match event {
+            Event::Starting => self.elapsed = Duration::ZERO,
+            Event::Progress(elapsed) => self.elapsed = *elapsed,
+            _ => (),
+        }
+    }
+}
+
+/// Waits for the next event, skipping over any a slow subscriber missed.
+/// Returns `None` once the session is gone.
+pub async fn next_event(
+    events: &mut broadcast::Receiver<Event>,
+) -> Option<Event> {
+    loop {
+        match events.recv().await {
+            Ok(event) => return Some(event),
+            Err(RecvError::Lagged(missed)) => {
+                warn!("Missed {missed} session event(s)");
+            }
+            Err(RecvError::Closed) => return None,
+        }
+    }
+}
+
+/// Configures a [`Session`].
+#[derive(Default)]
+pub struct SessionBuilder {
+    source: Source,
+    persist_portal: bool,
+    capture_source: Option<Box<dyn CaptureSource>>,
+    on_error: Option<OnError>,
+    #[cfg(feature = "whisper")]
+    transcriber: Option<Transcriber>,
+}
+
+impl SessionBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records from one of the built-in sources.
+    pub fn source(mut self, source: Source) -> Self {
+        self.source = source;
+        self
+    }
+
+    /// Keeps a portal restore token so the source picker only shows once.
+    pub fn persist_portal(mut self, persist: bool) -> Self {
+        self.persist_portal = persist;
+        self
+    }
+
+    /// Records from `source` instead of a built-in one.
+    pub fn capture_source(mut self, source: Box<dyn CaptureSource>) -> Self {
+        self.capture_source = Some(source);
+        self
+    }
+
+    /// What to do with a recording whose pipeline fails, salvaging it
+    /// unless set.
+    pub fn on_error(mut self, on_error: OnError) -> Self {
+        self.on_error = Some(on_error);
+        self
+    }
+
+    /// Transcribes saved recordings with `transcriber`.
+    #[cfg(feature = "whisper")]
+    pub fn transcriber(mut self, transcriber: Transcriber) -> Self {
+        self.transcriber = Some(transcriber);
+        self
+    }
+
+    /// Sets up GStreamer and checks the pipeline can run.
+    pub async fn build(self) -> Result<Session> {
+        let source = self
+            .capture_source
+            .unwrap_or_else(|| capture::open(self.source, self.persist_portal));
+        let (events, _) = broadcast::channel(EVENT_CAPACITY);
+
+        Ok(Session {
+            recorder: Recorder::new(source).await?,
+            on_error: self.on_error.unwrap_or(OnError::Salvage),
+            #[cfg(feature = "whisper")]
+            transcriber: self.transcriber,
+            metadata: Metadata::new(None, None, None),
+            state: State::Idle,
+            events,
+        })
+    }
+}
+
+/// Records, and optionally transcribes, one recording after another from
+/// the same source, announcing every step as an [`Event`].
+pub struct Session {
+    recorder: Recorder,
+    on_error: OnError,
+    #[cfg(feature = "whisper")]
+    transcriber: Option<Transcriber>,
+    metadata: Metadata,
+    state: State,
+    events: broadcast::Sender<Event>,
+}
+
+impl Session {
+    pub fn builder() -> SessionBuilder {
+        SessionBuilder::new()
+    }
+
+    /// Receives the events from now on.
+    pub fn subscribe(&self) -> broadcast::Receiver<Event> {
+        self.events.subscribe()
+    }
+
+    pub fn state(&self) -> State {
+        self.state
+    }
+
+    pub fn recorder(&self) -> &Recorder {
+        &self.recorder
+    }
+
+    /// Metadata of the current, or last finished, recording.
+    pub fn metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+
+    /// Records once, acting on `actions` until the recording is saved, and
+    /// transcribed if the session has a transcriber, or discarded.
+    ///
+    /// `metadata` describes where the recording happens; the rest is filled
+    /// in as it goes. The recording is saved when it reaches the length
+    /// limit and cancelled if `actions` closes.
+    #[instrument(name = "session", skip_all)]
+    pub async fn run(
+        &mut self,
+        metadata: Metadata,
+        actions: &mut mpsc::Receiver<Action>,
+    ) -> Result<()> {
+        self.metadata = metadata;
+        self.emit(Event::Starting);
+
+        let result = match self.recorder.start(&self.metadata.naming()).await {
+            Ok(()) => {
+                self.emit(Event::Started);
+                self.record(actions).await
+            }
+            Err(e) => Err(e),
+        };
+
+        match &result {
+            Ok(()) => self.emit(Event::Done),
+            Err(e) => self.emit(Event::Failed {
+                message: e.to_string(),
+                metadata: Box::new(self.metadata.clone()),
+            }),
+        }
+
+        result
+    }
+
+    async fn record(
+        &mut self,
+        actions: &mut mpsc::Receiver<Action>,
+    ) -> Result<()> {
+        let mut progress = interval(PROGRESS_INTERVAL);
+        // Label prompts, answered with the index of their marker. Saving
+        // waits briefly for any still open; cancelling abandons them.
+        let mut labels = JoinSet::new();
+
+        loop {
+            let elapsed = self.recorder.elapsed().unwrap_or_default();
+            let limit = (!self.recorder.is_paused())
+                .then(|| RECORDING_LIMIT.saturating_sub(elapsed));
+
+            let action = tokio::select! {
+                _ = progress.tick() => {
+                    self.emit(Event::Progress(elapsed));
+                    continue;
+                }
+
+                _ = wait(limit) => {
+                    info!(
+                        "Recording reached the {}-second limit, saving",
+                        RECORDING_LIMIT.as_secs()
+                    );
+                    Action::Save
+                }
+
+                action = actions.recv() => action.unwrap_or(Action::Cancel),
+
+                Some(answer) = labels.join_next() => {
+                    self.apply_label(answer);
+                    continue;
+                }
+
+                Some(problem) = self.recorder.problem() => {
+                    let fatal =
+                        !matches!(problem, Error::PipelineWarning { .. });
+                    self.emit(Event::Problem {
+                        message: problem.to_string(),
+                        fatal,
+                    });
+                    if !fatal {
+                        warn!("{}", problem);
+                        continue;
+                    }
+
+                    error!("Recording failed: {}", problem);
+                    let outcome = match self.on_error {
+                        OnError::Salvage => {
+                            info!("Salvaging what was recorded");
+                            self.save(&mut labels).await
+                        }
+                        OnError::Cancel => self.cancel().await,
+                    };
+
+                    return outcome.and(Err(problem));
+                }
+            };
+
+            match action {
+                Action::Save => return self.save(&mut labels).await,
+                Action::Cancel => return self.cancel().await,
+                Action::Pause => self.toggle_pause(),
+                Action::Marker => self.add_marker(&mut labels),
+            }
+        }
+    }
+
+    fn toggle_pause(&mut self) {
+        let (result, event) = if self.recorder.is_paused() {
+            (self.recorder.resume(), Event::Resumed)
+        } else {
+            (self.recorder.pause(), Event::Paused)
+        };
+
+        match result {
+            Ok(()) => {
+                info!(
+                    "Recording {}",
+                    self.state.next(&event).unwrap_or_default()
+                );
+                self.emit(event);
+            }
+            Err(e) => warn!("Failed to toggle pause: {}", e),
+        }
+    }
+
+    /// Places a marker now and asks for its label in the background, as the
+    /// prompt may stay open a while.
+    fn add_marker(&mut self, labels: &mut JoinSet<(usize, Option<String>)>) {
+        let offset = self.recorder.elapsed().unwrap_or_default();
+        let index = self.recorder.markers().len();
+        info!("Marker at {:.1}s", offset.as_secs_f64());
+
+        self.recorder.add_marker(offset, None);
+        self.emit(Event::Marker(Marker {
+            offset,
+            label: None,
+        }));
+
+        labels.spawn(async move { (index, markers::prompt_label().await) });
+    }
+
+    fn apply_label(
+        &mut self,
+        answer: std::result::Result<(usize, Option<String>), JoinError>,
+    ) {
+        if let Ok((index, Some(label))) = answer {
+            info!("Marker {} labelled: {label}", index + 1);
+            self.recorder.label_marker(index, label);
+        }
+    }
+
+    /// Gives label prompts still open a moment to be answered, with the
+    /// recording paused so the wait does not end up in it.
+    async fn await_labels(
+        &mut self,
+        labels: &mut JoinSet<(usize, Option<String>)>,
+    ) {
+        if labels.is_empty() {
+            return;
+        }
+
+        info!("Waiting for {} marker label(s)", labels.len());
+        if !self.recorder.is_paused() {
+            if let Err(e) = self.recorder.pause() {
+                warn!("Failed to pause while waiting for labels: {}", e);
+            }
+        }
+
+        let answered = timeout(LABEL_GRACE, async {
+            while let Some(answer) = labels.join_next().await {
+                self.apply_label(answer);
+            }
+        })
+        .await;
+
+        if answered.is_err() {
+            warn!(
+                "{} marker label(s) not answered in time, left unlabelled",
+                labels.len()
+            );
+            labels.abort_all();
+        }
+    }
+
+    async fn save(
+        &mut self,
+        labels: &mut JoinSet<(usize, Option<String>)>,
+    ) -> Result<()> {
+        info!("Saving recording...");
+        self.emit(Event::Stopping);
+        self.await_labels(labels).await;
+
+        let recording = self.recorder.stop().await;
+        self.metadata.capture =
+            self.recorder.capture().cloned().unwrap_or_default();
+        let Some(recording) = recording? else {
+            return Err(Error::ScreenCapture(
+                "recording did not produce any data".into(),
+            ));
+        };
+
+        info!("Recording saved to: {}", recording.display());
+
+        self.metadata.recording = recording.clone();
+        self.metadata.markers = (!self.recorder.markers().is_empty())
+            .then(|| markers::sidecar_path(&recording));
+        self.write_metadata().await;
+        self.emit(Event::Saved(Box::new(self.metadata.clone())));
+
+        #[cfg(feature = "whisper")]
+        if let Some(transcriber) = self.transcriber.clone() {
+            self.emit(Event::Transcribing);
+
+            match transcriber.start(&recording, self.recorder.markers()).await {
+                Ok(transcript) => {
+                    info!("Transcription saved to: {}", transcript.display());
+
+                    self.metadata.transcript = Some(transcript);
+                    self.write_metadata().await;
+                    self.emit(Event::Transcribed(Box::new(
+                        self.metadata.clone(),
+                    )));
+                }
+                Err(e) => {
+                    warn!("Transcription failed: {}", e);
+                    self.emit(Event::Problem {
+                        message: format!("Transcription failed: {e}"),
+                        fatal: false,
+                    });
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn cancel(&mut self) -> Result<()> {
+        info!("Cancelling recording...");
+        self.emit(Event::Stopping);
+
+        let cancelled = self.recorder.cancel().await;
+        self.metadata.capture =
+            self.recorder.capture().cloned().unwrap_or_default();
+        cancelled?;
+
+        self.emit(Event::Cancelled(Box::new(self.metadata.clone())));
+        Ok(())
+    }
+
+    async fn write_metadata(&self) {
+        match self.metadata.write().await {
+            Ok(path) => info!("Metadata saved to: {}", path.display()),
+            Err(e) => warn!("Failed to write metadata: {}", e),
+        }
+    }
+
+    /// Moves to the state `event` leads to and announces it.
+    fn emit(&mut self, event: Event) {
+        let Some(state) = self.state.next(&event) else {
+            error!(
+                "Not sending {event:?}, which cannot happen while {}",
+                self.state
+            );
+            return;
+        };
+        self.state = state;
+
+        // Nobody listening is fine.
+        let _ = self.events.send(event);
+    }
+}
+
+/// Sleeps for `duration`, or forever without one.
+async fn wait(duration: Option<Duration>) {
+    match duration {
+        Some(duration) => sleep(duration).await,
+        None => future::pending().await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn metadata() -> Box<Metadata> {
+        Box::new(Metadata::new(None, None, None))
+    }
+
+    fn problem(fatal: bool) -> Event {
+        Event::Problem {
+            message: "encoder stalled".into(),
+            fatal,
+        }
+    }
+
+    fn failed() -> Event {
+        Event::Failed {
+            message: "portal closed".into(),
+            metadata: metadata(),
+        }
+    }
+
+    /// The states `events` move an idle session through.
+    fn walk(events: &[Event]) -> Vec<State> {
+        let mut state = State::Idle;
+        events
+            .iter()
+            .map(|event| {
+                state = state.next(event).unwrap_or_else(|| {
+                    panic!("{event:?} rejected while {state}")
+                });
+                state
+            })
+            .collect()
+    }
+
+    #[test]
+    fn saved_and_transcribed_recording() {
+        use State::*;
+
+        let states = walk(&[
+            Event::Starting,
+            Event::Started,
+            Event::Progress(Duration::from_secs(1)),
+            Event::Paused,
+            Event::Marker(Marker {
+                offset: Duration::from_secs(1),
+                label: None,
+            }),
+            Event::Resumed,
+            Event::Stopping,
+            Event::Saved(metadata()),
+            Event::Transcribing,
+            Event::Transcribed(metadata()),
+            Event::Done,
+        ]);
+
+        assert_eq!(
+            states,
+            [
+                Starting,
+                Recording,
+                Recording,
+                Paused,
+                Paused,
+                Recording,
+                Stopping,
+                Stopping,
+                Transcribing,
+                Transcribing,
+                Done,
+            ]
+        );
+    }
+
+    #[test]
+    fn failed_transcription_still_ends_done() {
+        let states = walk(&[
+            Event::Starting,
+            Event::Started,
+            Event::Stopping,
+            Event::Saved(metadata()),
+            Event::Transcribing,
+            problem(false),
+            Event::Done,
+        ]);
+
+        assert_eq!(states[5], State::Transcribing);
+        assert_eq!(states.last(), Some(&State::Done));
+        assert_eq!(State::Transcribing.next(&problem(true)), None);
+    }
+
+    #[test]
+    fn sessions_can_fail_until_they_end() {
+        use State::*;
+
+        for state in [Starting, Recording, Paused, Stopping, Transcribing] {
+            assert_eq!(state.next(&failed()), Some(Failed), "while {state}");
+        }
+        for state in [Idle, Done, Failed] {
+            assert_eq!(state.next(&failed()), None, "while {state}");
+        }
+    }
+
+    #[test]
+    fn ended_sessions_can_start_again() {
+        use State::*;
+
+        for state in [Idle, Done, Failed] {
+            assert_eq!(state.next(&Event::Starting), Some(Starting));
+        }
+        for state in [Starting, Recording, Paused, Stopping, Transcribing] {
+            assert_eq!(state.next(&Event::Starting), None, "while {state}");
+        }
+    }
+
+    #[test]
+    fn out_of_order_events_are_rejected() {
+        use State::*;
+
+        assert_eq!(Idle.next(&Event::Started), None);
+        assert_eq!(Idle.next(&Event::Progress(Duration::ZERO)), None);
+        assert_eq!(Starting.next(&Event::Stopping), None);
+        assert_eq!(Recording.next(&Event::Resumed), None);
+        assert_eq!(Paused.next(&Event::Paused), None);
+        assert_eq!(Recording.next(&Event::Done), None);
+        assert_eq!(Stopping.next(&Event::Transcribed(metadata())), None);
+        assert_eq!(Transcribing.next(&Event::Saved(metadata())), None);
+    }
+}
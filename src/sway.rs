@@ -0,0 +1,484 @@
+use std::{
+    env,
+    io::{self, Read, Write},
+    os::unix::net::UnixStream as StdUnixStream,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use serde::Deserialize;
+use serde_json::Value;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::UnixStream,
+    time::timeout,
+};
+
+use crate::{Error, Result};
+
+const MAGIC: &[u8; 6] = b"i3-ipc";
+const HEADER_LEN: usize = MAGIC.len() + 8;
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(2);
+
+const RUN_COMMAND: u32 = 0;
+const SUBSCRIBE: u32 = 2;
+const GET_TREE: u32 = 4;
+const GET_CONFIG: u32 = 9;
+
+/// Client for sway's i3-compatible IPC socket (`$SWAYSOCK`).
+///
+/// Every message is framed as `i3-ipc`, a little-endian payload length and
+/// message type, then a JSON payload.
+#[derive(Debug, Clone)]
+pub struct SwayClient {
+    socket: PathBuf,
+}
+
+#[derive(Debug, Deserialize)]
+struct CommandOutcome {
+    success: bool,
+    #[serde(default)]
+    error: Option<String>,
+}
+
+impl SwayClient {
+    pub fn from_env() -> Result<Self> {
+        let socket = env::var("SWAYSOCK").map_err(|_| Error::SwayNotRunning)?;
+
+        Ok(Self::with_socket(socket))
+    }
+
+    pub fn with_socket(socket: impl Into<PathBuf>) -> Self {
+        Self {
+            socket: socket.into(),
+        }
+    }
+
+    /// Runs each command in turn over a single connection.
+    pub async fn run(&self, commands: &[String]) -> Result<()> {
+        let mut stream = UnixStream::connect(&self.socket)
+            .await
+            .map_err(|source| socket_error(&self.socket, source))?;
+
+        for command in commands {
+            let reply = timeout(REQUEST_TIMEOUT, async {
+                write_message(&mut stream, RUN_COMMAND, command).await?;
+                read_message(&mut stream).await
+            })
+            .await
+            .unwrap_or_else(|_| Err(io::ErrorKind::TimedOut.into()))
+            .map_err(|source| socket_error(&self.socket, source))?;
+
+            check_outcome(command, &reply.1)?;
+        }
+
+        Ok(())
+    }
+
+    /// Blocking variant of [`SwayClient::run`] for use in `Drop`.
+    pub fn run_blocking(&self, commands: &[String]) -> Result<()> {
+        let mut stream = StdUnixStream::connect(&self.socket)
+            .map_err(|source| socket_error(&self.socket, source))?;
+
+        for command in commands {
+            let reply = (|| -> io::Result<String> {
+                stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+                stream.write_all(&frame(RUN_COMMAND, command))?;
+
+                let mut header = [0; HEADER_LEN];
+                stream.read_exact(&mut header)?;
+                let (_, len) = parse_header(&header)?;
+
+                let mut payload = vec![0; len];
+                stream.read_exact(&mut payload)?;
+                Ok(String::from_utf8_lossy(&payload).into_owned())
+            })()
+            .map_err(|source| socket_error(&self.socket, source))?;
+
+            check_outcome(command, &reply)?;
+        }
+
+        Ok(())
+    }
+
+    pub async fn tree(&self) -> Result<Value> {
+        let reply = self.request(GET_TREE, "").await?;
+        Ok(serde_json::from_str(&reply)?)
+    }
+
+    /// The config file as sway last loaded it, variables unexpanded.
+    pub async fn config(&self) -> Result<String> {
+        let reply: Value =
+            serde_json::from_str(&self.request(GET_CONFIG, "").await?)?;
+
+        reply["config"].as_str().map(str::to_string).ok_or_else(|| {
+            Error::SwayReply("GET_CONFIG reply has no config".into())
+        })
+    }
+
+    pub async fn subscribe(&self, events: &[&str]) -> Result<EventStream> {
+        let mut stream = UnixStream::connect(&self.socket)
+            .await
+            .map_err(|source| socket_error(&self.socket, source))?;
+
+        let payload = serde_json::to_string(events)?;
+        write_message(&mut stream, SUBSCRIBE, &payload)
+            .await
+            .map_err(|source| socket_error(&self.socket, source))?;
+        let (_, reply) = read_message(&mut stream)
+            .await
+            .map_err(|source| socket_error(&self.socket, source))?;
+        check_outcome(&format!("subscribe {payload}"), &reply)?;
+
+        Ok(EventStream {
+            socket: self.socket.clone(),
+            stream,
+            buffer: Vec::new(),
+        })
+    }
+
+    async fn request(&self, kind: u32, payload: &str) -> Result<String> {
+        timeout(REQUEST_TIMEOUT, async {
+            let mut stream = UnixStream::connect(&self.socket).await?;
+            write_message(&mut stream, kind, payload).await?;
+            read_message(&mut stream).await
+        })
+        .await
+        .unwrap_or_else(|_| Err(io::ErrorKind::TimedOut.into()))
+        .map(|(_, reply)| reply)
+        .map_err(|source| socket_error(&self.socket, source))
+    }
+}
+
+/// An event received on a subscribed connection.
+#[derive(Debug, Clone)]
+pub struct Event {
+    /// Event type with the high "is event" bit cleared, e.g. 0 for workspace.
+    pub kind: u32,
+    pub payload: Value,
+}
+
+/// Events from a subscribed connection.
+///
+/// Reads go through a buffer so `next` can be cancelled, e.g. by
+/// `tokio::select!`, without losing part of a message.
+pub struct EventStream {
+    socket: PathBuf,
+    stream: UnixStream,
+    buffer: Vec<u8>,
+}
+
+impl EventStream {
+    /// Waits for the next event; `None` once sway closes the socket.
+    pub async fn next(&mut self) -> Result<Option<Event>> {
+        loop {
+            if let Some((kind, payload)) = self.take_message()? {
+                return Ok(Some(Event {
+                    kind: kind & !(1 << 31),
+                    payload: serde_json::from_str(&payload)?,
+                }));
+            }
+
+            let read = self
+                .stream
+                .read_buf(&mut self.buffer)
+                .await
+                .map_err(|source| socket_error(&self.socket, source))?;
+
+            if read == 0 {
+                return Ok(None);
+            }
+        }
+    }
+
+    /// Splits the first complete message off the buffer, if there is one.
+    fn take_message(&mut self) -> Result<Option<(u32, String)>> {
+        let Some(header) = self.buffer.first_chunk::<HEADER_LEN>() else {
+            return Ok(None);
+        };
+
+        let (kind, len) = parse_header(header)
+            .map_err(|source| socket_error(&self.socket, source))?;
+
+        if self.buffer.len() < HEADER_LEN + len {
+            return Ok(None);
+        }
+
+        let message: Vec<u8> = self
+            .buffer
+            .drain(..HEADER_LEN + len)
+            .skip(HEADER_LEN)
+            .collect();
+
+        Ok(Some((kind, String::from_utf8_lossy(&message).into_owned())))
+    }
+}
+
+fn frame(kind: u32, payload: &str) -> Vec<u8> {
+    let mut message = Vec::with_capacity(HEADER_LEN + payload.len());
+    message.extend_from_slice(MAGIC);
+    message.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+    message.extend_from_slice(&kind.to_le_bytes());
+    message.extend_from_slice(payload.as_bytes());
+    message
+}
+
+fn parse_header(header: &[u8; HEADER_LEN]) -> io::Result<(u32, usize)> {
+    if &header[..MAGIC.len()] != MAGIC {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "missing i3-ipc magic",
+        ));
+    }
+
+    let len = u32::from_le_bytes(header[6..10].try_into().unwrap());
+    let kind = u32::from_le_bytes(header[10..14].try_into().unwrap());
+
+    Ok((kind, len as usize))
+}
+
+async fn write_message(
+    stream: &mut UnixStream,
+    kind: u32,
+    payload: &str,
+) -> io::Result<()> {
+    stream.write_all(&frame(kind, payload)).await
+}
+
+async fn read_message(stream: &mut UnixStream) -> io::Result<(u32, String)> {
+    let mut header = [0; HEADER_LEN];
+    stream.read_exact(&mut header).await?;
+    let (kind, len) = parse_header(&header)?;
+
+    let mut payload = vec![0; len];
+    stream.read_exact(&mut payload).await?;
+
+    Ok((kind, String::from_utf8_lossy(&payload).into_owned()))
+}
+
+/// Checks a `RUN_COMMAND` or `SUBSCRIBE` reply, which is either a single
+/// outcome object or one per command.
+fn check_outcome(command: &str, reply: &str) -> Result<()> {
+    let outcomes: Vec<CommandOutcome> = match serde_json::from_str(reply)? {
+        Value::Array(items) => items
+            .into_iter()
+            .map(serde_json::from_value)
+            .collect::<std::result::Result<_, _>>()?,
+        single => vec![serde_json::from_value(single)?],
+    };
+
+    match outcomes.into_iter().find(|outcome| !outcome.success) {
+        Some(failed) => Err(Error::SwayCommand {
+            command: command.to_string(),
+            error: failed.error.unwrap_or_else(|| "unknown error".into()),
+        }),
+        None => Ok(()),
+    }
+}
+
+fn socket_error(path: &Path, source: io::Error) -> Error {
+    Error::SwaySocket {
+        path: path.to_path_buf(),
+        source,
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::collections::VecDeque;
+
+    use tempfile::TempDir;
+    use tokio::{net::UnixListener, task::JoinHandle};
+
+    use super::*;
+
+    const SOCKET_NAME: &str = "sway-ipc.sock";
+    const SUCCESS: &str = r#"[{"success": true}]"#;
+
+    /// A client of the fake socket [`serve`] listens on.
+    pub(crate) fn client(dir: &TempDir) -> SwayClient {
+        SwayClient::with_socket(dir.path().join(SOCKET_NAME))
+    }
+
+    /// Answers one request per reply, as a message of the given type, on a
+    /// fake `$SWAYSOCK`. Connections are served one after the other; the
+    /// requests received are returned with their types.
+    pub(crate) fn serve(
+        dir: &TempDir,
+        replies: &[(u32, &str)],
+    ) -> JoinHandle<Vec<(u32, String)>> {
+        let listener =
+            UnixListener::bind(dir.path().join(SOCKET_NAME)).unwrap();
+        let mut replies: VecDeque<(u32, String)> = replies
+            .iter()
+            .map(|(kind, reply)| (*kind, reply.to_string()))
+            .collect();
+
+        tokio::spawn(async move {
+            let mut requests = Vec::new();
+            while !replies.is_empty() {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                while let Ok(request) = read_message(&mut stream).await {
+                    requests.push(request);
+                    let (kind, reply) = replies.pop_front().unwrap();
+                    write_message(&mut stream, kind, &reply).await.unwrap();
+                    if replies.is_empty() {
+                        break;
+                    }
+                }
+            }
+            requests
+        })
+    }
+
+    fn commands(commands: &[&str]) -> Vec<String> {
+        commands.iter().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn messages_are_framed_with_length_and_type() {
+        let message = frame(RUN_COMMAND, "exit");
+
+        assert_eq!(&message[..6], b"i3-ipc");
+        assert_eq!(&message[6..10], &4u32.to_le_bytes());
+        assert_eq!(&message[10..14], &RUN_COMMAND.to_le_bytes());
+        assert_eq!(&message[14..], b"exit");
+
+        let header = frame(GET_TREE, "").try_into().unwrap();
+        assert_eq!(parse_header(&header).unwrap(), (GET_TREE, 0));
+    }
+
+    #[test]
+    fn headers_without_the_magic_are_rejected() {
+        let mut header: [u8; HEADER_LEN] =
+            frame(GET_TREE, "").try_into().unwrap();
+        header[0] = b'x';
+
+        let error = parse_header(&header).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[tokio::test]
+    async fn commands_run_in_turn_over_one_connection() {
+        let dir = TempDir::new().unwrap();
+        let server =
+            serve(&dir, &[(RUN_COMMAND, SUCCESS), (RUN_COMMAND, SUCCESS)]);
+
+        client(&dir)
+            .run(&commands(&["unbindsym Mod4+r", "bindsym Mod4+r nop"]))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            server.await.unwrap(),
+            [
+                (RUN_COMMAND, "unbindsym Mod4+r".to_string()),
+                (RUN_COMMAND, "bindsym Mod4+r nop".to_string()),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn failed_commands_are_reported() {
+        let dir = TempDir::new().unwrap();
+        let _server = serve(
+            &dir,
+            &[(
+                RUN_COMMAND,
+                r#"[{"success": false, "error": "Unknown command"}]"#,
+            )],
+        );
+
+        match client(&dir).run(&commands(&["frobnicate"])).await {
+            Err(Error::SwayCommand { command, error }) => {
+                assert_eq!(command, "frobnicate");
+                assert_eq!(error, "Unknown command");
+            }
+            other => panic!("unexpected result: {other:?}"),
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn blocking_commands_use_the_same_framing() {
+        let dir = TempDir::new().unwrap();
+        let server = serve(&dir, &[(RUN_COMMAND, SUCCESS)]);
+        let client = client(&dir);
+
+        tokio::task::spawn_blocking(move || {
+            client.run_blocking(&commands(&["unbindsym Mod4+r"]))
+        })
+        .await
+        .unwrap()
+        .unwrap();
+
+        assert_eq!(
+            server.await.unwrap(),
+            [(RUN_COMMAND, "unbindsym Mod4+r".to_string())]
+        );
+    }
+
+    #[tokio::test]
+    async fn the_config_comes_from_get_config() {
+        let dir = TempDir::new().unwrap();
+        let server = serve(
+            &dir,
+            &[
+                (GET_CONFIG, r#"{"config": "set $mod Mod4\n"}"#),
+                (GET_CONFIG, "{}"),
+            ],
+        );
+        let client = client(&dir);
+
+        assert_eq!(client.config().await.unwrap(), "set $mod Mod4\n");
+        assert!(matches!(client.config().await, Err(Error::SwayReply(_))));
+        assert_eq!(
+            server.await.unwrap(),
+            [(GET_CONFIG, String::new()), (GET_CONFIG, String::new())]
+        );
+    }
+
+    #[tokio::test]
+    async fn a_missing_socket_names_its_path() {
+        let dir = TempDir::new().unwrap();
+
+        match client(&dir).tree().await {
+            Err(Error::SwaySocket { path, .. }) => {
+                assert_eq!(path, dir.path().join(SOCKET_NAME));
+            }
+            other => panic!("unexpected result: {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn events_split_across_reads_arrive_whole() {
+        let dir = TempDir::new().unwrap();
+        let listener =
+            UnixListener::bind(dir.path().join(SOCKET_NAME)).unwrap();
+        let server = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let request = read_message(&mut stream).await.unwrap();
+            write_message(&mut stream, SUBSCRIBE, r#"{"success": true}"#)
+                .await
+                .unwrap();
+
+            let event = frame(1 << 31, r#"{"change": "reload"}"#);
+            let (head, tail) = event.split_at(HEADER_LEN + 3);
+            stream.write_all(head).await.unwrap();
+            stream.flush().await.unwrap();
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            stream.write_all(tail).await.unwrap();
+            request
+        });
+
+        let mut events = client(&dir).subscribe(&["workspace"]).await.unwrap();
+        let event = events.next().await.unwrap().unwrap();
+
+        assert_eq!(event.kind, 0);
+        assert_eq!(event.payload["change"], "reload");
+        assert_eq!(
+            server.await.unwrap(),
+            (SUBSCRIBE, r#"["workspace"]"#.to_string())
+        );
+        assert!(events.next().await.unwrap().is_none());
+    }
+}
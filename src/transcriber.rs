@@ -1,20 +1,35 @@
 use std::{
     env,
     path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
 };
 
-use tokio::fs;
+use tokio::{fs, task};
+use tracing::{debug, info, instrument};
 
-use crate::{utils::run_async, Error, Result};
+use crate::{
+    command::{self, CommandRunner},
+    markers::Marker,
+    Error,
+    Result,
+};
 
-#[derive(Debug, Clone)]
+#[derive(Clone)]
 pub struct Transcriber {
+    runner: Arc<dyn CommandRunner>,
     command: String,
     model: Option<String>,
     language: Option<String>,
     extra_args: Vec<String>,
 }
 
+impl Default for Transcriber {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Transcriber {
     pub fn new() -> Self {
         let command = env::var("WHISPER_COMMAND")
@@ -33,6 +48,7 @@ impl Transcriber {
             .unwrap_or_default();
 
         Self {
+            runner: command::system(),
             command,
             model,
             language,
@@ -40,15 +56,37 @@ impl Transcriber {
         }
     }
 
-    pub async fn start(&self, audio_path: &Path) -> Result<PathBuf> {
+    /// Runs whisper through `runner` instead of spawning it directly.
+    pub fn with_runner(mut self, runner: Arc<dyn CommandRunner>) -> Self {
+        self.runner = runner;
+        self
+    }
+
+    /// The whisper executable, `WHISPER_COMMAND` or `whisper`.
+    pub fn command(&self) -> &str {
+        &self.command
+    }
+
+    /// Transcribes `audio_path` into a `.txt` next to it, with a line for
+    /// each marker where it falls. Whisper's `.srt` is kept alongside.
+    #[instrument(
+        name = "transcribe",
+        skip_all,
+        fields(recording = %audio_path.display())
+    )]
+    pub async fn start(
+        &self,
+        audio_path: &Path,
+        markers: &[Marker],
+    ) -> Result<PathBuf> {
         let output_dir = audio_path
             .parent()
             .map(Path::to_path_buf)
             .unwrap_or_else(|| PathBuf::from("."));
-        let mut expected = audio_path.to_path_buf();
-        expected.set_extension("txt");
+        let mut transcript = audio_path.to_path_buf();
+        transcript.set_extension("txt");
 
-        println!("Transcribing recording: {}", audio_path.display());
+        info!("Transcribing recording: {}", audio_path.display());
 
         let mut args = Vec::new();
         args.push(audio_path.to_string_lossy().to_string());
@@ -63,17 +101,24 @@ impl Transcriber {
             args.push(language.clone());
         }
 
+        // Subtitles keep segment timings, which the plain text loses.
         args.push("--output_format".into());
-        args.push("txt".into());
+        args.push("srt".into());
         args.push("--output_dir".into());
         args.push(output_dir.to_string_lossy().to_string());
 
         args.extend(self.extra_args.clone());
 
-        println!("Running Whisper command: {} {:?}", &self.command, &args);
+        debug!("Running Whisper command: {} {:?}", self.command, args);
 
-        let output =
-            run_async!(&self.command; &args).map_err(|err| match err {
+        let runner = Arc::clone(&self.runner);
+        let program = self.command.clone();
+        let output = task::spawn_blocking(move || runner.run(&program, &args))
+            .await
+            .map_err(|e| {
+                Error::Transcription(format!("Whisper task failed: {e}"))
+            })?
+            .map_err(|err| match err {
                 Error::Io(inner) => Error::Transcription(format!(
                     "Failed to run Whisper: {inner}"
                 )),
@@ -88,39 +133,266 @@ impl Transcriber {
             )));
         }
 
-        if fs::metadata(&expected).await.is_err() {
-            let audio_filename = audio_path.file_name().ok_or_else(|| {
-                Error::Transcription(
-                    "Audio path is missing a file name".to_string(),
-                )
-            })?;
-            let alternate = output_dir
-                .join(format!("{}.txt", audio_filename.to_string_lossy()));
-
-            match fs::metadata(&alternate).await {
-                Ok(_) => {
-                    fs::rename(&alternate, &expected).await.map_err(|err| {
-                        Error::Transcription(format!(
-                            "Failed to move transcript from {} to {}: {}",
-                            alternate.display(),
-                            expected.display(),
-                            err
-                        ))
-                    })?;
-                }
-                Err(e) => {
-                    return Err(Error::Transcription(format!(
-                        "Whisper did not produce a transcript at {}. Error: {}, Stdout: {}",
-                        e,
+        let subtitles =
+            Self::locate_output(audio_path, &output_dir, "srt", &output.stdout)
+                .await?;
+        let cues = parse_srt(&fs::read_to_string(&subtitles).await?);
+
+        fs::write(&transcript, render_transcript(&cues, markers)).await?;
+
+        info!("Transcript ready: {}", transcript.display());
+
+        Ok(transcript)
+    }
+
+    /// Finds Whisper's output with `extension`, which is named either after
+    /// the file stem or the full file name depending on the implementation,
+    /// and settles it on `<stem>.<extension>`.
+    async fn locate_output(
+        audio_path: &Path,
+        output_dir: &Path,
+        extension: &str,
+        stdout: &str,
+    ) -> Result<PathBuf> {
+        let expected = audio_path.with_extension(extension);
+
+        if fs::metadata(&expected).await.is_ok() {
+            return Ok(expected);
+        }
+
+        let audio_filename = audio_path.file_name().ok_or_else(|| {
+            Error::Transcription(
+                "Audio path is missing a file name".to_string(),
+            )
+        })?;
+        let alternate = output_dir
+            .join(format!("{}.{extension}", audio_filename.to_string_lossy()));
+
+        match fs::metadata(&alternate).await {
+            Ok(_) => {
+                fs::rename(&alternate, &expected).await.map_err(|err| {
+                    Error::Transcription(format!(
+                        "Failed to move transcript from {} to {}: {}",
+                        alternate.display(),
                         expected.display(),
-                        output.stdout.trim()
-                    )));
-                }
+                        err
+                    ))
+                })?;
+                Ok(expected)
             }
+            Err(e) => Err(Error::Transcription(format!(
+                "Whisper did not produce a transcript at {}. Error: {}, Stdout: {}",
+                expected.display(),
+                e,
+                stdout.trim()
+            ))),
+        }
+    }
+}
+
+/// One subtitle from Whisper's `.srt` output.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cue {
+    pub start: Duration,
+    pub end: Duration,
+    pub text: String,
+}
+
+/// Parses SRT blocks, skipping any that are malformed.
+pub fn parse_srt(contents: &str) -> Vec<Cue> {
+    contents
+        .replace("\r\n", "\n")
+        .split("\n\n")
+        .filter_map(|block| {
+            let mut lines =
+                block.lines().skip_while(|line| line.trim().is_empty());
+            let timing = lines.find(|line| line.contains("-->"))?;
+            let (start, end) = timing.split_once("-->")?;
+
+            let text = lines
+                .map(str::trim)
+                .filter(|line| !line.is_empty())
+                .collect::<Vec<_>>()
+                .join(" ");
+
+            Some(Cue {
+                start: parse_timestamp(start)?,
+                end: parse_timestamp(end)?,
+                text,
+            })
+        })
+        .collect()
+}
+
+/// Parses `HH:MM:SS,mmm` (or with a `.` before the milliseconds).
+fn parse_timestamp(value: &str) -> Option<Duration> {
+    let (clock, millis) = value.trim().split_once([',', '.'])?;
+    let mut parts = clock.split(':').map(|part| part.parse::<u64>().ok());
+    let (hours, minutes, seconds) =
+        (parts.next()??, parts.next()??, parts.next()??);
+
+    Some(
+        Duration::from_secs(hours * 3600 + minutes * 60 + seconds)
+            + Duration::from_millis(millis.parse().ok()?),
+    )
+}
+
+/// One line per segment, like Whisper's own `.txt`, with markers inserted
+/// before the first segment starting at or after them.
+fn render_transcript(cues: &[Cue], markers: &[Marker]) -> String {
+    let mut markers = markers.iter().enumerate().peekable();
+    let mut transcript = String::new();
+
+    for cue in cues {
+        while let Some((index, marker)) =
+            markers.next_if(|(_, marker)| marker.offset <= cue.start)
+        {
+            transcript.push_str(&marker_line(index, marker));
+            transcript.push('\n');
+        }
+
+        transcript.push_str(&cue.text);
+        transcript.push('\n');
+    }
+
+    for (index, marker) in markers {
+        transcript.push_str(&marker_line(index, marker));
+        transcript.push('\n');
+    }
+
+    transcript
+}
+
+fn marker_line(index: usize, marker: &Marker) -> String {
+    let line = format!("[Marker {} at {}]", index + 1, marker.timestamp());
+
+    match &marker.label {
+        Some(label) => format!("{line} {label}"),
+        None => line,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs as std_fs;
+
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::{command::ScriptedRunner, utils::Output};
+
+    const SUBTITLES: &str = "1\n00:00:00,000 --> 00:00:02,000\nHello\n\n\
+                             2\n00:00:02,000 --> 00:00:04,500\nthere\n";
+
+    /// Whisper through `runner`, without settings from the environment.
+    fn whisper(runner: &Arc<ScriptedRunner>) -> Transcriber {
+        Transcriber {
+            runner: runner.clone(),
+            command: "whisper".into(),
+            model: None,
+            language: None,
+            extra_args: Vec::new(),
         }
+    }
+
+    /// The command line transcribing `audio` in `dir`.
+    fn argv(audio: &Path, dir: &TempDir) -> Vec<String> {
+        vec![
+            "whisper".into(),
+            audio.display().to_string(),
+            "--output_format".into(),
+            "srt".into(),
+            "--output_dir".into(),
+            dir.path().display().to_string(),
+        ]
+    }
+
+    /// A runner expecting whisper to run once with `argv`.
+    fn scripted(argv: &[String], output: Output) -> Arc<ScriptedRunner> {
+        let argv: Vec<&str> = argv.iter().map(String::as_str).collect();
+        Arc::new(ScriptedRunner::new().expect(&argv, output))
+    }
+
+    fn success(stdout: &str) -> Output {
+        Output {
+            stdout: stdout.into(),
+            stderr: String::new(),
+            status: 0,
+        }
+    }
+
+    #[tokio::test]
+    async fn subtitles_named_after_the_stem_are_used() {
+        let dir = TempDir::new().unwrap();
+        let audio = dir.path().join("capture.wav");
+        std_fs::write(dir.path().join("capture.srt"), SUBTITLES).unwrap();
+        let runner = scripted(&argv(&audio, &dir), success(""));
+
+        let transcript = whisper(&runner).start(&audio, &[]).await.unwrap();
+
+        assert_eq!(transcript, dir.path().join("capture.txt"));
+        assert_eq!(
+            std_fs::read_to_string(transcript).unwrap(),
+            "Hello\nthere\n"
+        );
+        runner.assert_done();
+    }
+
+    #[tokio::test]
+    async fn subtitles_named_after_the_file_are_moved() {
+        let dir = TempDir::new().unwrap();
+        let audio = dir.path().join("capture.wav");
+        std_fs::write(dir.path().join("capture.wav.srt"), SUBTITLES).unwrap();
+        let runner = scripted(&argv(&audio, &dir), success(""));
+
+        let transcript = whisper(&runner).start(&audio, &[]).await.unwrap();
+
+        assert_eq!(
+            std_fs::read_to_string(transcript).unwrap(),
+            "Hello\nthere\n"
+        );
+        assert!(dir.path().join("capture.srt").exists());
+        assert!(!dir.path().join("capture.wav.srt").exists());
+    }
+
+    #[tokio::test]
+    async fn missing_subtitles_report_whispers_output() {
+        let dir = TempDir::new().unwrap();
+        let audio = dir.path().join("capture.wav");
+        let runner = scripted(
+            &argv(&audio, &dir),
+            success("Detected language: English\n"),
+        );
+
+        let error = whisper(&runner).start(&audio, &[]).await.unwrap_err();
+
+        assert!(matches!(
+            error,
+            Error::Transcription(message)
+                if message.ends_with("Stdout: Detected language: English")
+        ));
+        assert!(!dir.path().join("capture.txt").exists());
+    }
+
+    #[tokio::test]
+    async fn whisper_failures_are_reported() {
+        let dir = TempDir::new().unwrap();
+        let audio = dir.path().join("capture.wav");
+        let runner = scripted(
+            &argv(&audio, &dir),
+            Output {
+                stdout: String::new(),
+                stderr: "model not found\n".into(),
+                status: 2,
+            },
+        );
 
-        println!("Transcript ready: {}", expected.display());
+        let error = whisper(&runner).start(&audio, &[]).await.unwrap_err();
 
-        Ok(expected)
+        assert!(matches!(
+            error,
+            Error::Transcription(message)
+                if message == "Whisper exited with status 2: model not found"
+        ));
     }
 }
@@ -0,0 +1,312 @@
+use std::{
+    env,
+    path::{Path, PathBuf},
+    process::Stdio,
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+use tokio::{
+    fs,
+    process::Command,
+    sync::{broadcast, oneshot, Mutex},
+    time::interval,
+};
+use tracing::{info, warn};
+
+use crate::{
+    library,
+    s3::{Credentials, S3Client, MAX_URL_EXPIRY},
+    session::{self, Event},
+    utils,
+    Error,
+    Result,
+};
+
+const QUEUE_NAME: &str = "upload-queue.json";
+/// How often the daemon retries queued uploads.
+const RETRY_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+/// Held while the queue file is read and written back, as the daemon's
+/// retries run alongside the uploads of new recordings.
+static QUEUE_LOCK: Mutex<()> = Mutex::const_new(());
+/// Held for a whole retry, so that two never upload the same recording.
+static RETRY_LOCK: Mutex<()> = Mutex::const_new(());
+
+/// An upload waiting for the storage to become reachable.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct Pending {
+    path: PathBuf,
+    key: String,
+}
+
+/// Uploads recordings to S3-compatible storage and hands out presigned
+/// links to them.
+pub struct Uploader {
+    client: S3Client,
+    prefix: String,
+    expiry: Duration,
+}
+
+impl Uploader {
+    /// Configured through `HYPR_RECORDER_S3_*` and the usual `AWS_*`
+    /// credential variables. Returns `None` when uploads are not set up.
+    pub fn from_env() -> Result<Option<Self>> {
+        let Some(endpoint) = var("HYPR_RECORDER_S3_ENDPOINT") else {
+            return Ok(None);
+        };
+
+        let require = |name: &str| {
+            var(name).ok_or_else(|| Error::Upload(format!("{name} is not set")))
+        };
+        let bucket = require("HYPR_RECORDER_S3_BUCKET")?;
+        let credentials = Credentials {
+            access_key: require("AWS_ACCESS_KEY_ID")?,
+            secret_key: require("AWS_SECRET_ACCESS_KEY")?,
+            session_token: var("AWS_SESSION_TOKEN"),
+        };
+        let region = var("HYPR_RECORDER_S3_REGION")
+            .or_else(|| var("AWS_REGION"))
+            .unwrap_or_else(|| "us-east-1".into());
+
+        let expiry = match var("HYPR_RECORDER_S3_URL_EXPIRY") {
+            Some(secs) => secs
+                .parse()
+                .map(Duration::from_secs)
+                .map_err(|_| {
+                    Error::Upload(format!(
+                        "HYPR_RECORDER_S3_URL_EXPIRY must be seconds, got `{secs}`"
+                    ))
+                })?,
+            None => MAX_URL_EXPIRY,
+        };
+
+        Ok(Some(Self {
+            client: S3Client::new(&endpoint, &bucket, &region, credentials)?,
+            prefix: var("HYPR_RECORDER_S3_PREFIX").unwrap_or_default(),
+            expiry,
+        }))
+    }
+
+    /// Object key of a recording: the prefix, then its library id.
+    pub fn key(&self, recording: &Path) -> String {
+        let extension = recording
+            .extension()
+            .map(|ext| format!(".{}", ext.to_string_lossy()))
+            .unwrap_or_default();
+
+        format!("{}{}{extension}", self.prefix, library::id_of(recording))
+    }
+
+    /// Uploads a recording and returns a link to it. On failure the upload
+    /// is queued, to be retried by [`Uploader::retry_pending`].
+    pub async fn upload(&self, recording: &Path) -> Result<String> {
+        let pending = Pending {
+            path: recording.to_path_buf(),
+            key: self.key(recording),
+        };
+
+        info!("Uploading {} as {}", recording.display(), pending.key);
+
+        if let Err(e) = self.client.upload_file(recording, &pending.key).await {
+            let _lock = QUEUE_LOCK.lock().await;
+            let mut queue = load_queue().await;
+            if !queue.contains(&pending) {
+                queue.push(pending);
+            }
+            if let Err(save) = save_queue(&queue).await {
+                warn!("Failed to queue upload for later: {}", save);
+            }
+            return Err(e);
+        }
+
+        Ok(self.client.presigned_url(&pending.key, self.expiry))
+    }
+
+    /// Retries queued uploads, keeping those that fail again. Recordings
+    /// deleted in the meantime are dropped. Returns the links of the
+    /// uploads that went through; does nothing if a retry is already
+    /// running.
+    pub async fn retry_pending(&self) -> Result<Vec<(PathBuf, String)>> {
+        let Ok(_retrying) = RETRY_LOCK.try_lock() else {
+            return Ok(Vec::new());
+        };
+        let queue = {
+            let _lock = QUEUE_LOCK.lock().await;
+            load_queue().await
+        };
+        if queue.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        info!("Retrying {} queued upload(s)", queue.len());
+
+        // Uploads queued meanwhile are left alone, so the queue is only
+        // locked again to take out the ones that are done.
+        let mut done = Vec::new();
+        let mut uploaded = Vec::new();
+
+        for pending in queue {
+            if !pending.path.exists() {
+                info!("Dropping upload of missing {}", pending.path.display());
+                done.push(pending);
+                continue;
+            }
+
+            match self.client.upload_file(&pending.path, &pending.key).await {
+                Ok(()) => {
+                    let url =
+                        self.client.presigned_url(&pending.key, self.expiry);
+                    uploaded.push((pending.path.clone(), url));
+                    done.push(pending);
+                }
+                Err(e) => warn!(
+                    "Upload of {} failed again: {}",
+                    pending.path.display(),
+                    e
+                ),
+            }
+        }
+
+        let _lock = QUEUE_LOCK.lock().await;
+        let mut queue = load_queue().await;
+        queue.retain(|pending| !done.contains(pending));
+        save_queue(&queue).await?;
+        Ok(uploaded)
+    }
+}
+
+/// Uploads the recording a session saves, when uploads are configured,
+/// sending its link on `link`. The sender is dropped if nothing was saved.
+pub async fn follow(
+    mut events: broadcast::Receiver<Event>,
+    link: oneshot::Sender<Option<String>>,
+) {
+    let mut link = Some(link);
+
+    while let Some(event) = session::next_event(&mut events).await {
+        if let (Event::Saved(metadata), Some(link)) = (&event, link.take()) {
+            let _ = link.send(publish(&metadata.recording).await);
+        }
+
+        if event.ends_session() {
+            break;
+        }
+    }
+}
+
+/// Uploads the recording, copying its link to the clipboard.
+async fn publish(recording: &Path) -> Option<String> {
+    let uploader = match Uploader::from_env() {
+        Ok(Some(uploader)) => uploader,
+        Ok(None) => return None,
+        Err(e) => {
+            warn!("Uploads are misconfigured: {}", e);
+            return None;
+        }
+    };
+
+    let url = match uploader.upload(recording).await {
+        Ok(url) => url,
+        Err(e) => {
+            warn!("Upload failed, queued for retry: {}", e);
+            return None;
+        }
+    };
+
+    info!("Uploaded: {url}");
+
+    if let Err(e) = copy_to_clipboard(&url).await {
+        warn!("Failed to copy the link: {}", e);
+    }
+
+    // The storage is reachable again, so catch up on earlier failures
+    // without holding back the link.
+    tokio::spawn(async move { retry(&uploader).await });
+
+    Some(url)
+}
+
+/// Retries queued uploads right away and then every few minutes, so that
+/// recordings saved while offline go up without waiting for the next one.
+/// Meant for the daemon; returns at once when uploads are not configured.
+pub async fn retry_periodically() {
+    let uploader = match Uploader::from_env() {
+        Ok(Some(uploader)) => uploader,
+        Ok(None) => return,
+        Err(e) => {
+            warn!("Uploads are misconfigured: {}", e);
+            return;
+        }
+    };
+
+    let mut timer = interval(RETRY_INTERVAL);
+    loop {
+        timer.tick().await;
+        retry(&uploader).await;
+    }
+}
+
+async fn retry(uploader: &Uploader) {
+    match uploader.retry_pending().await {
+        Ok(uploaded) => {
+            for (path, url) in uploaded {
+                info!("Uploaded {}: {url}", path.display());
+            }
+        }
+        Err(e) => warn!("Failed to retry queued uploads: {}", e),
+    }
+}
+
+fn var(name: &str) -> Option<String> {
+    env::var(name).ok().filter(|value| !value.trim().is_empty())
+}
+
+fn queue_path() -> PathBuf {
+    utils::state_dir().join(QUEUE_NAME)
+}
+
+async fn load_queue() -> Vec<Pending> {
+    match fs::read(queue_path()).await {
+        Ok(bytes) => serde_json::from_slice(&bytes).unwrap_or_else(|e| {
+            warn!("Ignoring unreadable upload queue: {}", e);
+            Vec::new()
+        }),
+        Err(_) => Vec::new(),
+    }
+}
+
+async fn save_queue(queue: &[Pending]) -> Result<()> {
+    let path = queue_path();
+
+    if queue.is_empty() {
+        return match fs::remove_file(&path).await {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        };
+    }
+
+    fs::create_dir_all(utils::state_dir()).await?;
+    fs::write(&path, serde_json::to_vec_pretty(queue)?).await?;
+    Ok(())
+}
+
+/// Puts `text` on the Wayland clipboard through `wl-copy`.
+pub async fn copy_to_clipboard(text: &str) -> Result<()> {
+    // wl-copy keeps serving the clipboard from a background process, which
+    // would hold captured output pipes open; don't capture them.
+    let status = Command::new("wl-copy")
+        .arg("--")
+        .arg(text)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .await?;
+
+    if !status.success() {
+        return Err(Error::Upload(format!("wl-copy exited with {status}")));
+    }
+
+    Ok(())
+}
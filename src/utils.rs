@@ -1,6 +1,12 @@
-use std::process::{Command as StdCommand, ExitStatus};
+use std::{
+    env,
+    ffi::{CStr, CString},
+    path::PathBuf,
+    process::{Command as StdCommand, ExitStatus, Stdio},
+    time::{SystemTime, UNIX_EPOCH},
+};
 
-use tokio::process::Command as TokioCommand;
+use tokio::{io::AsyncWriteExt, process::Command as TokioCommand};
 
 use crate::error::Result;
 
@@ -50,43 +56,117 @@ fn exit_status_to_u8(status: ExitStatus) -> u8 {
     }
 }
 
-pub(crate) fn run_command(mut command: StdCommand) -> Result<Output> {
+pub fn run_command(mut command: StdCommand) -> Result<Output> {
     let output = command.output()?;
     Ok(Output::from(output))
 }
 
-pub(crate) async fn run_command_async(
-    mut command: TokioCommand,
-) -> Result<Output> {
+pub async fn run_command_async(mut command: TokioCommand) -> Result<Output> {
     let output = command.output().await?;
     Ok(Output::from(output))
 }
 
-macro_rules! run {
-    ($program:expr $(, $arg:expr )* $(,)?) => {{
-        let mut command = std::process::Command::new($program);
-        $(command.arg($arg);)*
-        $crate::utils::run_command(command)
-    }};
-    ($program:expr; $args:expr) => {{
-        let mut command = std::process::Command::new($program);
-        command.args($args);
-        $crate::utils::run_command(command)
-    }};
+/// Like [`run_command_async`], with `input` written to the command's stdin.
+pub async fn run_command_with_input(
+    mut command: TokioCommand,
+    input: &[u8],
+) -> Result<Output> {
+    command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+
+    let mut child = command.spawn()?;
+    let stdin = child.stdin.take();
+
+    // Feed stdin while collecting output, so neither side can block the
+    // other. A command that exits without reading it is not an error.
+    let feed = async move {
+        if let Some(mut stdin) = stdin {
+            let _ = stdin.write_all(input).await;
+        }
+    };
+    let ((), output) = tokio::join!(feed, child.wait_with_output());
+
+    Ok(Output::from(output?))
+}
+
+/// Where state kept across runs lives: `$XDG_STATE_HOME/hypr-recorder`,
+/// or `~/.local/state/hypr-recorder` when that is unset.
+pub fn state_dir() -> PathBuf {
+    env::var_os("XDG_STATE_HOME")
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute())
+        .or_else(|| env::home_dir().map(|home| home.join(".local/state")))
+        .unwrap_or_else(env::temp_dir)
+        .join("hypr-recorder")
 }
 
-macro_rules! run_async {
-    ($program:expr $(, $arg:expr )* $(,)?) => {{
-        let mut command = tokio::process::Command::new($program);
-        $(command.arg($arg);)*
-        $crate::utils::run_command_async(command).await
-    }};
-    ($program:expr; $args:expr) => {{
-        let mut command = tokio::process::Command::new($program);
-        command.args($args);
-        $crate::utils::run_command_async(command).await
-    }};
+fn local_time(time: SystemTime) -> Option<libc::tm> {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs() as libc::time_t)
+        .unwrap_or_default();
+
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+    if unsafe { libc::localtime_r(&secs, &mut tm) }.is_null() {
+        return None;
+    }
+
+    Some(tm)
 }
 
-pub(crate) use run;
-pub(crate) use run_async;
+/// Formats `time` as local-time RFC 3339, e.g. `2024-05-01T14:03:12+02:00`.
+pub fn rfc3339(time: SystemTime) -> String {
+    let Some(tm) = local_time(time) else {
+        return String::new();
+    };
+
+    let offset = tm.tm_gmtoff / 60;
+    let sign = if offset < 0 { '-' } else { '+' };
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}{sign}{:02}:{:02}",
+        tm.tm_year + 1900,
+        tm.tm_mon + 1,
+        tm.tm_mday,
+        tm.tm_hour,
+        tm.tm_min,
+        tm.tm_sec,
+        offset.abs() / 60,
+        offset.abs() % 60,
+    )
+}
+
+/// Expands `strftime(3)` conversions in `format` for the local `time`.
+pub fn strftime(format: &str, time: SystemTime) -> String {
+    let (Some(tm), Ok(format)) = (local_time(time), CString::new(format))
+    else {
+        return String::new();
+    };
+
+    let mut buffer = vec![0u8; 4096];
+    let written = unsafe {
+        libc::strftime(
+            buffer.as_mut_ptr().cast(),
+            buffer.len(),
+            format.as_ptr(),
+            &tm,
+        )
+    };
+
+    buffer.truncate(written);
+    String::from_utf8_lossy(&buffer).into_owned()
+}
+
+pub fn hostname() -> Option<String> {
+    let mut buffer = [0 as libc::c_char; 256];
+
+    if unsafe { libc::gethostname(buffer.as_mut_ptr(), buffer.len()) } != 0 {
+        return None;
+    }
+    buffer[buffer.len() - 1] = 0;
+
+    let name = unsafe { CStr::from_ptr(buffer.as_ptr()) };
+    Some(name.to_string_lossy().into_owned())
+}